@@ -224,6 +224,14 @@ impl DrawableComponent for AluCore {
                 match alu_op {
                     AluOperation::NoOp => { "NOP" }
                     AluOperation::Eq { .. } => { "==" }
+                    AluOperation::Lt { .. } => { "<" }
+                    AluOperation::Le { .. } => { "<=" }
+                    AluOperation::Gt { .. } => { ">" }
+                    AluOperation::Ge { .. } => { ">=" }
+                    AluOperation::LtU { .. } => { "<U" }
+                    AluOperation::LeU { .. } => { "<=U" }
+                    AluOperation::GtU { .. } => { ">U" }
+                    AluOperation::GeU { .. } => { ">=U" }
                     // AluOperation::Mov { .. } => {"MOV"}
                     AluOperation::Latch { .. } => { "LAT" }
                     AluOperation::Not { .. } => { "!" }
@@ -241,6 +249,32 @@ impl DrawableComponent for AluCore {
                     AluOperation::Neg { .. } => { "NEG" }
                     AluOperation::ReadFromMem { .. } => { "READ" }
                     AluOperation::WriteToMem { .. } => { "WRIT" }
+                    AluOperation::AtomicAdd { .. } => { "A+" }
+                    AluOperation::AtomicSwap { .. } => { "ASWP" }
+                    AluOperation::Mux { .. } => { "MUX" }
+                    AluOperation::Accumulate { .. } => { "ACC" }
+                    AluOperation::Counter { .. } => { "CNT" }
+                    AluOperation::ShiftRightLogical { .. } => { ">>>" }
+                    AluOperation::RotateLeft { .. } => { "ROL" }
+                    AluOperation::RotateRight { .. } => { "ROR" }
+                    AluOperation::PopCount { .. } => { "POP" }
+                    AluOperation::LeadingZeros { .. } => { "CLZ" }
+                    AluOperation::TrailingZeros { .. } => { "CTZ" }
+                    AluOperation::BitReverse { .. } => { "REV" }
+                    AluOperation::AddSaturating { .. } => { "+SAT" }
+                    AluOperation::SubSaturating { .. } => { "-SAT" }
+                    AluOperation::AddWithCarry { .. } => { "+C" }
+                    AluOperation::SubWithBorrow { .. } => { "-B" }
+                    AluOperation::FAdd { .. } => { "F+" }
+                    AluOperation::FSub { .. } => { "F-" }
+                    AluOperation::FMul { .. } => { "F*" }
+                    AluOperation::FDiv { .. } => { "F/" }
+                    AluOperation::FCmp { .. } => { "FCMP" }
+                    AluOperation::IntToFloat { .. } => { "I2F" }
+                    AluOperation::FloatToInt { .. } => { "F2I" }
+                    AluOperation::Custom { .. } => {
+                        self.custom_op().map_or("CUST", |custom_op| custom_op.name())
+                    }
                 }
             };
 
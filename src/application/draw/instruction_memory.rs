@@ -86,7 +86,7 @@ impl DrawableComponent for InstructionMemory {
         {
             let mut current_cell_ix = drawing_state.0;
             loop {
-                let Some(current_instruction) = self.0.get(current_cell_ix).cloned() else {
+                let Some(current_instruction) = self.read(current_cell_ix) else {
                     break;
                 };
                 let instruction_value_text = format!("{:#?}", current_instruction);
@@ -1,5 +1,6 @@
-use std::collections::HashMap;
-use super::AluOperation;
+use super::float::{float_compare, float_result, float_to_int, int_to_float, word_to_f32};
+use super::{CustomAluOperation, CustomOpInputs, CustomOps};
+use super::{add_with_carry, flags_word, sub_with_borrow, AluOperation, AluPortTable, FLAG_CARRY, FLAG_OVERFLOW};
 use crate::application::draw::port::SignalType::Activation;
 use crate::application::draw::port::{PortDefns, PortSignalDirection, SignalType};
 use crate::application::grid::component::{PortDataContainer, PortName};
@@ -7,18 +8,23 @@ use crate::application::simulation::alu::AluPortName::{
     ActivationIn, ActivationOut, DataIn0, DataIn1, DataOut0, DataOut1,
 };
 use crate::application::simulation::main_memory::{MainMemory, MainMemoryIo};
-use crate::application::simulation::memory_primitives::register::Register;
-use crate::word::{ToBool, ToWord, Word};
-use std::mem::transmute;
-use std::ops::Index;
+use crate::application::simulation::memory_timing::MemoryAccessKind;
+use crate::application::simulation::error::{MemoryFault, SimulationError};
+use crate::application::simulation::performance::AluCounters;
+use crate::word::{ToBool, ToWord, UWord, Word, WordWidth};
+use crate::Step;
+use std::sync::Arc;
 use PortSignalDirection::{Input, Output};
 use SignalType::Data;
-use crate::application::simulation::cpu_registers::{CpuRegisterActReader, CpuRegisterActWriter, CpuRegisterDataReader, CpuRegisterDataWriter, CpuRegisterReadRequest, CpuRegisterWriteRequest};
+use crate::application::simulation::cpu_registers::{CpuRegisterActReader, CpuRegisterActWriter, CpuRegisterAddress, CpuRegisterBank, CpuRegisterDataReader, CpuRegisterDataWriter};
 
 #[derive(Clone, Copy, Eq, PartialEq, Debug, Hash)]
 pub enum AluCoreState {
     Normal,
-    Waiting,
+    /// A memory access is in flight, its result held in `inner_memory_0`
+    Waiting {
+        cycles_left : Step,
+    },
 }
 
 #[derive(Clone, Copy, PartialEq, Eq, Debug, Hash)]
@@ -84,11 +90,24 @@ pub struct AluCore {
     pub addr: usize,
     pub operation       : AluOperation,
     pub old_operation   : AluOperation,
+    pub port_table      : AluPortTable,
+    /// Set when the operation was (re)configured and hasn't been evaluated since
+    pub pending_evaluation: bool,
     pub main_memory     : MainMemoryIo,
+    pub state           : AluCoreState,
 
     pub inner_memory_0  : Word,
     pub inner_memory_1  : Word,
 
+    /// Results are wrapped to it as they're written to the registers
+    pub word_width      : WordWidth,
+
+    pub custom_ops      : CustomOps,
+    /// The operation of `custom_ops` a `Custom` operation selects, if registered
+    custom_op           : Option<Arc<dyn CustomAluOperation>>,
+
+    pub counters        : AluCounters,
+
     pub data_input_0    : CpuRegisterDataReader,
     pub data_input_1    : CpuRegisterDataReader,
     pub activation_input: CpuRegisterActReader,
@@ -99,40 +118,54 @@ pub struct AluCore {
 }
 
 impl AluCore {
-    pub fn collect_read_requests<'a>(&'a mut self) -> HashMap<AluPortName, CpuRegisterReadRequest<'a>> {
-        [
-            (DataIn0, self.data_input_0.get_read_request()),
-            (DataIn1, self.data_input_1.get_read_request()),
-            (ActivationIn, self.activation_input.get_read_request()),
-        ]
-        .into_iter()
-        .filter_map(|(name, opt_req)|
-            opt_req.map(|req| (name, req))
-        )
-        .collect()
+    pub fn read_inputs(&mut self, register_bank: &CpuRegisterBank) {
+        let [data_0, data_1, activation] = self.port_table.inputs;
+        if let Some(source) = data_0 {
+            self.data_input_0.latch(register_bank.components[source].value);
+        }
+        if let Some(source) = data_1 {
+            self.data_input_1.latch(register_bank.components[source].value);
+        }
+        if let Some(source) = activation {
+            self.activation_input.latch(register_bank.components[source].value);
+        }
     }
-    pub fn collect_write_requests(&mut self) -> HashMap<AluPortName, CpuRegisterWriteRequest> {
-        [
-            (DataOut0, self.data_output_0.get_write_request()),
-            (DataOut1, self.data_output_1.get_write_request()),
-            (ActivationOut, self.activation_output.get_write_request()),
-        ]
-        .into_iter()
-        .filter_map(|(name, opt_req)|
-            opt_req.map(|req| (name, req))
-        )
-        .collect()
+    pub fn write_outputs(&self, register_bank: &mut CpuRegisterBank) {
+        for (target, value) in self.driven_outputs() {
+            register_bank.components[target].write(value);
+        }
+    }
+    /// The registers the outputs write, with the values they write to them
+    pub fn driven_outputs(&self) -> impl Iterator<Item = (CpuRegisterAddress, Word)> + '_ {
+        let values = [
+            self.data_output_0.value(),
+            self.data_output_1.value(),
+            self.activation_output.value(),
+        ];
+        self.port_table.outputs.into_iter()
+            .zip(values)
+            .filter_map(|(target, value)| Some((target?, self.word_width.wrap(value?))))
     }
-    pub fn new(alu_addr: usize, main_memory: &MainMemory) -> Self {
+    pub fn new(alu_addr: usize, main_memory: &MainMemory, custom_ops: &CustomOps) -> Self {
         AluCore {
             addr                : alu_addr,
             main_memory         : main_memory.get_io(),
+            state               : AluCoreState::Normal,
             operation           : AluOperation::NoOp,
             old_operation       : AluOperation::NoOp,
+            port_table          : AluPortTable::default(),
+            pending_evaluation  : false,
 
             inner_memory_0      : Default::default(),
             inner_memory_1      : Default::default(),
 
+            word_width          : WordWidth::default(),
+
+            custom_ops          : custom_ops.clone(),
+            custom_op           : None,
+
+            counters            : AluCounters::default(),
+
             data_input_0        : CpuRegisterDataReader::new(),
             data_input_1        : CpuRegisterDataReader::new(),
             activation_input    : CpuRegisterActReader::new(),
@@ -180,10 +213,17 @@ impl AluCore {
 
 
     pub fn set_new_operation(&mut self, new_operation: AluOperation){
-        self.old_operation = self.operation.clone();
-        self.operation = new_operation.clone();
+        self.old_operation = self.operation;
+        self.operation = new_operation;
 
-        let ports_config = new_operation.get_ports_config();
+        self.custom_op = new_operation.custom_id()
+            .and_then(|id| self.custom_ops.get(id).cloned());
+        let mut ports_config = new_operation.get_ports_config();
+        if let Some(custom_op) = &self.custom_op {
+            ports_config = custom_op.ports().apply(&ports_config);
+        }
+        self.port_table = AluPortTable::from(&ports_config);
+        self.pending_evaluation = true;
         self.data_input_0.set_connection(ports_config.data_input_0);
         self.data_input_1.set_connection(ports_config.data_input_1);
         self.activation_input
@@ -196,9 +236,117 @@ impl AluCore {
             .set_connection(ports_config.activation_output);
         self.inner_memory_0 = 0;
         self.inner_memory_1 = 0;
+        self.state = AluCoreState::Normal;
     }
 
-    pub fn execute(&mut self) {
+    /// Accounts for a cycle without evaluating the ALU, because none of its inputs changed or it
+    /// was evaluated through `settle`
+    pub fn skip(&mut self) {
+        self.update_counters();
+    }
+
+    fn update_counters(&mut self) {
+        if self.operation == AluOperation::NoOp {
+            return;
+        }
+        self.counters.cycles_configured += 1;
+        if self.state == AluCoreState::Normal && self.activation_input.read() == Some(true) {
+            self.counters.cycles_activated += 1;
+            self.counters.ops_executed[self.operation.kind() as usize] += 1;
+        }
+    }
+
+    /// Starts a memory access through `access`, which returns the word for `data_output_0`. The
+    /// access itself happens right away, but its result and activation only show up once the
+    /// memory latency elapsed.
+    fn start_memory_access(
+        &mut self,
+        addr    : usize,
+        kind    : MemoryAccessKind,
+        cycle   : Step,
+        access  : impl FnOnce(&mut MainMemoryIo) -> Result<Word, MemoryFault>,
+    ) -> Result<(), MemoryFault> {
+        let Some(latency) = self.main_memory.start_access(addr, kind, cycle)? else {
+            // retried next cycle, as long as the activation input holds
+            self.counters.memory_stall_cycles += 1;
+            self.activation_output.write(false);
+            return Ok(());
+        };
+        let result = access(&mut self.main_memory)?;
+        if latency == 0 {
+            self.data_output_0.write(result);
+            self.activation_output.write(true);
+        } else {
+            self.inner_memory_0 = result;
+            self.state = AluCoreState::Waiting { cycles_left: latency };
+            self.activation_output.write(false);
+        }
+        Ok(())
+    }
+
+    fn wait_memory_access(&mut self, cycles_left: Step) {
+        if cycles_left > 1 {
+            self.counters.memory_stall_cycles += 1;
+            self.state = AluCoreState::Waiting { cycles_left: cycles_left - 1 };
+            self.activation_output.write(false);
+        } else {
+            self.state = AluCoreState::Normal;
+            self.data_output_0.write(self.inner_memory_0);
+            self.activation_output.write(true);
+        }
+    }
+
+    pub fn custom_op(&self) -> Option<&Arc<dyn CustomAluOperation>> {
+        self.custom_op.as_ref()
+    }
+
+    /// Whether the configured operation is combinational, see `AluOpKind::is_combinational`
+    pub fn is_combinational(&self) -> bool {
+        match &self.custom_op {
+            Some(custom_op) => custom_op.is_combinational(),
+            None            => self.operation.kind().is_combinational(),
+        }
+    }
+
+    fn check_custom_op(&self, cycle: Step) -> Result<(), SimulationError> {
+        match self.operation {
+            AluOperation::Custom { id, .. } if self.custom_op.is_none() =>
+                Err(SimulationError::UnknownCustomOperation { alu: self.addr, cycle, id }),
+            _ => Ok(()),
+        }
+    }
+
+    /// Rotates the low `word_width` bits of `value` by `shift_count` modulo the width
+    fn rotate_left(&self, value: Word, shift_count: UWord) -> Word {
+        let bits = self.word_width.bits();
+        let shift_count = (shift_count % bits as UWord) as u32;
+        let value = self.word_width.unsigned(value);
+        (value << shift_count | value.checked_shr(bits - shift_count).unwrap_or(0)) as Word
+    }
+
+    pub fn execute(&mut self, cycle: Step) -> Result<(), SimulationError> {
+        self.check_custom_op(cycle)?;
+        self.update_counters();
+        self.evaluate(cycle)
+            .map_err(|fault| SimulationError::MemoryFault { alu: self.addr, cycle, fault })
+    }
+
+    /// Evaluates the operation without accounting for a cycle, as often as combinational logic
+    /// needs to settle within one
+    pub fn settle(&mut self, cycle: Step) -> Result<(), SimulationError> {
+        self.check_custom_op(cycle)?;
+        self.evaluate(cycle)
+            .map_err(|fault| SimulationError::MemoryFault { alu: self.addr, cycle, fault })
+    }
+
+    fn evaluate(&mut self, cycle: Step) -> Result<(), MemoryFault> {
+        self.pending_evaluation = false;
+
+        if let AluCoreState::Waiting { cycles_left } = self.state {
+            self.wait_memory_access(cycles_left);
+            return Ok(());
+        }
+
         let op = self.operation;
         match &op {
             AluOperation::NoOp => {}
@@ -213,6 +361,33 @@ impl AluCore {
                     self.activation_output.write(false)
                 }
             }
+            AluOperation::Lt { .. }
+            | AluOperation::Le { .. }
+            | AluOperation::Gt { .. }
+            | AluOperation::Ge { .. }
+            | AluOperation::LtU { .. }
+            | AluOperation::LeU { .. }
+            | AluOperation::GtU { .. }
+            | AluOperation::GeU { .. } => {
+                if self.activation_input.read().unwrap() {
+                    let in_0 = self.data_input_0.read().unwrap();
+                    let in_1 = self.data_input_1.read().unwrap();
+                    let res = match op {
+                        AluOperation::Lt { .. }     => in_0 < in_1,
+                        AluOperation::Le { .. }     => in_0 <= in_1,
+                        AluOperation::Gt { .. }     => in_0 > in_1,
+                        AluOperation::Ge { .. }     => in_0 >= in_1,
+                        AluOperation::LtU { .. }    => (in_0 as UWord) < in_1 as UWord,
+                        AluOperation::LeU { .. }    => in_0 as UWord <= in_1 as UWord,
+                        AluOperation::GtU { .. }    => in_0 as UWord > in_1 as UWord,
+                        _                           => in_0 as UWord >= in_1 as UWord,
+                    };
+                    self.data_output_0.write(res.to_word());
+                    self.activation_output.write(true)
+                } else {
+                    self.activation_output.write(false)
+                }
+            }
             // AluOperation::Mov {
             //     activation_input,
             //     ..
@@ -303,7 +478,8 @@ impl AluCore {
                     let inp_0 = self.data_input_0.read().unwrap();
                     let inp_1 = self.data_input_1.read().unwrap();
 
-                    let res = inp_0 << inp_1;
+                    let shift_count = self.word_width.unsigned(inp_1);
+                    let res = if shift_count < self.word_width.bits() as UWord { inp_0 << shift_count } else { 0 };
                     self.data_output_0.write(res);
 
                     self.activation_output.write(true);
@@ -318,7 +494,8 @@ impl AluCore {
                     let inp_0 = self.data_input_0.read().unwrap();
                     let inp_1 = self.data_input_1.read().unwrap();
 
-                    let res = inp_0 >> inp_1;
+                    let shift_count = self.word_width.unsigned(inp_1);
+                    let res = inp_0 >> shift_count.min(self.word_width.bits() as UWord - 1);
                     self.data_output_0.write(res);
 
                     self.activation_output.write(true);
@@ -335,9 +512,9 @@ impl AluCore {
                     let inp_0 = self.data_input_0.read().unwrap();
                     let inp_1 = self.data_input_1.read().unwrap();
 
-                    let (first_word, overflow) = inp_0.overflowing_add(inp_1);
+                    let (first_word, flags) = add_with_carry(inp_0, inp_1, false, self.word_width);
                     self.data_output_0.write(first_word);
-                    self.data_output_1.write(overflow as i32 );
+                    self.data_output_1.write((flags & FLAG_OVERFLOW != 0) as Word);
                     self.activation_output.write(true);
                 } else {
                     self.activation_output.write(false);
@@ -350,9 +527,9 @@ impl AluCore {
                     let inp_0 = self.data_input_0.read().unwrap();
                     let inp_1 = self.data_input_1.read().unwrap();
 
-                    let (first_word, overflow) = inp_0.overflowing_sub(inp_1);
-                    self.data_output_0.write(first_word,);
-                    self.data_output_1.write(overflow as i32);
+                    let (first_word, flags) = sub_with_borrow(inp_0, inp_1, false, self.word_width);
+                    self.data_output_0.write(first_word);
+                    self.data_output_1.write((flags & FLAG_OVERFLOW != 0) as Word);
 
                     self.activation_output.write(true);
                 } else {
@@ -368,15 +545,14 @@ impl AluCore {
                     let inp_1 = self.data_input_1.read().unwrap();
 
                     if let Some(_second_word_output) = second_word_output {
-                        let (first_word_res, second_word_res) =
-                            (inp_0 as i32).widening_mul(inp_1 as i32);
+                        let product = inp_0 as i128 * inp_1 as i128;
                         self.data_output_0
-                            .write(unsafe { transmute(first_word_res) },);
+                            .write(product as Word);
                         self.data_output_1
-                            .write(second_word_res);
+                            .write((product >> self.word_width.bits()) as Word);
                     } else {
                         self.data_output_0
-                            .write((inp_0) * (inp_1), );
+                            .write(inp_0.wrapping_mul(inp_1));
                     }
 
                     self.activation_output.write(true);
@@ -398,8 +574,8 @@ impl AluCore {
                         }
                         self.data_output_0.write(0);
                     } else {
-                        let res = dividend / divisor;
-                        self.data_output_1.write(res);
+                        let res = dividend.wrapping_div(divisor);
+                        self.data_output_0.write(res);
                         if let Some(_div_by_zero_flag_output) = div_by_zero_flag_output {
                             self.data_output_1.write(0);
                         }
@@ -424,8 +600,8 @@ impl AluCore {
                         }
                         self.data_output_0.write(0);
                     } else {
-                        let res = dividend % divisor;
-                        self.data_output_1.write(res);
+                        let res = dividend.wrapping_rem(divisor);
+                        self.data_output_0.write(res);
                         if let Some(_div_by_zero_flag_output) = div_by_zero_flag_output {
                             self.data_output_1.write(0);
                         }
@@ -440,7 +616,7 @@ impl AluCore {
                 ..
             } => {
                 if self.activation_input.read().unwrap() {
-                    let res = -self.data_input_0.read().unwrap();
+                    let res = self.data_input_0.read().unwrap().wrapping_neg();
                     self.data_output_0.write(res);
 
                     self.activation_output.write(true);
@@ -452,11 +628,10 @@ impl AluCore {
                 ..
             } => {
                 if self.activation_input.read() .unwrap(){
-                    let addr = self.data_input_0.read().unwrap();
-                    let res = self.main_memory.read(addr as usize);
-                    self.data_output_0.write(res);
-
-                    self.activation_output.write(true);
+                    let addr = self.data_input_0.read().unwrap() as usize;
+                    self.start_memory_access(addr, MemoryAccessKind::Read, cycle, |memory|
+                        memory.read(addr, cycle)
+                    )?;
                 } else {
                     self.activation_output.write(false);
                 }
@@ -465,10 +640,34 @@ impl AluCore {
                 ..
             } => {
                 if self.activation_input.read().unwrap() {
-                    let addr = self.data_input_0.read().unwrap();
+                    let addr = self.data_input_0.read().unwrap() as usize;
                     let data = self.data_input_1.read().unwrap();
-                    self.main_memory.write(addr as usize, data);
-                    self.activation_output.write(true);
+                    self.start_memory_access(addr, MemoryAccessKind::Write, cycle, |memory| {
+                        memory.write(addr, data, cycle)?;
+                        Ok(data)
+                    })?;
+                } else {
+                    self.activation_output.write(false);
+                }
+            }
+            AluOperation::AtomicAdd { .. } => {
+                if self.activation_input.read().unwrap() {
+                    let addr = self.data_input_0.read().unwrap() as usize;
+                    let data = self.data_input_1.read().unwrap();
+                    self.start_memory_access(addr, MemoryAccessKind::ReadWrite, cycle, |memory|
+                        memory.fetch_add(addr, data, cycle)
+                    )?;
+                } else {
+                    self.activation_output.write(false);
+                }
+            }
+            AluOperation::AtomicSwap { .. } => {
+                if self.activation_input.read().unwrap() {
+                    let addr = self.data_input_0.read().unwrap() as usize;
+                    let data = self.data_input_1.read().unwrap();
+                    self.start_memory_access(addr, MemoryAccessKind::ReadWrite, cycle, |memory|
+                        memory.swap(addr, data, cycle)
+                    )?;
                 } else {
                     self.activation_output.write(false);
                 }
@@ -491,7 +690,516 @@ impl AluCore {
                 }
                 todo!()
             }
+            AluOperation::Mux { .. } => {
+                let res = if self.activation_input.read().unwrap() {
+                    self.data_input_1.read().unwrap()
+                } else {
+                    self.data_input_0.read().unwrap()
+                };
+                self.data_output_0.write(res);
+                self.activation_output.write(true)
+            }
+            AluOperation::Accumulate { .. } => {
+                let activated = self.activation_input.read().unwrap();
+                if self.data_input_1.read().unwrap().to_bool() {
+                    self.inner_memory_0 = 0;
+                } else if activated {
+                    let sum = self.inner_memory_0.wrapping_add(self.data_input_0.read().unwrap());
+                    self.inner_memory_0 = self.word_width.wrap(sum);
+                }
+                self.data_output_0.write(self.inner_memory_0);
+                self.activation_output.write(activated)
+            }
+            AluOperation::Counter { .. } => {
+                let mut wrapped = false;
+                if self.activation_input.read().unwrap() {
+                    let limit = self.data_input_0.read().unwrap();
+                    self.inner_memory_0 = self.word_width.wrap(self.inner_memory_0.wrapping_add(1));
+                    if limit > 0 && self.inner_memory_0 >= limit {
+                        self.inner_memory_0 = 0;
+                        wrapped = true;
+                    }
+                }
+                self.data_output_0.write(self.inner_memory_0);
+                self.activation_output.write(wrapped)
+            }
+            AluOperation::ShiftRightLogical { .. } => {
+                if self.activation_input.read().unwrap() {
+                    let value = self.data_input_0.read().unwrap();
+                    let shift_count = self.word_width.unsigned(self.data_input_1.read().unwrap());
+                    let res = if shift_count < self.word_width.bits() as UWord {
+                        self.word_width.unsigned(value) >> shift_count
+                    } else {
+                        0
+                    };
+                    self.data_output_0.write(res as Word);
+                    self.activation_output.write(true);
+                } else {
+                    self.activation_output.write(false);
+                }
+            }
+            AluOperation::RotateLeft { .. } => {
+                if self.activation_input.read().unwrap() {
+                    let value = self.data_input_0.read().unwrap();
+                    let shift_count = self.data_input_1.read().unwrap() as UWord;
+                    self.data_output_0.write(self.rotate_left(value, shift_count));
+                    self.activation_output.write(true);
+                } else {
+                    self.activation_output.write(false);
+                }
+            }
+            AluOperation::RotateRight { .. } => {
+                if self.activation_input.read().unwrap() {
+                    let value = self.data_input_0.read().unwrap();
+                    let bits = self.word_width.bits() as UWord;
+                    let shift_count = self.data_input_1.read().unwrap() as UWord;
+                    self.data_output_0.write(self.rotate_left(value, bits - shift_count % bits));
+                    self.activation_output.write(true);
+                } else {
+                    self.activation_output.write(false);
+                }
+            }
+            AluOperation::PopCount { .. } => {
+                if self.activation_input.read().unwrap() {
+                    let value = self.data_input_0.read().unwrap();
+                    self.data_output_0.write(self.word_width.unsigned(value).count_ones() as Word);
+                    self.activation_output.write(true);
+                } else {
+                    self.activation_output.write(false);
+                }
+            }
+            AluOperation::LeadingZeros { .. } => {
+                if self.activation_input.read().unwrap() {
+                    let value = self.data_input_0.read().unwrap();
+                    let unused = Word::BITS - self.word_width.bits();
+                    self.data_output_0.write((self.word_width.unsigned(value).leading_zeros() - unused) as Word);
+                    self.activation_output.write(true);
+                } else {
+                    self.activation_output.write(false);
+                }
+            }
+            AluOperation::TrailingZeros { .. } => {
+                if self.activation_input.read().unwrap() {
+                    let value = self.data_input_0.read().unwrap();
+                    let zeros = self.word_width.unsigned(value).trailing_zeros().min(self.word_width.bits());
+                    self.data_output_0.write(zeros as Word);
+                    self.activation_output.write(true);
+                } else {
+                    self.activation_output.write(false);
+                }
+            }
+            AluOperation::BitReverse { .. } => {
+                if self.activation_input.read().unwrap() {
+                    let value = self.data_input_0.read().unwrap();
+                    let unused = Word::BITS - self.word_width.bits();
+                    self.data_output_0.write((self.word_width.unsigned(value).reverse_bits() >> unused) as Word);
+                    self.activation_output.write(true);
+                } else {
+                    self.activation_output.write(false);
+                }
+            }
+            AluOperation::AddSaturating { .. } => {
+                if self.activation_input.read().unwrap() {
+                    let inp_0 = self.data_input_0.read().unwrap();
+                    let inp_1 = self.data_input_1.read().unwrap();
+
+                    let (_, flags) = add_with_carry(inp_0, inp_1, false, self.word_width);
+                    let res = inp_0.saturating_add(inp_1).clamp(self.word_width.min(), self.word_width.max());
+                    self.data_output_0.write(res);
+                    self.data_output_1.write(flags_word(res, flags & FLAG_CARRY != 0, flags & FLAG_OVERFLOW != 0));
+                    self.activation_output.write(true);
+                } else {
+                    self.activation_output.write(false);
+                }
+            }
+            AluOperation::SubSaturating { .. } => {
+                if self.activation_input.read().unwrap() {
+                    let inp_0 = self.data_input_0.read().unwrap();
+                    let inp_1 = self.data_input_1.read().unwrap();
+
+                    let (_, flags) = sub_with_borrow(inp_0, inp_1, false, self.word_width);
+                    let res = inp_0.saturating_sub(inp_1).clamp(self.word_width.min(), self.word_width.max());
+                    self.data_output_0.write(res);
+                    self.data_output_1.write(flags_word(res, flags & FLAG_CARRY != 0, flags & FLAG_OVERFLOW != 0));
+                    self.activation_output.write(true);
+                } else {
+                    self.activation_output.write(false);
+                }
+            }
+            AluOperation::AddWithCarry { .. } => {
+                let carry_in = self.activation_input.read().unwrap();
+                let inp_0 = self.data_input_0.read().unwrap();
+                let inp_1 = self.data_input_1.read().unwrap();
+
+                let (res, flags) = add_with_carry(inp_0, inp_1, carry_in, self.word_width);
+                self.data_output_0.write(res);
+                self.data_output_1.write(flags);
+                self.activation_output.write(flags & FLAG_CARRY != 0);
+            }
+            AluOperation::SubWithBorrow { .. } => {
+                let carry_in = self.activation_input.read().unwrap();
+                let inp_0 = self.data_input_0.read().unwrap();
+                let inp_1 = self.data_input_1.read().unwrap();
+
+                let (res, flags) = sub_with_borrow(inp_0, inp_1, carry_in, self.word_width);
+                self.data_output_0.write(res);
+                self.data_output_1.write(flags);
+                self.activation_output.write(flags & FLAG_CARRY != 0);
+            }
+            AluOperation::FAdd { .. } => {
+                if self.activation_input.read().unwrap() {
+                    let inp_0 = word_to_f32(self.data_input_0.read().unwrap());
+                    let inp_1 = word_to_f32(self.data_input_1.read().unwrap());
+
+                    let (res, flags) = float_result(inp_0, inp_1, inp_0 + inp_1);
+                    self.data_output_0.write(res);
+                    self.data_output_1.write(flags);
+                    self.activation_output.write(true);
+                } else {
+                    self.activation_output.write(false);
+                }
+            }
+            AluOperation::FSub { .. } => {
+                if self.activation_input.read().unwrap() {
+                    let inp_0 = word_to_f32(self.data_input_0.read().unwrap());
+                    let inp_1 = word_to_f32(self.data_input_1.read().unwrap());
+
+                    let (res, flags) = float_result(inp_0, inp_1, inp_0 - inp_1);
+                    self.data_output_0.write(res);
+                    self.data_output_1.write(flags);
+                    self.activation_output.write(true);
+                } else {
+                    self.activation_output.write(false);
+                }
+            }
+            AluOperation::FMul { .. } => {
+                if self.activation_input.read().unwrap() {
+                    let inp_0 = word_to_f32(self.data_input_0.read().unwrap());
+                    let inp_1 = word_to_f32(self.data_input_1.read().unwrap());
+
+                    let (res, flags) = float_result(inp_0, inp_1, inp_0 * inp_1);
+                    self.data_output_0.write(res);
+                    self.data_output_1.write(flags);
+                    self.activation_output.write(true);
+                } else {
+                    self.activation_output.write(false);
+                }
+            }
+            AluOperation::FDiv { .. } => {
+                if self.activation_input.read().unwrap() {
+                    let inp_0 = word_to_f32(self.data_input_0.read().unwrap());
+                    let inp_1 = word_to_f32(self.data_input_1.read().unwrap());
+
+                    let (res, flags) = float_result(inp_0, inp_1, inp_0 / inp_1);
+                    self.data_output_0.write(res);
+                    self.data_output_1.write(flags);
+                    self.activation_output.write(true);
+                } else {
+                    self.activation_output.write(false);
+                }
+            }
+            AluOperation::FCmp { .. } => {
+                if self.activation_input.read().unwrap() {
+                    let inp_0 = word_to_f32(self.data_input_0.read().unwrap());
+                    let inp_1 = word_to_f32(self.data_input_1.read().unwrap());
+
+                    let (res, flags) = float_compare(inp_0, inp_1);
+                    self.data_output_0.write(res);
+                    self.data_output_1.write(flags);
+                    self.activation_output.write(true);
+                } else {
+                    self.activation_output.write(false);
+                }
+            }
+            AluOperation::IntToFloat { .. } => {
+                if self.activation_input.read().unwrap() {
+                    let (res, flags) = int_to_float(self.data_input_0.read().unwrap());
+                    self.data_output_0.write(res);
+                    self.data_output_1.write(flags);
+                    self.activation_output.write(true);
+                } else {
+                    self.activation_output.write(false);
+                }
+            }
+            AluOperation::FloatToInt { .. } => {
+                if self.activation_input.read().unwrap() {
+                    let (res, flags) = float_to_int(word_to_f32(self.data_input_0.read().unwrap()), self.word_width);
+                    self.data_output_0.write(res);
+                    self.data_output_1.write(flags);
+                    self.activation_output.write(true);
+                } else {
+                    self.activation_output.write(false);
+                }
+            }
+            AluOperation::Custom { .. } => {
+                // `check_custom_op` made sure it's registered
+                if let Some(custom_op) = self.custom_op.clone() {
+                    let inputs = CustomOpInputs {
+                        activated       : self.activation_input.read().unwrap(),
+                        data_input_0    : self.data_input_0.read(),
+                        data_input_1    : self.data_input_1.read(),
+                    };
+                    let mut state = [self.inner_memory_0, self.inner_memory_1];
+                    let outputs = custom_op.execute(inputs, &mut state);
+                    [self.inner_memory_0, self.inner_memory_1] = state;
+                    if let Some(value) = outputs.data_output_0 {
+                        self.data_output_0.write(value);
+                    }
+                    if let Some(value) = outputs.data_output_1 {
+                        self.data_output_1.write(value);
+                    }
+                    self.activation_output.write(outputs.activation_output);
+                }
+            }
         }
+        Ok(())
     }
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::application::simulation::cpu_registers::CpuRegisterAddress;
+    use crate::application::simulation::instruction::Instruction;
+    use crate::application::simulation::simulation::Cpu;
+
+    type Comparison = fn(CpuRegisterAddress) -> AluOperation;
+
+    /// The comparisons, each with its expected result on the two inputs at a width
+    fn comparisons() -> [(Comparison, fn(Word, Word, WordWidth) -> bool); 8] {
+        macro_rules! comparison {
+            ($variant:ident) => {
+                |data_output| AluOperation::$variant {
+                    activation_input: 0, activation_output: None, data_input_0: 1, data_input_1: 2,
+                    data_output,
+                }
+            };
+        }
+        [
+            (comparison!(Lt),  |a, b, _| a < b),
+            (comparison!(Le),  |a, b, _| a <= b),
+            (comparison!(Gt),  |a, b, _| a > b),
+            (comparison!(Ge),  |a, b, _| a >= b),
+            (comparison!(LtU), |a, b, width| width.unsigned(a) < width.unsigned(b)),
+            (comparison!(LeU), |a, b, width| width.unsigned(a) <= width.unsigned(b)),
+            (comparison!(GtU), |a, b, width| width.unsigned(a) > width.unsigned(b)),
+            (comparison!(GeU), |a, b, width| width.unsigned(a) >= width.unsigned(b)),
+        ]
+    }
+
+    #[test]
+    fn signed_and_unsigned_comparisons_at_every_width() {
+        for width in WordWidth::ALL {
+            let values = [width.min(), width.min() + 1, -1, 0, 1, width.max() - 1, width.max()];
+            for (a, b) in values.into_iter().flat_map(|a| values.map(|b| (a, b))) {
+                let mut program = vec![
+                    Instruction::SetLiteral { literal: a, register: 1 },
+                    Instruction::SetLiteral { literal: b, register: 2 },
+                ];
+                program.extend(comparisons().iter().enumerate().map(|(ix, (comparison, _))| {
+                    Instruction::SetAluConfig { alu_addr: ix, alu_config: comparison(10 + ix) }
+                }));
+                program.push(Instruction::SetLiteral { literal: !0, register: 0 });
+                program.push(Instruction::NoOp);
+
+                let mut cpu = Cpu::new(program, vec![]);
+                cpu.set_word_width(width);
+                cpu.run(20).unwrap();
+                for (ix, (comparison, expected)) in comparisons().iter().enumerate() {
+                    assert_eq!(
+                        cpu.register_bank.components[10 + ix].value,
+                        expected(a, b, width).to_word(),
+                        "{:?} {:?} {} {}",
+                        width,
+                        comparison(10 + ix).kind(),
+                        a,
+                        b,
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn add_and_sub_write_overflow_as_0_or_1() {
+        for width in WordWidth::ALL {
+            for (a, b, add_overflow, sub_overflow) in [
+                (1, 1, 0, 0),
+                (width.max(), 1, 1, 0),
+                (width.min(), 1, 0, 1),
+                (0, 0, 0, 0),
+                (-1, -1, 0, 0),
+            ] {
+                let program = vec![
+                    Instruction::SetLiteral { literal: a, register: 1 },
+                    Instruction::SetLiteral { literal: b, register: 2 },
+                    Instruction::SetAluConfig { alu_addr: 0, alu_config: AluOperation::Add {
+                        activation_input: 0, data_input_0: 1, data_input_1: 2, data_output_0: 10,
+                        flags_output: Some(11), activation_output: None,
+                    }},
+                    Instruction::SetAluConfig { alu_addr: 1, alu_config: AluOperation::Sub {
+                        activation_input: 0, data_input_0: 1, data_input_1: 2, data_output_0: 12,
+                        flags_output: Some(13), activation_output: None,
+                    }},
+                    Instruction::SetLiteral { literal: !0, register: 0 },
+                    Instruction::NoOp,
+                ];
+                let mut cpu = Cpu::new(program, vec![]);
+                cpu.set_word_width(width);
+                cpu.run(10).unwrap();
+                let registers = &cpu.register_bank.components;
+                assert_eq!(registers[11].value, add_overflow, "{:?} {} + {}", width, a, b);
+                assert_eq!(registers[13].value, sub_overflow, "{:?} {} - {}", width, a, b);
+            }
+        }
+    }
+
+    #[test]
+    fn mux_outputs_the_input_its_selector_picks_every_cycle() {
+        let mux = |select_input, data_output, activation_output| AluOperation::Mux {
+            select_input, data_input_0: 1, data_input_1: 2, data_output, activation_output,
+        };
+        let program = vec![
+            Instruction::SetLiteral { literal: 10, register: 1 },
+            Instruction::SetLiteral { literal: 20, register: 2 },
+            Instruction::SetLiteral { literal: !0, register: 3 },
+            Instruction::SetAluConfig { alu_addr: 0, alu_config: mux(3, 5, Some(6)) },
+            Instruction::SetAluConfig { alu_addr: 1, alu_config: mux(4, 7, Some(8)) },
+            Instruction::NoOp,
+        ];
+        let mut cpu = Cpu::new(program, vec![]);
+        cpu.run(10).unwrap();
+        let registers = &cpu.register_bank.components;
+        assert_eq!((registers[5].value, registers[6].value), (20, true.to_word()));
+        assert_eq!((registers[7].value, registers[8].value), (10, true.to_word()));
+    }
+
+    #[test]
+    fn division_results_go_to_the_first_output() {
+        let div = |divisor, data_output_0, div_by_zero_flag_output| AluOperation::Div {
+            activation_input: 0, dividend: 1, divisor, data_output_0, div_by_zero_flag_output, activation_output: None,
+        };
+        let rem = |divisor, data_output_0, div_by_zero_flag_output| AluOperation::Rem {
+            activation_input: 0, dividend: 1, divisor, data_output_0, div_by_zero_flag_output, activation_output: None,
+        };
+        let program = vec![
+            Instruction::SetLiteral { literal: -7, register: 1 },
+            Instruction::SetLiteral { literal: 2, register: 2 },
+            Instruction::SetAluConfig { alu_addr: 0, alu_config: div(2, 4, Some(5)) },
+            Instruction::SetAluConfig { alu_addr: 1, alu_config: rem(2, 6, Some(7)) },
+            Instruction::SetAluConfig { alu_addr: 2, alu_config: div(3, 8, Some(9)) },
+            Instruction::SetAluConfig { alu_addr: 3, alu_config: rem(3, 10, Some(11)) },
+            Instruction::SetLiteral { literal: !0, register: 0 },
+            Instruction::NoOp,
+        ];
+        let mut cpu = Cpu::new(program, vec![]);
+        cpu.run(10).unwrap();
+        let registers = &cpu.register_bank.components;
+        assert_eq!((registers[4].value, registers[5].value), (-3, 0));
+        assert_eq!((registers[6].value, registers[7].value), (-1, 0));
+        assert_eq!((registers[8].value, registers[9].value), (0, 1));
+        assert_eq!((registers[10].value, registers[11].value), (0, 1));
+    }
+
+    type BitOp = fn(CpuRegisterAddress, CpuRegisterAddress, CpuRegisterAddress) -> AluOperation;
+
+    /// Each bit operation on its inputs, with its expected result at a width. Unary operations
+    /// ignore their second input.
+    fn bit_operations(width: WordWidth) -> [(BitOp, Word, Word, Word); 18] {
+        macro_rules! shift {
+            ($variant:ident) => {
+                |value, shift_count, data_output_0| AluOperation::$variant {
+                    activation_input: 0, value, shift_count, data_output_0, activation_output: None,
+                }
+            };
+        }
+        macro_rules! unary {
+            ($variant:ident) => {
+                |data_input, _, data_output| AluOperation::$variant {
+                    activation_input: 0, data_input, data_output, activation_output: None,
+                }
+            };
+        }
+        let bits = width.bits() as Word;
+        [
+            (shift!(ShiftLeft),         1,                  bits - 1,   width.min()),
+            (shift!(ShiftLeft),         1,                  bits,       0),
+            (shift!(ShiftRight),        -8,                 2,          -2),
+            (shift!(ShiftRight),        -8,                 bits + 5,   -1),
+            (shift!(ShiftRightLogical), -2,                 1,          width.max()),
+            (shift!(ShiftRightLogical), -1,                 bits,       0),
+            (shift!(RotateLeft),        width.min() + 1,    1,          3),
+            (shift!(RotateLeft),        3,                  -1,         width.min() + 1),
+            (shift!(RotateRight),       3,                  1,          width.min() + 1),
+            (shift!(RotateRight),       3,                  bits,       3),
+            (unary!(PopCount),          -1,                 0,          bits),
+            (unary!(PopCount),          0b1011,             0,          3),
+            (unary!(LeadingZeros),      1,                  0,          bits - 1),
+            (unary!(LeadingZeros),      0,                  0,          bits),
+            (unary!(TrailingZeros),     4,                  0,          2),
+            (unary!(TrailingZeros),     0,                  0,          bits),
+            (unary!(BitReverse),        1,                  0,          width.min()),
+            (unary!(BitReverse),        width.min() + 2,    0,          1 << (bits - 2) | 1),
+        ]
+    }
+
+    #[test]
+    fn bit_operations_at_every_width() {
+        for width in WordWidth::ALL {
+            let operations = bit_operations(width);
+            let mut program = Vec::new();
+            for (ix, (operation, input_0, input_1, _)) in operations.iter().enumerate() {
+                program.push(Instruction::SetLiteral { literal: *input_0, register: 1 + 2 * ix });
+                program.push(Instruction::SetLiteral { literal: *input_1, register: 2 + 2 * ix });
+                program.push(Instruction::SetAluConfig {
+                    alu_addr    : ix,
+                    alu_config  : operation(1 + 2 * ix, 2 + 2 * ix, 40 + ix),
+                });
+            }
+            program.push(Instruction::SetLiteral { literal: !0, register: 0 });
+            program.push(Instruction::NoOp);
+
+            let mut cpu = Cpu::new(program, vec![]);
+            cpu.set_word_width(width);
+            cpu.run(100).unwrap();
+            for (ix, (operation, input_0, input_1, expected)) in operations.iter().enumerate() {
+                assert_eq!(
+                    cpu.register_bank.components[40 + ix].value,
+                    *expected,
+                    "{:?} {:?} {} {}",
+                    width,
+                    operation(0, 0, 0).kind(),
+                    input_0,
+                    input_1,
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn accumulators_and_counters_wrap_to_the_word_width() {
+        let program = vec![
+            Instruction::SetLiteral { literal: 100, register: 1 },
+            Instruction::SetAluConfig { alu_addr: 0, alu_config: AluOperation::Accumulate {
+                activation_input: 0, data_input: 1, reset_input: 2, data_output: 3, activation_output: None,
+            }},
+            // a limit of 0 never wraps, so the count runs past the width
+            Instruction::SetAluConfig { alu_addr: 1, alu_config: AluOperation::Counter {
+                activation_input: 0, limit_input: 5, data_output: 4, activation_output: None,
+            }},
+            Instruction::SetLiteral { literal: !0, register: 0 },
+            Instruction::WaitForActivationSignal { register_index: 6 },
+        ];
+        let mut cpu = Cpu::new(program, vec![]);
+        cpu.set_word_width(WordWidth::W8);
+        cpu.run(300).unwrap();
+
+        let activations = cpu.alu_bank.components[0].counters.cycles_activated as Word;
+        assert!(activations > 128);
+        let alus = &cpu.alu_bank.components;
+        let registers = &cpu.register_bank.components;
+        assert_eq!(alus[0].inner_memory_0, WordWidth::W8.wrap(100 * activations));
+        assert_eq!(alus[1].inner_memory_0, WordWidth::W8.wrap(activations));
+        assert_eq!((registers[3].value, registers[4].value), (alus[0].inner_memory_0, alus[1].inner_memory_0));
+    }
+}
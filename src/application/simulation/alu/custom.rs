@@ -0,0 +1,183 @@
+use std::sync::Arc;
+use crate::application::simulation::alu::AluPortsConfig;
+use crate::word::Word;
+
+/// Index of a custom operation in the `CustomOps` of the CPU
+pub type CustomOpId = u8;
+
+/// The ports a custom operation uses. The activation input is always connected.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub struct CustomOpPorts {
+    pub data_input_0        : bool,
+    pub data_input_1        : bool,
+    pub data_output_0       : bool,
+    pub data_output_1       : bool,
+    pub activation_output   : bool,
+}
+
+impl CustomOpPorts {
+    /// Disconnects the ports of `config` the operation doesn't use
+    pub fn apply(&self, config: &AluPortsConfig) -> AluPortsConfig {
+        let keep = |used: bool, port| if used { port } else { None };
+        AluPortsConfig {
+            data_input_0        : keep(self.data_input_0, config.data_input_0),
+            data_input_1        : keep(self.data_input_1, config.data_input_1),
+            activation_input    : config.activation_input,
+            data_output_0       : keep(self.data_output_0, config.data_output_0),
+            data_output_1       : keep(self.data_output_1, config.data_output_1),
+            activation_output   : keep(self.activation_output, config.activation_output),
+        }
+    }
+}
+
+/// What a custom operation reads, `None` for unconnected inputs
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct CustomOpInputs {
+    pub activated       : bool,
+    pub data_input_0    : Option<Word>,
+    pub data_input_1    : Option<Word>,
+}
+
+/// What a custom operation writes, `None` leaving an output as it was
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub struct CustomOpOutputs {
+    pub data_output_0       : Option<Word>,
+    pub data_output_1       : Option<Word>,
+    pub activation_output   : bool,
+}
+
+/// A function unit defined outside the crate, configured through `AluOperation::Custom`
+pub trait CustomAluOperation: Send + Sync {
+    /// Drawn on the ALUs holding the operation
+    fn name(&self) -> &str;
+
+    fn ports(&self) -> CustomOpPorts;
+
+    /// Whether the outputs depend only on the current inputs, see `AluOpKind::is_combinational`.
+    /// Defaults to false, so an operation keeping `state` is evaluated every cycle unless it
+    /// opts out.
+    fn is_combinational(&self) -> bool {
+        false
+    }
+
+    /// Called on every evaluation, activated or not. `state` is kept across evaluations and
+    /// cleared when the ALU is reconfigured.
+    fn execute(&self, inputs: CustomOpInputs, state: &mut [Word; 2]) -> CustomOpOutputs;
+}
+
+/// The custom operations a CPU is built with, shared by its ALUs
+#[derive(Clone, Default)]
+pub struct CustomOps {
+    ops : Vec<Arc<dyn CustomAluOperation>>,
+}
+
+impl CustomOps {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the id `AluOperation::Custom` selects the operation with, or `None` once all ids
+    /// are taken
+    pub fn register(&mut self, op: impl CustomAluOperation + 'static) -> Option<CustomOpId> {
+        let id = CustomOpId::try_from(self.ops.len()).ok()?;
+        self.ops.push(Arc::new(op));
+        Some(id)
+    }
+
+    pub fn get(&self, id: CustomOpId) -> Option<&Arc<dyn CustomAluOperation>> {
+        self.ops.get(id as usize)
+    }
+
+    pub fn len(&self) -> usize {
+        self.ops.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ops.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::application::simulation::alu::AluOperation;
+    use crate::application::simulation::encoding::encode_config_frame;
+    use crate::application::simulation::error::{ControllerFault, SimulationError};
+    use crate::application::simulation::instruction::Instruction;
+    use crate::application::simulation::main_memory::MainMemory;
+    use crate::application::simulation::program_image::{ProgramImage, ProgramImageError};
+    use crate::application::simulation::scheduling::SchedulingMode;
+    use crate::application::simulation::simulation::Cpu;
+
+    /// Counts the cycles it's evaluated in, without overriding `is_combinational`
+    struct CycleCounter;
+
+    impl CustomAluOperation for CycleCounter {
+        fn name(&self) -> &str {
+            "CNT"
+        }
+
+        fn ports(&self) -> CustomOpPorts {
+            CustomOpPorts { data_output_0: true, ..CustomOpPorts::default() }
+        }
+
+        fn execute(&self, _inputs: CustomOpInputs, state: &mut [Word; 2]) -> CustomOpOutputs {
+            state[0] += 1;
+            CustomOpOutputs { data_output_0: Some(state[0]), ..CustomOpOutputs::default() }
+        }
+    }
+
+    fn counter(id: CustomOpId) -> AluOperation {
+        AluOperation::Custom {
+            id, activation_input: 0, data_input_0: None, data_input_1: None,
+            data_output_0: Some(1), data_output_1: None, activation_output: None,
+        }
+    }
+
+    #[test]
+    fn stateful_custom_operations_run_every_cycle_under_event_driven_scheduling() {
+        let mut custom_ops = CustomOps::new();
+        let id = custom_ops.register(CycleCounter).unwrap();
+        let program = vec![
+            Instruction::SetAluConfig { alu_addr: 0, alu_config: counter(id) },
+            Instruction::Jump { addr: 1 },
+        ];
+        let mut cpu = Cpu::with_custom_ops(program, &MainMemory::new(vec![]), &custom_ops);
+        cpu.scheduling_mode = SchedulingMode::EventDriven;
+        cpu.run(11).unwrap();
+        assert_eq!(cpu.register_bank.components[1].value, 10);
+    }
+
+    #[test]
+    fn unknown_custom_operations_fault_when_configured() {
+        let mut custom_ops = CustomOps::new();
+        let id = custom_ops.register(CycleCounter).unwrap();
+        let unknown = ControllerFault::UnknownCustomOperation { alu: 0, id: id + 1 };
+
+        let mut cpu = Cpu::new(vec![Instruction::SetAluConfig { alu_addr: 0, alu_config: counter(id + 1) }], vec![]);
+        assert!(matches!(cpu.run(2), Err(SimulationError::ControllerFault { fault, .. }) if fault == unknown));
+
+        let frame = encode_config_frame(&[(0, counter(id + 1))]).unwrap();
+        let mut cpu = Cpu::with_custom_ops(
+            vec![Instruction::LoadConfigFrame { source_addr: 0, count: 1 }],
+            &MainMemory::new(frame),
+            &custom_ops,
+        );
+        assert!(matches!(cpu.run(4), Err(SimulationError::ControllerFault { fault, .. }) if fault == unknown));
+    }
+
+    #[test]
+    fn images_only_run_on_cpus_built_with_their_custom_operations() {
+        let mut custom_ops = CustomOps::new();
+        let id = custom_ops.register(CycleCounter).unwrap();
+        let image = ProgramImage::new(vec![
+            Instruction::SetAluConfig { alu_addr: 0, alu_config: counter(id) },
+            Instruction::Jump { addr: 1 },
+        ], vec![]);
+
+        assert!(matches!(image.clone().into_cpu(), Err(ProgramImageError::OutOfRange(_))));
+        let mut cpu = image.into_cpu_with(&custom_ops).unwrap();
+        cpu.run(11).unwrap();
+        assert_eq!(cpu.register_bank.components[1].value, 10);
+    }
+}
@@ -0,0 +1,43 @@
+use crate::word::{Word, WordWidth};
+
+// bits of the flags word written by the arithmetic operations. Overflow keeps bit 0, where it was
+// the only flag.
+pub const FLAG_OVERFLOW : Word = 1 << 0;
+pub const FLAG_CARRY    : Word = 1 << 1;
+pub const FLAG_ZERO     : Word = 1 << 2;
+pub const FLAG_NEGATIVE : Word = 1 << 3;
+// set by the floating-point operations for a NaN result, or operands they can't order
+pub const FLAG_INVALID  : Word = 1 << 4;
+
+pub fn flags_word(result: Word, carry: bool, overflow: bool) -> Word {
+    let mut flags = 0;
+    if overflow {
+        flags |= FLAG_OVERFLOW;
+    }
+    if carry {
+        flags |= FLAG_CARRY;
+    }
+    if result == 0 {
+        flags |= FLAG_ZERO;
+    }
+    if result < 0 {
+        flags |= FLAG_NEGATIVE;
+    }
+    flags
+}
+
+/// `a + b + carry_in` at `width` and its flags word, the carry flag being the unsigned carry out
+pub fn add_with_carry(a: Word, b: Word, carry_in: bool, width: WordWidth) -> (Word, Word) {
+    let wide = a as i128 + b as i128 + carry_in as i128;
+    let result = width.wrap(wide as Word);
+    let carry = width.unsigned(a) as u128 + width.unsigned(b) as u128 + carry_in as u128 > width.unsigned(-1) as u128;
+    (result, flags_word(result, carry, wide != result as i128))
+}
+
+/// `a - b - borrow_in` at `width` and its flags word, the carry flag being the unsigned borrow
+pub fn sub_with_borrow(a: Word, b: Word, borrow_in: bool, width: WordWidth) -> (Word, Word) {
+    let wide = a as i128 - b as i128 - borrow_in as i128;
+    let result = width.wrap(wide as Word);
+    let borrow = (width.unsigned(a) as u128) < width.unsigned(b) as u128 + borrow_in as u128;
+    (result, flags_word(result, borrow, wide != result as i128))
+}
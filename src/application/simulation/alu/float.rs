@@ -0,0 +1,151 @@
+use crate::application::simulation::alu::{flags_word, FLAG_INVALID, FLAG_OVERFLOW};
+use crate::word::{Word, WordWidth};
+
+// Floating-point operations read the low 32 bits of words as IEEE-754 `f32` bit patterns, and
+// write them sign-extended. They need words of at least 32 bits, so the controller refuses to
+// configure them on narrower machines. Every NaN they produce is the canonical quiet NaN, so
+// results don't depend on the host.
+pub const CANONICAL_NAN: Word = 0x7fc0_0000;
+
+pub fn word_to_f32(word: Word) -> f32 {
+    f32::from_bits(word as u32)
+}
+
+pub fn f32_to_word(value: f32) -> Word {
+    if value.is_nan() {
+        CANONICAL_NAN
+    } else {
+        value.to_bits() as i32 as Word
+    }
+}
+
+fn float_flags(result: f32, invalid: bool, overflow: bool) -> Word {
+    let mut flags = 0;
+    if invalid {
+        flags |= FLAG_INVALID;
+    }
+    if overflow {
+        flags |= FLAG_OVERFLOW;
+    }
+    if !result.is_nan() {
+        // -0.0 counts as zero, not negative
+        flags |= flags_word((result > 0.0) as Word - (result < 0.0) as Word, false, false);
+    }
+    flags
+}
+
+/// The word and flags word of `result`, computed from `a` and `b`. A NaN result is invalid,
+/// and an infinite one from finite operands, dividing by zero included, overflows.
+pub fn float_result(a: f32, b: f32, result: f32) -> (Word, Word) {
+    let overflow = result.is_infinite() && a.is_finite() && b.is_finite();
+    (f32_to_word(result), float_flags(result, result.is_nan(), overflow))
+}
+
+/// -1, 0 or 1 as `a` is less than, equal to or greater than `b`, with 0 and the invalid flag
+/// alone if either is NaN
+pub fn float_compare(a: f32, b: f32) -> (Word, Word) {
+    match a.partial_cmp(&b) {
+        Some(ordering)  => {
+            let res = ordering as Word;
+            (res, flags_word(res, false, false))
+        }
+        None            => (0, FLAG_INVALID),
+    }
+}
+
+/// Rounds to the nearest float
+pub fn int_to_float(value: Word) -> (Word, Word) {
+    let result = value as f32;
+    (f32_to_word(result), float_flags(result, false, false))
+}
+
+/// Truncates toward zero. NaN gives 0 and the invalid flag, values out of the range of `width`
+/// clamp to it and set the overflow flag.
+pub fn float_to_int(value: f32, width: WordWidth) -> (Word, Word) {
+    let result = (value as Word).clamp(width.min(), width.max());
+    let overflow = !value.is_nan() && (value.trunc() < width.min() as f32 || value.trunc() >= -(width.min() as f32));
+    let flags = flags_word(result, false, overflow);
+    if value.is_nan() {
+        (0, flags | FLAG_INVALID)
+    } else {
+        (result, flags)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::application::simulation::alu::{AluOperation, FLAG_NEGATIVE};
+    use crate::application::simulation::cpu_registers::CpuRegisterAddress;
+    use crate::application::simulation::instruction::Instruction;
+    use crate::application::simulation::simulation::Cpu;
+
+    type FloatOp = fn(CpuRegisterAddress, CpuRegisterAddress, CpuRegisterAddress, CpuRegisterAddress) -> AluOperation;
+
+    /// Each operation on its inputs, with the word and flags word it should output at a width
+    fn float_operations(width: WordWidth) -> [(FloatOp, Word, Word, (Word, Word)); 6] {
+        macro_rules! binary {
+            ($variant:ident) => {
+                |data_input_0, data_input_1, data_output_0, flags_output| AluOperation::$variant {
+                    activation_input: 0, data_input_0, data_input_1, data_output_0,
+                    flags_output: Some(flags_output), activation_output: None,
+                }
+            };
+        }
+        macro_rules! conversion {
+            ($variant:ident) => {
+                |data_input, _, data_output, flags_output| AluOperation::$variant {
+                    activation_input: 0, data_input, data_output,
+                    flags_output: Some(flags_output), activation_output: None,
+                }
+            };
+        }
+        let float = f32_to_word;
+        let infinity = float(f32::INFINITY);
+        let too_large = if width == WordWidth::W32 {
+            (i32::MAX as Word, FLAG_OVERFLOW)
+        } else {
+            (3_000_000_000, 0)
+        };
+        [
+            (binary!(FAdd),             float(1.5),     float(2.25),    (float(3.75), 0)),
+            (binary!(FMul),             float(-2.0),    float(3.0),     (float(-6.0), FLAG_NEGATIVE)),
+            (binary!(FDiv),             float(1.0),     float(0.0),     (infinity, FLAG_OVERFLOW)),
+            (binary!(FSub),             infinity,       infinity,       (CANONICAL_NAN, FLAG_INVALID)),
+            (conversion!(IntToFloat),   -3,             0,              (float(-3.0), FLAG_NEGATIVE)),
+            (conversion!(FloatToInt),   float(3e9),     0,              too_large),
+        ]
+    }
+
+    #[test]
+    fn float_operations_at_32_and_64_bits() {
+        for width in [WordWidth::W32, WordWidth::W64] {
+            let operations = float_operations(width);
+            let mut program = Vec::new();
+            for (ix, (operation, input_0, input_1, _)) in operations.iter().enumerate() {
+                program.push(Instruction::SetLiteral { literal: *input_0, register: 1 + 2 * ix });
+                program.push(Instruction::SetLiteral { literal: *input_1, register: 2 + 2 * ix });
+                program.push(Instruction::SetAluConfig {
+                    alu_addr    : ix,
+                    alu_config  : operation(1 + 2 * ix, 2 + 2 * ix, 30 + 2 * ix, 31 + 2 * ix),
+                });
+            }
+            program.push(Instruction::SetLiteral { literal: !0, register: 0 });
+            program.push(Instruction::NoOp);
+
+            let mut cpu = Cpu::new(program, vec![]);
+            cpu.set_word_width(width);
+            cpu.run(40).unwrap();
+            for (ix, (operation, _, _, expected)) in operations.iter().enumerate() {
+                let registers = &cpu.register_bank.components;
+                assert_eq!(
+                    (registers[30 + 2 * ix].value, registers[31 + 2 * ix].value),
+                    *expected,
+                    "{:?} {:?}",
+                    width,
+                    operation(0, 0, 0, 0).kind(),
+                );
+            }
+        }
+    }
+}
@@ -1,9 +1,14 @@
 pub mod core;
 pub mod op;
+pub mod flags;
+pub mod float;
+pub mod custom;
 
 use std::array;
 pub use core::*;
 pub use op::*;
+pub use flags::*;
+pub use custom::*;
 use crate::{Step };
 use crate::application::simulation::component_bank::ComponentBank;
 use crate::application::simulation::cpu_registers::CpuRegisterBank;
@@ -16,6 +21,7 @@ pub const ALU_COUNT: usize = 32;
 impl AluBank {
     pub fn new(
         main_memory: &mut MainMemory,
+        custom_ops: &CustomOps,
     ) -> Self{
 
         Self{
@@ -23,6 +29,7 @@ impl AluBank {
                 AluCore::new(
                     i,
                     main_memory,
+                    custom_ops,
                 )
             ))
         }
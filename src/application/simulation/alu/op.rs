@@ -1,4 +1,6 @@
-use crate::application::simulation::cpu_registers::CpuRegisterAddress;
+use crate::application::simulation::alu::CustomOpId;
+use crate::application::simulation::cpu_registers::{register_mask, CpuRegisterAddress, RegisterMask};
+use crate::word::WordWidth;
 
 #[derive(Clone, PartialEq, Eq, Debug, Copy)]
 pub enum MovInput {
@@ -16,6 +18,64 @@ pub enum AluOperation {
         data_input_1        : CpuRegisterAddress,
         data_output         : CpuRegisterAddress,
     },
+    /// Signed comparisons, with the same ports as `Eq`
+    Lt {
+        activation_input    : CpuRegisterAddress,
+        activation_output   : Option<CpuRegisterAddress>,
+        data_input_0        : CpuRegisterAddress,
+        data_input_1        : CpuRegisterAddress,
+        data_output         : CpuRegisterAddress,
+    },
+    Le {
+        activation_input    : CpuRegisterAddress,
+        activation_output   : Option<CpuRegisterAddress>,
+        data_input_0        : CpuRegisterAddress,
+        data_input_1        : CpuRegisterAddress,
+        data_output         : CpuRegisterAddress,
+    },
+    Gt {
+        activation_input    : CpuRegisterAddress,
+        activation_output   : Option<CpuRegisterAddress>,
+        data_input_0        : CpuRegisterAddress,
+        data_input_1        : CpuRegisterAddress,
+        data_output         : CpuRegisterAddress,
+    },
+    Ge {
+        activation_input    : CpuRegisterAddress,
+        activation_output   : Option<CpuRegisterAddress>,
+        data_input_0        : CpuRegisterAddress,
+        data_input_1        : CpuRegisterAddress,
+        data_output         : CpuRegisterAddress,
+    },
+    /// Unsigned comparisons, with the same ports as `Eq`
+    LtU {
+        activation_input    : CpuRegisterAddress,
+        activation_output   : Option<CpuRegisterAddress>,
+        data_input_0        : CpuRegisterAddress,
+        data_input_1        : CpuRegisterAddress,
+        data_output         : CpuRegisterAddress,
+    },
+    LeU {
+        activation_input    : CpuRegisterAddress,
+        activation_output   : Option<CpuRegisterAddress>,
+        data_input_0        : CpuRegisterAddress,
+        data_input_1        : CpuRegisterAddress,
+        data_output         : CpuRegisterAddress,
+    },
+    GtU {
+        activation_input    : CpuRegisterAddress,
+        activation_output   : Option<CpuRegisterAddress>,
+        data_input_0        : CpuRegisterAddress,
+        data_input_1        : CpuRegisterAddress,
+        data_output         : CpuRegisterAddress,
+    },
+    GeU {
+        activation_input    : CpuRegisterAddress,
+        activation_output   : Option<CpuRegisterAddress>,
+        data_input_0        : CpuRegisterAddress,
+        data_input_1        : CpuRegisterAddress,
+        data_output         : CpuRegisterAddress,
+    },
     // Mov {
     //     activation_input    : CpuRegisterAddress,
     //     value_input         : CpuRegisterAddress,
@@ -57,6 +117,7 @@ pub enum AluOperation {
         data_output_0        : CpuRegisterAddress,
         activation_output : Option<CpuRegisterAddress>,
     },
+    /// Gives 0 for shift counts of the word width or more
     ShiftLeft {
         activation_input  : CpuRegisterAddress,
         value                   : CpuRegisterAddress,
@@ -64,6 +125,8 @@ pub enum AluOperation {
         data_output_0        : CpuRegisterAddress,
         activation_output : Option<CpuRegisterAddress>,
     },
+    /// Shifts copies of the sign bit in from the left, so shift counts of the word width or more
+    /// give 0 or -1
     ShiftRight {
         activation_input: CpuRegisterAddress,
         value: CpuRegisterAddress,
@@ -78,6 +141,8 @@ pub enum AluOperation {
         data_output_0: CpuRegisterAddress,
         activation_output: Option<CpuRegisterAddress>,
     },
+    /// Writes 1 to `flags_output` on signed overflow and 0 otherwise, as does `Sub`. Their
+    /// saturating and carry variants write a word of `FLAG_*` bits instead.
     Add {
         activation_input  : CpuRegisterAddress,
         data_input_1            : CpuRegisterAddress,
@@ -138,7 +203,418 @@ pub enum AluOperation {
         data_input              : CpuRegisterAddress,
         activation_output : Option<CpuRegisterAddress>,
     },
+    /// Adds the input to the word in memory in a single access, outputting the previous word
+    AtomicAdd {
+        activation_input    : CpuRegisterAddress,
+        address_input       : CpuRegisterAddress,
+        data_input          : CpuRegisterAddress,
+        data_output         : CpuRegisterAddress,
+        activation_output   : Option<CpuRegisterAddress>,
+    },
+    /// Replaces the word in memory in a single access, outputting the previous word
+    AtomicSwap {
+        activation_input    : CpuRegisterAddress,
+        address_input       : CpuRegisterAddress,
+        data_input          : CpuRegisterAddress,
+        data_output         : CpuRegisterAddress,
+        activation_output   : Option<CpuRegisterAddress>,
+    },
+    /// Outputs `data_input_1` if the selector is set and `data_input_0` otherwise, every cycle. The
+    /// selector takes the activation input port.
+    Mux {
+        select_input        : CpuRegisterAddress,
+        data_input_0        : CpuRegisterAddress,
+        data_input_1        : CpuRegisterAddress,
+        data_output         : CpuRegisterAddress,
+        activation_output   : Option<CpuRegisterAddress>,
+    },
+    /// Adds `data_input` to a running sum on every activated cycle, or clears the sum while
+    /// `reset_input` is set
+    Accumulate {
+        activation_input    : CpuRegisterAddress,
+        data_input          : CpuRegisterAddress,
+        reset_input         : CpuRegisterAddress,
+        data_output         : CpuRegisterAddress,
+        activation_output   : Option<CpuRegisterAddress>,
+    },
+    /// Counts activated cycles, wrapping to 0 when the count reaches `limit_input`, and sets the
+    /// activation output for the cycle it wraps in. A limit below 1 never wraps.
+    Counter {
+        activation_input    : CpuRegisterAddress,
+        limit_input         : CpuRegisterAddress,
+        data_output         : CpuRegisterAddress,
+        activation_output   : Option<CpuRegisterAddress>,
+    },
+    /// Shifts zeros in from the left, giving 0 for shift counts of the word width or more
+    ShiftRightLogical {
+        activation_input    : CpuRegisterAddress,
+        value               : CpuRegisterAddress,
+        shift_count         : CpuRegisterAddress,
+        data_output_0       : CpuRegisterAddress,
+        activation_output   : Option<CpuRegisterAddress>,
+    },
+    /// Rotates by the shift count modulo the word width, so a negative count rotates the other
+    /// way
+    RotateLeft {
+        activation_input    : CpuRegisterAddress,
+        value               : CpuRegisterAddress,
+        shift_count         : CpuRegisterAddress,
+        data_output_0       : CpuRegisterAddress,
+        activation_output   : Option<CpuRegisterAddress>,
+    },
+    /// Rotates by the shift count modulo the word width, so a negative count rotates the other
+    /// way
+    RotateRight {
+        activation_input    : CpuRegisterAddress,
+        value               : CpuRegisterAddress,
+        shift_count         : CpuRegisterAddress,
+        data_output_0       : CpuRegisterAddress,
+        activation_output   : Option<CpuRegisterAddress>,
+    },
+    PopCount {
+        activation_input    : CpuRegisterAddress,
+        data_input          : CpuRegisterAddress,
+        data_output         : CpuRegisterAddress,
+        activation_output   : Option<CpuRegisterAddress>,
+    },
+    /// Gives the word width in bits for 0
+    LeadingZeros {
+        activation_input    : CpuRegisterAddress,
+        data_input          : CpuRegisterAddress,
+        data_output         : CpuRegisterAddress,
+        activation_output   : Option<CpuRegisterAddress>,
+    },
+    /// Gives the word width in bits for 0
+    TrailingZeros {
+        activation_input    : CpuRegisterAddress,
+        data_input          : CpuRegisterAddress,
+        data_output         : CpuRegisterAddress,
+        activation_output   : Option<CpuRegisterAddress>,
+    },
+    BitReverse {
+        activation_input    : CpuRegisterAddress,
+        data_input          : CpuRegisterAddress,
+        data_output         : CpuRegisterAddress,
+        activation_output   : Option<CpuRegisterAddress>,
+    },
+    /// Clamps to the word range instead of wrapping, setting the overflow flag when it clamps
+    AddSaturating {
+        activation_input    : CpuRegisterAddress,
+        data_input_0        : CpuRegisterAddress,
+        data_input_1        : CpuRegisterAddress,
+        data_output_0       : CpuRegisterAddress,
+        flags_output        : Option<CpuRegisterAddress>,
+        activation_output   : Option<CpuRegisterAddress>,
+    },
+    /// Clamps to the word range instead of wrapping, setting the overflow flag when it clamps
+    SubSaturating {
+        activation_input    : CpuRegisterAddress,
+        data_input_0        : CpuRegisterAddress,
+        data_input_1        : CpuRegisterAddress,
+        data_output_0       : CpuRegisterAddress,
+        flags_output        : Option<CpuRegisterAddress>,
+        activation_output   : Option<CpuRegisterAddress>,
+    },
+    /// Adds the carry input, set for any nonzero value, and sets the carry output on an unsigned
+    /// carry out, so wider additions chain one word per ALU through the carry ports. Evaluated
+    /// every cycle.
+    AddWithCarry {
+        carry_input         : CpuRegisterAddress,
+        data_input_0        : CpuRegisterAddress,
+        data_input_1        : CpuRegisterAddress,
+        data_output_0       : CpuRegisterAddress,
+        flags_output        : Option<CpuRegisterAddress>,
+        carry_output        : Option<CpuRegisterAddress>,
+    },
+    /// Subtracts the carry input, set for any nonzero value, as a borrow and sets the carry output
+    /// on an unsigned borrow, so wider subtractions chain one word per ALU through the carry
+    /// ports. Evaluated every cycle.
+    SubWithBorrow {
+        carry_input         : CpuRegisterAddress,
+        data_input_0        : CpuRegisterAddress,
+        data_input_1        : CpuRegisterAddress,
+        data_output_0       : CpuRegisterAddress,
+        flags_output        : Option<CpuRegisterAddress>,
+        carry_output        : Option<CpuRegisterAddress>,
+    },
+    /// Operates on `f32` bit patterns, see `alu::float` for NaN, infinity and flags
+    FAdd {
+        activation_input    : CpuRegisterAddress,
+        data_input_0        : CpuRegisterAddress,
+        data_input_1        : CpuRegisterAddress,
+        data_output_0       : CpuRegisterAddress,
+        flags_output        : Option<CpuRegisterAddress>,
+        activation_output   : Option<CpuRegisterAddress>,
+    },
+    FSub {
+        activation_input    : CpuRegisterAddress,
+        data_input_0        : CpuRegisterAddress,
+        data_input_1        : CpuRegisterAddress,
+        data_output_0       : CpuRegisterAddress,
+        flags_output        : Option<CpuRegisterAddress>,
+        activation_output   : Option<CpuRegisterAddress>,
+    },
+    FMul {
+        activation_input    : CpuRegisterAddress,
+        data_input_0        : CpuRegisterAddress,
+        data_input_1        : CpuRegisterAddress,
+        data_output_0       : CpuRegisterAddress,
+        flags_output        : Option<CpuRegisterAddress>,
+        activation_output   : Option<CpuRegisterAddress>,
+    },
+    FDiv {
+        activation_input    : CpuRegisterAddress,
+        data_input_0        : CpuRegisterAddress,
+        data_input_1        : CpuRegisterAddress,
+        data_output_0       : CpuRegisterAddress,
+        flags_output        : Option<CpuRegisterAddress>,
+        activation_output   : Option<CpuRegisterAddress>,
+    },
+    /// Outputs -1, 0 or 1 as `data_input_0` is less than, equal to or greater than `data_input_1`
+    FCmp {
+        activation_input    : CpuRegisterAddress,
+        data_input_0        : CpuRegisterAddress,
+        data_input_1        : CpuRegisterAddress,
+        data_output_0       : CpuRegisterAddress,
+        flags_output        : Option<CpuRegisterAddress>,
+        activation_output   : Option<CpuRegisterAddress>,
+    },
+    IntToFloat {
+        activation_input    : CpuRegisterAddress,
+        data_input          : CpuRegisterAddress,
+        data_output         : CpuRegisterAddress,
+        flags_output        : Option<CpuRegisterAddress>,
+        activation_output   : Option<CpuRegisterAddress>,
+    },
+    /// Truncates toward zero, clamping to the word range
+    FloatToInt {
+        activation_input    : CpuRegisterAddress,
+        data_input          : CpuRegisterAddress,
+        data_output         : CpuRegisterAddress,
+        flags_output        : Option<CpuRegisterAddress>,
+        activation_output   : Option<CpuRegisterAddress>,
+    },
+    /// Runs the custom operation registered under `id` with the CPU, leaving the ports it doesn't
+    /// use unconnected
+    Custom {
+        id                  : CustomOpId,
+        activation_input    : CpuRegisterAddress,
+        data_input_0        : Option<CpuRegisterAddress>,
+        data_input_1        : Option<CpuRegisterAddress>,
+        data_output_0       : Option<CpuRegisterAddress>,
+        data_output_1       : Option<CpuRegisterAddress>,
+        activation_output   : Option<CpuRegisterAddress>,
+    },
+}
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug, Hash)]
+pub enum AluOpKind {
+    NoOp,
+    Eq,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    LtU,
+    LeU,
+    GtU,
+    GeU,
+    Latch,
+    Not,
+    And,
+    Or,
+    Xor,
+    ShiftLeft,
+    ShiftRight,
+    SelectPart,
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Rem,
+    Neg,
+    ReadFromMem,
+    WriteToMem,
+    AtomicAdd,
+    AtomicSwap,
+    Mux,
+    Accumulate,
+    Counter,
+    ShiftRightLogical,
+    RotateLeft,
+    RotateRight,
+    PopCount,
+    LeadingZeros,
+    TrailingZeros,
+    BitReverse,
+    AddSaturating,
+    SubSaturating,
+    AddWithCarry,
+    SubWithBorrow,
+    FAdd,
+    FSub,
+    FMul,
+    FDiv,
+    FCmp,
+    IntToFloat,
+    FloatToInt,
+    Custom,
+}
+
+impl AluOpKind {
+    pub const COUNT: usize = 50;
+    pub const ALL: [AluOpKind; Self::COUNT] = [
+        AluOpKind::NoOp,
+        AluOpKind::Eq,
+        AluOpKind::Lt,
+        AluOpKind::Le,
+        AluOpKind::Gt,
+        AluOpKind::Ge,
+        AluOpKind::LtU,
+        AluOpKind::LeU,
+        AluOpKind::GtU,
+        AluOpKind::GeU,
+        AluOpKind::Latch,
+        AluOpKind::Not,
+        AluOpKind::And,
+        AluOpKind::Or,
+        AluOpKind::Xor,
+        AluOpKind::ShiftLeft,
+        AluOpKind::ShiftRight,
+        AluOpKind::SelectPart,
+        AluOpKind::Add,
+        AluOpKind::Sub,
+        AluOpKind::Mul,
+        AluOpKind::Div,
+        AluOpKind::Rem,
+        AluOpKind::Neg,
+        AluOpKind::ReadFromMem,
+        AluOpKind::WriteToMem,
+        AluOpKind::AtomicAdd,
+        AluOpKind::AtomicSwap,
+        AluOpKind::Mux,
+        AluOpKind::Accumulate,
+        AluOpKind::Counter,
+        AluOpKind::ShiftRightLogical,
+        AluOpKind::RotateLeft,
+        AluOpKind::RotateRight,
+        AluOpKind::PopCount,
+        AluOpKind::LeadingZeros,
+        AluOpKind::TrailingZeros,
+        AluOpKind::BitReverse,
+        AluOpKind::AddSaturating,
+        AluOpKind::SubSaturating,
+        AluOpKind::AddWithCarry,
+        AluOpKind::SubWithBorrow,
+        AluOpKind::FAdd,
+        AluOpKind::FSub,
+        AluOpKind::FMul,
+        AluOpKind::FDiv,
+        AluOpKind::FCmp,
+        AluOpKind::IntToFloat,
+        AluOpKind::FloatToInt,
+        AluOpKind::Custom,
+    ];
+
+    /// Whether the outputs depend only on the current inputs, so evaluating the operation again
+    /// with unchanged inputs can't change anything. `Custom` operations answer for themselves
+    /// through `AluCore::is_combinational`.
+    pub fn is_combinational(&self) -> bool {
+        !matches!(
+            self,
+            AluOpKind::Latch
+            | AluOpKind::Accumulate
+            | AluOpKind::Counter
+            | AluOpKind::Custom
+            | AluOpKind::ReadFromMem
+            | AluOpKind::WriteToMem
+            | AluOpKind::AtomicAdd
+            | AluOpKind::AtomicSwap
+        )
+    }
+
+    /// Whether the operation can run on words of `width`. Floating-point operations need the 32
+    /// bits of an `f32`.
+    pub fn runs_at(&self, width: WordWidth) -> bool {
+        width.bits() >= 32 || !matches!(
+            self,
+            AluOpKind::FAdd
+            | AluOpKind::FSub
+            | AluOpKind::FMul
+            | AluOpKind::FDiv
+            | AluOpKind::FCmp
+            | AluOpKind::IntToFloat
+            | AluOpKind::FloatToInt
+        )
+    }
+
+    /// Whether the operation touches state shared with other ALUs, which forces it to be
+    /// evaluated in address order
+    pub fn accesses_memory(&self) -> bool {
+        matches!(
+            self,
+            AluOpKind::ReadFromMem
+            | AluOpKind::WriteToMem
+            | AluOpKind::AtomicAdd
+            | AluOpKind::AtomicSwap
+        )
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            AluOpKind::NoOp         => "NoOp",
+            AluOpKind::Eq           => "Eq",
+            AluOpKind::Lt           => "Lt",
+            AluOpKind::Le           => "Le",
+            AluOpKind::Gt           => "Gt",
+            AluOpKind::Ge           => "Ge",
+            AluOpKind::LtU          => "LtU",
+            AluOpKind::LeU          => "LeU",
+            AluOpKind::GtU          => "GtU",
+            AluOpKind::GeU          => "GeU",
+            AluOpKind::Latch        => "Latch",
+            AluOpKind::Not          => "Not",
+            AluOpKind::And          => "And",
+            AluOpKind::Or           => "Or",
+            AluOpKind::Xor          => "Xor",
+            AluOpKind::ShiftLeft    => "ShiftLeft",
+            AluOpKind::ShiftRight   => "ShiftRight",
+            AluOpKind::SelectPart   => "SelectPart",
+            AluOpKind::Add          => "Add",
+            AluOpKind::Sub          => "Sub",
+            AluOpKind::Mul          => "Mul",
+            AluOpKind::Div          => "Div",
+            AluOpKind::Rem          => "Rem",
+            AluOpKind::Neg          => "Neg",
+            AluOpKind::ReadFromMem  => "ReadFromMem",
+            AluOpKind::WriteToMem   => "WriteToMem",
+            AluOpKind::AtomicAdd    => "AtomicAdd",
+            AluOpKind::AtomicSwap   => "AtomicSwap",
+            AluOpKind::Mux          => "Mux",
+            AluOpKind::Accumulate   => "Accumulate",
+            AluOpKind::Counter      => "Counter",
+            AluOpKind::ShiftRightLogical => "ShiftRightLogical",
+            AluOpKind::RotateLeft   => "RotateLeft",
+            AluOpKind::RotateRight  => "RotateRight",
+            AluOpKind::PopCount     => "PopCount",
+            AluOpKind::LeadingZeros => "LeadingZeros",
+            AluOpKind::TrailingZeros => "TrailingZeros",
+            AluOpKind::BitReverse   => "BitReverse",
+            AluOpKind::AddSaturating => "AddSaturating",
+            AluOpKind::SubSaturating => "SubSaturating",
+            AluOpKind::AddWithCarry => "AddWithCarry",
+            AluOpKind::SubWithBorrow => "SubWithBorrow",
+            AluOpKind::FAdd         => "FAdd",
+            AluOpKind::FSub         => "FSub",
+            AluOpKind::FMul         => "FMul",
+            AluOpKind::FDiv         => "FDiv",
+            AluOpKind::FCmp         => "FCmp",
+            AluOpKind::IntToFloat   => "IntToFloat",
+            AluOpKind::FloatToInt   => "FloatToInt",
+            AluOpKind::Custom       => "Custom",
+        }
+    }
 }
+
 pub struct AluPortsConfig {
     pub data_input_0    : Option<CpuRegisterAddress>,
     pub data_input_1    : Option<CpuRegisterAddress>,
@@ -147,7 +623,86 @@ pub struct AluPortsConfig {
     pub data_output_1    : Option<CpuRegisterAddress>,
     pub activation_output: Option<CpuRegisterAddress>,
 }
+pub const ALU_INPUT_PORT_COUNT: usize = 3;
+pub const ALU_OUTPUT_PORT_COUNT: usize = 3;
+
+/// The registers wired to each port, flattened once per configuration so a step only walks
+/// fixed-size arrays. Inputs are ordered `DataIn0, DataIn1, ActivationIn` and outputs
+/// `DataOut0, DataOut1, ActivationOut`.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub struct AluPortTable {
+    pub inputs      : [Option<CpuRegisterAddress>; ALU_INPUT_PORT_COUNT],
+    pub outputs     : [Option<CpuRegisterAddress>; ALU_OUTPUT_PORT_COUNT],
+    pub input_mask  : RegisterMask,
+}
+
+impl From<&AluPortsConfig> for AluPortTable {
+    fn from(config: &AluPortsConfig) -> Self {
+        let inputs = [config.data_input_0, config.data_input_1, config.activation_input];
+        AluPortTable {
+            inputs,
+            outputs     : [config.data_output_0, config.data_output_1, config.activation_output],
+            input_mask  : register_mask(inputs),
+        }
+    }
+}
+
 impl AluOperation {
+    pub fn kind(&self) -> AluOpKind {
+        match self {
+            AluOperation::NoOp              => AluOpKind::NoOp,
+            AluOperation::Eq { .. }         => AluOpKind::Eq,
+            AluOperation::Lt { .. }         => AluOpKind::Lt,
+            AluOperation::Le { .. }         => AluOpKind::Le,
+            AluOperation::Gt { .. }         => AluOpKind::Gt,
+            AluOperation::Ge { .. }         => AluOpKind::Ge,
+            AluOperation::LtU { .. }        => AluOpKind::LtU,
+            AluOperation::LeU { .. }        => AluOpKind::LeU,
+            AluOperation::GtU { .. }        => AluOpKind::GtU,
+            AluOperation::GeU { .. }        => AluOpKind::GeU,
+            AluOperation::Latch { .. }      => AluOpKind::Latch,
+            AluOperation::Not { .. }        => AluOpKind::Not,
+            AluOperation::And { .. }        => AluOpKind::And,
+            AluOperation::Or { .. }         => AluOpKind::Or,
+            AluOperation::Xor { .. }        => AluOpKind::Xor,
+            AluOperation::ShiftLeft { .. }  => AluOpKind::ShiftLeft,
+            AluOperation::ShiftRight { .. } => AluOpKind::ShiftRight,
+            AluOperation::SelectPart { .. } => AluOpKind::SelectPart,
+            AluOperation::Add { .. }        => AluOpKind::Add,
+            AluOperation::Sub { .. }        => AluOpKind::Sub,
+            AluOperation::Mul { .. }        => AluOpKind::Mul,
+            AluOperation::Div { .. }        => AluOpKind::Div,
+            AluOperation::Rem { .. }        => AluOpKind::Rem,
+            AluOperation::Neg { .. }        => AluOpKind::Neg,
+            AluOperation::ReadFromMem { .. }=> AluOpKind::ReadFromMem,
+            AluOperation::WriteToMem { .. } => AluOpKind::WriteToMem,
+            AluOperation::AtomicAdd { .. }  => AluOpKind::AtomicAdd,
+            AluOperation::AtomicSwap { .. } => AluOpKind::AtomicSwap,
+            AluOperation::Mux { .. }        => AluOpKind::Mux,
+            AluOperation::Accumulate { .. } => AluOpKind::Accumulate,
+            AluOperation::Counter { .. }    => AluOpKind::Counter,
+            AluOperation::ShiftRightLogical { .. }=> AluOpKind::ShiftRightLogical,
+            AluOperation::RotateLeft { .. } => AluOpKind::RotateLeft,
+            AluOperation::RotateRight { .. }=> AluOpKind::RotateRight,
+            AluOperation::PopCount { .. }   => AluOpKind::PopCount,
+            AluOperation::LeadingZeros { .. }=> AluOpKind::LeadingZeros,
+            AluOperation::TrailingZeros { .. }=> AluOpKind::TrailingZeros,
+            AluOperation::BitReverse { .. } => AluOpKind::BitReverse,
+            AluOperation::AddSaturating { .. }=> AluOpKind::AddSaturating,
+            AluOperation::SubSaturating { .. }=> AluOpKind::SubSaturating,
+            AluOperation::AddWithCarry { .. }=> AluOpKind::AddWithCarry,
+            AluOperation::SubWithBorrow { .. }=> AluOpKind::SubWithBorrow,
+            AluOperation::FAdd { .. }       => AluOpKind::FAdd,
+            AluOperation::FSub { .. }       => AluOpKind::FSub,
+            AluOperation::FMul { .. }       => AluOpKind::FMul,
+            AluOperation::FDiv { .. }       => AluOpKind::FDiv,
+            AluOperation::FCmp { .. }       => AluOpKind::FCmp,
+            AluOperation::IntToFloat { .. } => AluOpKind::IntToFloat,
+            AluOperation::FloatToInt { .. } => AluOpKind::FloatToInt,
+            AluOperation::Custom { .. }     => AluOpKind::Custom,
+        }
+    }
+
     pub fn get_ports_config(&self) -> AluPortsConfig {
         match self.clone() {
             AluOperation::NoOp => AluPortsConfig {
@@ -165,7 +720,64 @@ impl AluOperation {
                 data_input_0,
                 data_input_1,
                 data_output: data_output_0,
-            } => AluPortsConfig {
+            }
+            | AluOperation::Lt {
+                activation_input,
+                activation_output,
+                data_input_0,
+                data_input_1,
+                data_output: data_output_0,
+            }
+            | AluOperation::Le {
+                activation_input,
+                activation_output,
+                data_input_0,
+                data_input_1,
+                data_output: data_output_0,
+            }
+            | AluOperation::Gt {
+                activation_input,
+                activation_output,
+                data_input_0,
+                data_input_1,
+                data_output: data_output_0,
+            }
+            | AluOperation::Ge {
+                activation_input,
+                activation_output,
+                data_input_0,
+                data_input_1,
+                data_output: data_output_0,
+            }
+            | AluOperation::LtU {
+                activation_input,
+                activation_output,
+                data_input_0,
+                data_input_1,
+                data_output: data_output_0,
+            }
+            | AluOperation::LeU {
+                activation_input,
+                activation_output,
+                data_input_0,
+                data_input_1,
+                data_output: data_output_0,
+            }
+            | AluOperation::GtU {
+                activation_input,
+                activation_output,
+                data_input_0,
+                data_input_1,
+                data_output: data_output_0,
+            }
+            | AluOperation::GeU {
+                activation_input,
+                activation_output,
+                data_input_0,
+                data_input_1,
+                data_output: data_output_0,
+            }
+            => AluPortsConfig {
                 data_input_0: Some(data_input_0),
                 data_input_1: Some(data_input_1),
                 activation_input: Some(activation_input),
@@ -212,7 +824,7 @@ impl AluOperation {
                 activation_input: Some(activation_input),
                 data_output_0: Some(data_output_0),
                 data_output_1: None,
-                activation_output:activation_output,
+                activation_output,
             },
             AluOperation::And {
                 activation_input,
@@ -226,7 +838,7 @@ impl AluOperation {
                 activation_input: Some(activation_input),
                 data_output_0: Some(data_output_0),
                 data_output_1: None,
-                activation_output: activation_output,
+                activation_output,
             },
             AluOperation::Or {
                 activation_input,
@@ -240,7 +852,7 @@ impl AluOperation {
                 activation_input    : Some(activation_input),
                 data_output_0: Some(data_output_0),
                 data_output_1: None,
-                activation_output: activation_output,
+                activation_output,
             },
             AluOperation::Xor {
                 activation_input,
@@ -254,7 +866,7 @@ impl AluOperation {
                 activation_input: Some(activation_input),
                 data_output_0: Some(data_output_0),
                 data_output_1: None,
-                activation_output: activation_output,
+                activation_output,
             },
             AluOperation::ShiftLeft {
                 activation_input,
@@ -268,7 +880,7 @@ impl AluOperation {
                 activation_input: Some(activation_input),
                 data_output_0: Some(data_output_0),
                 data_output_1: None,
-                activation_output: activation_output,
+                activation_output,
             },
             AluOperation::ShiftRight {
                 activation_input,
@@ -282,7 +894,7 @@ impl AluOperation {
                 activation_input: Some(activation_input),
                 data_output_0: Some(data_output_0),
                 data_output_1: None,
-                activation_output: activation_output,
+                activation_output,
             },
             AluOperation::SelectPart {
                 activation_input,
@@ -296,7 +908,7 @@ impl AluOperation {
                 activation_input: Some(activation_input),
                 data_output_0: Some(data_output_0),
                 data_output_1: None,
-                activation_output: activation_output,
+                activation_output,
             },
             AluOperation::Add {
                 activation_input,
@@ -311,7 +923,7 @@ impl AluOperation {
                 activation_input: Some(activation_input),
                 data_output_0: Some(data_output_0),
                 data_output_1: flags_output,
-                activation_output: activation_output,
+                activation_output,
             },
             AluOperation::Sub {
                 activation_input,
@@ -326,7 +938,7 @@ impl AluOperation {
                 activation_input: Some(activation_input),
                 data_output_0: Some(data_output_0),
                 data_output_1: flags_output,
-                activation_output: activation_output,
+                activation_output,
             },
             AluOperation::Mul {
                 activation_input,
@@ -341,7 +953,7 @@ impl AluOperation {
                 activation_input: Some(activation_input),
                 data_output_0: Some(data_output_0),
                 data_output_1: second_word_output,
-                activation_output: activation_output,
+                activation_output,
             },
             AluOperation::Div {
                 activation_input,
@@ -356,7 +968,7 @@ impl AluOperation {
                 activation_input: Some(activation_input),
                 data_output_0: Some(data_output_0),
                 data_output_1: div_by_zero_output,
-                activation_output: activation_output,
+                activation_output,
             },
             AluOperation::Rem {
                 activation_input,
@@ -371,7 +983,7 @@ impl AluOperation {
                 activation_input: Some(activation_input),
                 data_output_0: Some(data_output_0),
                 data_output_1: div_by_zero_output,
-                activation_output: activation_output,
+                activation_output,
             },
             AluOperation::Neg {
                 activation_input,
@@ -384,7 +996,7 @@ impl AluOperation {
                 activation_input: Some(activation_input),
                 data_output_0: Some(data_output_0),
                 data_output_1: None,
-                activation_output: activation_output,
+                activation_output,
             },
             AluOperation::ReadFromMem {
                 activation_input,
@@ -397,7 +1009,7 @@ impl AluOperation {
                 activation_input: Some(activation_input),
                 data_output_0: Some(data_output_0),
                 data_output_1: None,
-                activation_output: activation_output,
+                activation_output,
             },
             AluOperation::WriteToMem {
                 activation_input,
@@ -405,13 +1017,782 @@ impl AluOperation {
                 address_input,
                 activation_output,
             } => AluPortsConfig {
-                data_input_0: Some(data_input),
-                data_input_1: Some(address_input),
+                data_input_0: Some(address_input),
+                data_input_1: Some(data_input),
                 activation_input: Some(activation_input),
                 data_output_0: None,
                 data_output_1: None,
                 activation_output,
             },
+            AluOperation::AtomicAdd {
+                activation_input,
+                address_input,
+                data_input,
+                data_output,
+                activation_output,
+            }
+            | AluOperation::AtomicSwap {
+                activation_input,
+                address_input,
+                data_input,
+                data_output,
+                activation_output,
+            } => AluPortsConfig {
+                data_input_0: Some(address_input),
+                data_input_1: Some(data_input),
+                activation_input: Some(activation_input),
+                data_output_0: Some(data_output),
+                data_output_1: None,
+                activation_output,
+            },
+            AluOperation::Mux {
+                select_input,
+                data_input_0,
+                data_input_1,
+                data_output,
+                activation_output,
+            } => AluPortsConfig {
+                data_input_0: Some(data_input_0),
+                data_input_1: Some(data_input_1),
+                activation_input: Some(select_input),
+                data_output_0: Some(data_output),
+                data_output_1: None,
+                activation_output,
+            },
+            AluOperation::Accumulate {
+                activation_input,
+                data_input,
+                reset_input,
+                data_output,
+                activation_output,
+            } => AluPortsConfig {
+                data_input_0: Some(data_input),
+                data_input_1: Some(reset_input),
+                activation_input: Some(activation_input),
+                data_output_0: Some(data_output),
+                data_output_1: None,
+                activation_output,
+            },
+            AluOperation::Counter {
+                activation_input,
+                limit_input,
+                data_output,
+                activation_output,
+            } => AluPortsConfig {
+                data_input_0: Some(limit_input),
+                data_input_1: None,
+                activation_input: Some(activation_input),
+                data_output_0: Some(data_output),
+                data_output_1: None,
+                activation_output,
+            },
+            AluOperation::ShiftRightLogical {
+                activation_input,
+                value,
+                shift_count,
+                data_output_0,
+                activation_output,
+            } => AluPortsConfig {
+                data_input_0: Some(value),
+                data_input_1: Some(shift_count),
+                activation_input: Some(activation_input),
+                data_output_0: Some(data_output_0),
+                data_output_1: None,
+                activation_output,
+            },
+            AluOperation::RotateLeft {
+                activation_input,
+                value,
+                shift_count,
+                data_output_0,
+                activation_output,
+            } => AluPortsConfig {
+                data_input_0: Some(value),
+                data_input_1: Some(shift_count),
+                activation_input: Some(activation_input),
+                data_output_0: Some(data_output_0),
+                data_output_1: None,
+                activation_output,
+            },
+            AluOperation::RotateRight {
+                activation_input,
+                value,
+                shift_count,
+                data_output_0,
+                activation_output,
+            } => AluPortsConfig {
+                data_input_0: Some(value),
+                data_input_1: Some(shift_count),
+                activation_input: Some(activation_input),
+                data_output_0: Some(data_output_0),
+                data_output_1: None,
+                activation_output,
+            },
+            AluOperation::PopCount {
+                activation_input,
+                data_input,
+                data_output,
+                activation_output,
+            } => AluPortsConfig {
+                data_input_0: Some(data_input),
+                data_input_1: None,
+                activation_input: Some(activation_input),
+                data_output_0: Some(data_output),
+                data_output_1: None,
+                activation_output,
+            },
+            AluOperation::LeadingZeros {
+                activation_input,
+                data_input,
+                data_output,
+                activation_output,
+            } => AluPortsConfig {
+                data_input_0: Some(data_input),
+                data_input_1: None,
+                activation_input: Some(activation_input),
+                data_output_0: Some(data_output),
+                data_output_1: None,
+                activation_output,
+            },
+            AluOperation::TrailingZeros {
+                activation_input,
+                data_input,
+                data_output,
+                activation_output,
+            } => AluPortsConfig {
+                data_input_0: Some(data_input),
+                data_input_1: None,
+                activation_input: Some(activation_input),
+                data_output_0: Some(data_output),
+                data_output_1: None,
+                activation_output,
+            },
+            AluOperation::BitReverse {
+                activation_input,
+                data_input,
+                data_output,
+                activation_output,
+            } => AluPortsConfig {
+                data_input_0: Some(data_input),
+                data_input_1: None,
+                activation_input: Some(activation_input),
+                data_output_0: Some(data_output),
+                data_output_1: None,
+                activation_output,
+            },
+            AluOperation::AddSaturating {
+                activation_input,
+                data_input_0,
+                data_input_1,
+                data_output_0,
+                flags_output,
+                activation_output,
+            } => AluPortsConfig {
+                data_input_0: Some(data_input_0),
+                data_input_1: Some(data_input_1),
+                activation_input: Some(activation_input),
+                data_output_0: Some(data_output_0),
+                data_output_1: flags_output,
+                activation_output,
+            },
+            AluOperation::SubSaturating {
+                activation_input,
+                data_input_0,
+                data_input_1,
+                data_output_0,
+                flags_output,
+                activation_output,
+            } => AluPortsConfig {
+                data_input_0: Some(data_input_0),
+                data_input_1: Some(data_input_1),
+                activation_input: Some(activation_input),
+                data_output_0: Some(data_output_0),
+                data_output_1: flags_output,
+                activation_output,
+            },
+            AluOperation::AddWithCarry {
+                carry_input,
+                data_input_0,
+                data_input_1,
+                data_output_0,
+                flags_output,
+                carry_output,
+            } => AluPortsConfig {
+                data_input_0: Some(data_input_0),
+                data_input_1: Some(data_input_1),
+                activation_input: Some(carry_input),
+                data_output_0: Some(data_output_0),
+                data_output_1: flags_output,
+                activation_output: carry_output,
+            },
+            AluOperation::SubWithBorrow {
+                carry_input,
+                data_input_0,
+                data_input_1,
+                data_output_0,
+                flags_output,
+                carry_output,
+            } => AluPortsConfig {
+                data_input_0: Some(data_input_0),
+                data_input_1: Some(data_input_1),
+                activation_input: Some(carry_input),
+                data_output_0: Some(data_output_0),
+                data_output_1: flags_output,
+                activation_output: carry_output,
+            },
+            AluOperation::FAdd {
+                activation_input,
+                data_input_0,
+                data_input_1,
+                data_output_0,
+                flags_output,
+                activation_output,
+            } => AluPortsConfig {
+                data_input_0: Some(data_input_0),
+                data_input_1: Some(data_input_1),
+                activation_input: Some(activation_input),
+                data_output_0: Some(data_output_0),
+                data_output_1: flags_output,
+                activation_output,
+            },
+            AluOperation::FSub {
+                activation_input,
+                data_input_0,
+                data_input_1,
+                data_output_0,
+                flags_output,
+                activation_output,
+            } => AluPortsConfig {
+                data_input_0: Some(data_input_0),
+                data_input_1: Some(data_input_1),
+                activation_input: Some(activation_input),
+                data_output_0: Some(data_output_0),
+                data_output_1: flags_output,
+                activation_output,
+            },
+            AluOperation::FMul {
+                activation_input,
+                data_input_0,
+                data_input_1,
+                data_output_0,
+                flags_output,
+                activation_output,
+            } => AluPortsConfig {
+                data_input_0: Some(data_input_0),
+                data_input_1: Some(data_input_1),
+                activation_input: Some(activation_input),
+                data_output_0: Some(data_output_0),
+                data_output_1: flags_output,
+                activation_output,
+            },
+            AluOperation::FDiv {
+                activation_input,
+                data_input_0,
+                data_input_1,
+                data_output_0,
+                flags_output,
+                activation_output,
+            } => AluPortsConfig {
+                data_input_0: Some(data_input_0),
+                data_input_1: Some(data_input_1),
+                activation_input: Some(activation_input),
+                data_output_0: Some(data_output_0),
+                data_output_1: flags_output,
+                activation_output,
+            },
+            AluOperation::FCmp {
+                activation_input,
+                data_input_0,
+                data_input_1,
+                data_output_0,
+                flags_output,
+                activation_output,
+            } => AluPortsConfig {
+                data_input_0: Some(data_input_0),
+                data_input_1: Some(data_input_1),
+                activation_input: Some(activation_input),
+                data_output_0: Some(data_output_0),
+                data_output_1: flags_output,
+                activation_output,
+            },
+            AluOperation::IntToFloat {
+                activation_input,
+                data_input,
+                data_output,
+                flags_output,
+                activation_output,
+            } => AluPortsConfig {
+                data_input_0: Some(data_input),
+                data_input_1: None,
+                activation_input: Some(activation_input),
+                data_output_0: Some(data_output),
+                data_output_1: flags_output,
+                activation_output,
+            },
+            AluOperation::FloatToInt {
+                activation_input,
+                data_input,
+                data_output,
+                flags_output,
+                activation_output,
+            } => AluPortsConfig {
+                data_input_0: Some(data_input),
+                data_input_1: None,
+                activation_input: Some(activation_input),
+                data_output_0: Some(data_output),
+                data_output_1: flags_output,
+                activation_output,
+            },
+            AluOperation::Custom {
+                id: _,
+                activation_input,
+                data_input_0,
+                data_input_1,
+                data_output_0,
+                data_output_1,
+                activation_output,
+            } => AluPortsConfig {
+                data_input_0,
+                data_input_1,
+                activation_input: Some(activation_input),
+                data_output_0,
+                data_output_1,
+                activation_output,
+            },
         }
     }
 }
+
+impl AluOpKind {
+    pub fn from_index(index: usize) -> Option<AluOpKind> {
+        Self::ALL.get(index).copied()
+    }
+}
+
+impl AluOperation {
+    /// The same operation with every connected port moved `offset` registers up, `None` if a
+    /// port would land at or past `register_count`
+    pub fn offset_registers(&self, offset: usize, register_count: usize) -> Option<AluOperation> {
+        let ports = self.get_ports_config();
+        let shift = |port: Option<CpuRegisterAddress>| -> Option<Option<CpuRegisterAddress>> {
+            match port {
+                None        => Some(None),
+                Some(port)  => port.checked_add(offset).filter(|port| *port < register_count).map(Some),
+            }
+        };
+        let shifted = AluPortsConfig {
+            data_input_0        : shift(ports.data_input_0)?,
+            data_input_1        : shift(ports.data_input_1)?,
+            activation_input    : shift(ports.activation_input)?,
+            data_output_0       : shift(ports.data_output_0)?,
+            data_output_1       : shift(ports.data_output_1)?,
+            activation_output   : shift(ports.activation_output)?,
+        };
+        AluOperation::from_ports_config(self.kind(), &shifted).map(|op| op.with_custom_id(self.custom_id()))
+    }
+
+    pub fn custom_id(&self) -> Option<CustomOpId> {
+        match self {
+            AluOperation::Custom { id, .. } => Some(*id),
+            _                               => None,
+        }
+    }
+
+    /// Sets the id of a `Custom` operation, leaving other operations as they are
+    pub fn with_custom_id(mut self, custom_id: Option<CustomOpId>) -> Self {
+        if let (AluOperation::Custom { id, .. }, Some(custom_id)) = (&mut self, custom_id) {
+            *id = custom_id;
+        }
+        self
+    }
+
+    /// The inverse of `get_ports_config`, `None` if a port the operation needs isn't connected
+    pub fn from_ports_config(kind: AluOpKind, ports: &AluPortsConfig) -> Option<AluOperation> {
+        let activation_input = ports.activation_input;
+        let activation_output = ports.activation_output;
+        let data_input_0 = ports.data_input_0;
+        let data_input_1 = ports.data_input_1;
+        let data_output_0 = ports.data_output_0;
+        let data_output_1 = ports.data_output_1;
+
+        Some(match kind {
+            AluOpKind::NoOp => AluOperation::NoOp,
+            AluOpKind::Eq => AluOperation::Eq {
+                activation_input    : activation_input?,
+                activation_output,
+                data_input_0        : data_input_0?,
+                data_input_1        : data_input_1?,
+                data_output         : data_output_0?,
+            },
+            AluOpKind::Lt => AluOperation::Lt {
+                activation_input    : activation_input?,
+                activation_output,
+                data_input_0        : data_input_0?,
+                data_input_1        : data_input_1?,
+                data_output         : data_output_0?,
+            },
+            AluOpKind::Le => AluOperation::Le {
+                activation_input    : activation_input?,
+                activation_output,
+                data_input_0        : data_input_0?,
+                data_input_1        : data_input_1?,
+                data_output         : data_output_0?,
+            },
+            AluOpKind::Gt => AluOperation::Gt {
+                activation_input    : activation_input?,
+                activation_output,
+                data_input_0        : data_input_0?,
+                data_input_1        : data_input_1?,
+                data_output         : data_output_0?,
+            },
+            AluOpKind::Ge => AluOperation::Ge {
+                activation_input    : activation_input?,
+                activation_output,
+                data_input_0        : data_input_0?,
+                data_input_1        : data_input_1?,
+                data_output         : data_output_0?,
+            },
+            AluOpKind::LtU => AluOperation::LtU {
+                activation_input    : activation_input?,
+                activation_output,
+                data_input_0        : data_input_0?,
+                data_input_1        : data_input_1?,
+                data_output         : data_output_0?,
+            },
+            AluOpKind::LeU => AluOperation::LeU {
+                activation_input    : activation_input?,
+                activation_output,
+                data_input_0        : data_input_0?,
+                data_input_1        : data_input_1?,
+                data_output         : data_output_0?,
+            },
+            AluOpKind::GtU => AluOperation::GtU {
+                activation_input    : activation_input?,
+                activation_output,
+                data_input_0        : data_input_0?,
+                data_input_1        : data_input_1?,
+                data_output         : data_output_0?,
+            },
+            AluOpKind::GeU => AluOperation::GeU {
+                activation_input    : activation_input?,
+                activation_output,
+                data_input_0        : data_input_0?,
+                data_input_1        : data_input_1?,
+                data_output         : data_output_0?,
+            },
+            AluOpKind::Latch => AluOperation::Latch {
+                activation_input    : activation_input?,
+                data_input          : data_input_0?,
+                hold_input          : data_input_1?,
+                data_output         : data_output_0?,
+                activation_output,
+            },
+            AluOpKind::Not => AluOperation::Not {
+                activation_input    : activation_input?,
+                data_input          : data_input_0?,
+                data_output         : data_output_0?,
+                activation_output,
+            },
+            AluOpKind::And => AluOperation::And {
+                activation_input    : activation_input?,
+                data_input_0        : data_input_0?,
+                data_input_1        : data_input_1?,
+                data_output_0       : data_output_0?,
+                activation_output,
+            },
+            AluOpKind::Or => AluOperation::Or {
+                activation_input    : activation_input?,
+                data_input_0        : data_input_0?,
+                data_input_1        : data_input_1?,
+                data_output_0       : data_output_0?,
+                activation_output,
+            },
+            AluOpKind::Xor => AluOperation::Xor {
+                activation_input    : activation_input?,
+                data_input_0        : data_input_0?,
+                data_input_1        : data_input_1?,
+                data_output_0       : data_output_0?,
+                activation_output,
+            },
+            AluOpKind::ShiftLeft => AluOperation::ShiftLeft {
+                activation_input    : activation_input?,
+                value               : data_input_0?,
+                shift_count         : data_input_1?,
+                data_output_0       : data_output_0?,
+                activation_output,
+            },
+            AluOpKind::ShiftRight => AluOperation::ShiftRight {
+                activation_input    : activation_input?,
+                value               : data_input_0?,
+                shift_count         : data_input_1?,
+                data_output_0       : data_output_0?,
+                activation_output,
+            },
+            AluOpKind::SelectPart => AluOperation::SelectPart {
+                activation_input    : activation_input?,
+                selection_input     : data_input_0?,
+                data_input          : data_input_1?,
+                data_output_0       : data_output_0?,
+                activation_output,
+            },
+            AluOpKind::Add => AluOperation::Add {
+                activation_input    : activation_input?,
+                data_input_0        : data_input_0?,
+                data_input_1        : data_input_1?,
+                data_output_0       : data_output_0?,
+                flags_output        : data_output_1,
+                activation_output,
+            },
+            AluOpKind::Sub => AluOperation::Sub {
+                activation_input    : activation_input?,
+                data_input_0        : data_input_0?,
+                data_input_1        : data_input_1?,
+                data_output_0       : data_output_0?,
+                flags_output        : data_output_1,
+                activation_output,
+            },
+            AluOpKind::Mul => AluOperation::Mul {
+                activation_input    : activation_input?,
+                data_input_0        : data_input_0?,
+                data_input_1        : data_input_1?,
+                first_word_output   : data_output_0?,
+                second_word_output  : data_output_1,
+                activation_output,
+            },
+            AluOpKind::Div => AluOperation::Div {
+                activation_input        : activation_input?,
+                dividend                : data_input_0?,
+                divisor                 : data_input_1?,
+                data_output_0           : data_output_0?,
+                div_by_zero_flag_output : data_output_1,
+                activation_output,
+            },
+            AluOpKind::Rem => AluOperation::Rem {
+                activation_input        : activation_input?,
+                dividend                : data_input_0?,
+                divisor                 : data_input_1?,
+                data_output_0           : data_output_0?,
+                div_by_zero_flag_output : data_output_1,
+                activation_output,
+            },
+            AluOpKind::Neg => AluOperation::Neg {
+                activation_input    : activation_input?,
+                input               : data_input_0?,
+                data_output_0       : data_output_0?,
+                activation_output,
+            },
+            AluOpKind::ReadFromMem => AluOperation::ReadFromMem {
+                activation_input    : activation_input?,
+                data_input_0        : data_input_0?,
+                data_output_0       : data_output_0?,
+                activation_output,
+            },
+            AluOpKind::WriteToMem => AluOperation::WriteToMem {
+                activation_input    : activation_input?,
+                address_input       : data_input_0?,
+                data_input          : data_input_1?,
+                activation_output,
+            },
+            AluOpKind::AtomicAdd => AluOperation::AtomicAdd {
+                activation_input    : activation_input?,
+                address_input       : data_input_0?,
+                data_input          : data_input_1?,
+                data_output         : data_output_0?,
+                activation_output,
+            },
+            AluOpKind::AtomicSwap => AluOperation::AtomicSwap {
+                activation_input    : activation_input?,
+                address_input       : data_input_0?,
+                data_input          : data_input_1?,
+                data_output         : data_output_0?,
+                activation_output,
+            },
+            AluOpKind::Mux => AluOperation::Mux {
+                select_input        : activation_input?,
+                data_input_0        : data_input_0?,
+                data_input_1        : data_input_1?,
+                data_output         : data_output_0?,
+                activation_output,
+            },
+            AluOpKind::Accumulate => AluOperation::Accumulate {
+                activation_input    : activation_input?,
+                data_input          : data_input_0?,
+                reset_input         : data_input_1?,
+                data_output         : data_output_0?,
+                activation_output,
+            },
+            AluOpKind::Counter => AluOperation::Counter {
+                activation_input    : activation_input?,
+                limit_input         : data_input_0?,
+                data_output         : data_output_0?,
+                activation_output,
+            },
+            AluOpKind::ShiftRightLogical => AluOperation::ShiftRightLogical {
+                activation_input    : activation_input?,
+                value               : data_input_0?,
+                shift_count         : data_input_1?,
+                data_output_0       : data_output_0?,
+                activation_output,
+            },
+            AluOpKind::RotateLeft => AluOperation::RotateLeft {
+                activation_input    : activation_input?,
+                value               : data_input_0?,
+                shift_count         : data_input_1?,
+                data_output_0       : data_output_0?,
+                activation_output,
+            },
+            AluOpKind::RotateRight => AluOperation::RotateRight {
+                activation_input    : activation_input?,
+                value               : data_input_0?,
+                shift_count         : data_input_1?,
+                data_output_0       : data_output_0?,
+                activation_output,
+            },
+            AluOpKind::PopCount => AluOperation::PopCount {
+                activation_input    : activation_input?,
+                data_input          : data_input_0?,
+                data_output         : data_output_0?,
+                activation_output,
+            },
+            AluOpKind::LeadingZeros => AluOperation::LeadingZeros {
+                activation_input    : activation_input?,
+                data_input          : data_input_0?,
+                data_output         : data_output_0?,
+                activation_output,
+            },
+            AluOpKind::TrailingZeros => AluOperation::TrailingZeros {
+                activation_input    : activation_input?,
+                data_input          : data_input_0?,
+                data_output         : data_output_0?,
+                activation_output,
+            },
+            AluOpKind::BitReverse => AluOperation::BitReverse {
+                activation_input    : activation_input?,
+                data_input          : data_input_0?,
+                data_output         : data_output_0?,
+                activation_output,
+            },
+            AluOpKind::AddSaturating => AluOperation::AddSaturating {
+                activation_input    : activation_input?,
+                data_input_0        : data_input_0?,
+                data_input_1        : data_input_1?,
+                data_output_0       : data_output_0?,
+                flags_output        : data_output_1,
+                activation_output,
+            },
+            AluOpKind::SubSaturating => AluOperation::SubSaturating {
+                activation_input    : activation_input?,
+                data_input_0        : data_input_0?,
+                data_input_1        : data_input_1?,
+                data_output_0       : data_output_0?,
+                flags_output        : data_output_1,
+                activation_output,
+            },
+            AluOpKind::AddWithCarry => AluOperation::AddWithCarry {
+                carry_input         : activation_input?,
+                data_input_0        : data_input_0?,
+                data_input_1        : data_input_1?,
+                data_output_0       : data_output_0?,
+                flags_output        : data_output_1,
+                carry_output        : activation_output,
+            },
+            AluOpKind::SubWithBorrow => AluOperation::SubWithBorrow {
+                carry_input         : activation_input?,
+                data_input_0        : data_input_0?,
+                data_input_1        : data_input_1?,
+                data_output_0       : data_output_0?,
+                flags_output        : data_output_1,
+                carry_output        : activation_output,
+            },
+            AluOpKind::FAdd => AluOperation::FAdd {
+                activation_input    : activation_input?,
+                data_input_0        : data_input_0?,
+                data_input_1        : data_input_1?,
+                data_output_0       : data_output_0?,
+                flags_output        : data_output_1,
+                activation_output,
+            },
+            AluOpKind::FSub => AluOperation::FSub {
+                activation_input    : activation_input?,
+                data_input_0        : data_input_0?,
+                data_input_1        : data_input_1?,
+                data_output_0       : data_output_0?,
+                flags_output        : data_output_1,
+                activation_output,
+            },
+            AluOpKind::FMul => AluOperation::FMul {
+                activation_input    : activation_input?,
+                data_input_0        : data_input_0?,
+                data_input_1        : data_input_1?,
+                data_output_0       : data_output_0?,
+                flags_output        : data_output_1,
+                activation_output,
+            },
+            AluOpKind::FDiv => AluOperation::FDiv {
+                activation_input    : activation_input?,
+                data_input_0        : data_input_0?,
+                data_input_1        : data_input_1?,
+                data_output_0       : data_output_0?,
+                flags_output        : data_output_1,
+                activation_output,
+            },
+            AluOpKind::FCmp => AluOperation::FCmp {
+                activation_input    : activation_input?,
+                data_input_0        : data_input_0?,
+                data_input_1        : data_input_1?,
+                data_output_0       : data_output_0?,
+                flags_output        : data_output_1,
+                activation_output,
+            },
+            AluOpKind::IntToFloat => AluOperation::IntToFloat {
+                activation_input    : activation_input?,
+                data_input          : data_input_0?,
+                data_output         : data_output_0?,
+                flags_output        : data_output_1,
+                activation_output,
+            },
+            AluOpKind::FloatToInt => AluOperation::FloatToInt {
+                activation_input    : activation_input?,
+                data_input          : data_input_0?,
+                data_output         : data_output_0?,
+                flags_output        : data_output_1,
+                activation_output,
+            },
+            // the id isn't part of the ports, see `with_custom_id`
+            AluOpKind::Custom => AluOperation::Custom {
+                id                  : 0,
+                activation_input    : activation_input?,
+                data_input_0,
+                data_input_1,
+                data_output_0,
+                data_output_1,
+                activation_output,
+            },
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::application::simulation::instruction::Instruction;
+    use crate::application::simulation::simulation::Cpu;
+
+    #[test]
+    fn write_to_mem_stores_its_data_at_its_address() {
+        let write = AluOperation::WriteToMem { activation_input: 0, address_input: 1, data_input: 2, activation_output: None };
+        let ports = write.get_ports_config();
+        assert_eq!((ports.data_input_0, ports.data_input_1), (Some(1), Some(2)));
+
+        let program = vec![
+            Instruction::SetLiteral { literal: 3, register: 1 },
+            Instruction::SetLiteral { literal: 42, register: 2 },
+            Instruction::SetAluConfig { alu_addr: 0, alu_config: write },
+            Instruction::SetLiteral { literal: !0, register: 0 },
+            Instruction::NoOp,
+        ];
+        let mut cpu = Cpu::new(program, vec![0; 8]);
+        cpu.run(10).unwrap();
+        assert_eq!(cpu.main_memory.contents(), vec![0, 0, 0, 42, 0, 0, 0, 0]);
+    }
+}
@@ -0,0 +1,303 @@
+use std::num::NonZeroUsize;
+use crate::Step;
+use crate::word::Word;
+use crate::application::simulation::memory_timing::MemoryAccessKind;
+use crate::application::simulation::performance::CacheStats;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum WritePolicy {
+    /// Writes allocate a line and only reach memory when the dirty line is evicted
+    WriteBack,
+    /// Writes always reach memory and don't allocate a line on a miss
+    WriteThrough,
+}
+
+/// A set-associative cache with LRU replacement. Sizes are in words.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct CacheConfig {
+    pub size            : NonZeroUsize,
+    pub line_size       : NonZeroUsize,
+    pub associativity   : NonZeroUsize,
+    pub write_policy    : WritePolicy,
+    pub hit_latency     : Step,
+    /// Added to `hit_latency` for each trip to main memory (a miss, a write-back, a bypass or a
+    /// write-through) while main memory has no timing model. With one, its bank latencies are
+    /// added instead.
+    pub miss_latency    : Step,
+}
+
+impl CacheConfig {
+    pub fn set_count(&self) -> usize {
+        (self.size.get() / (self.line_size.get() * self.associativity.get())).max(1)
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum CacheLookup {
+    Hit,
+    Miss {
+        /// A dirty line must be written back to make room
+        write_back  : bool,
+    },
+    /// The access goes to memory without allocating a line
+    Bypass,
+}
+
+#[derive(Clone, Default)]
+struct CacheLine {
+    valid       : bool,
+    /// Whether `data` holds writes main memory hasn't seen yet
+    dirty       : bool,
+    tag         : usize,
+    last_used   : u64,
+    /// The `line_size` words from the line's first address on
+    data        : Vec<Word>,
+}
+
+/// A dirty line leaving the cache, whose words main memory must store
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct WrittenBackLine {
+    pub addr    : usize,
+    pub data    : Vec<Word>,
+}
+
+/// Holds copies of the lines of main memory, which the accesses of its ALU read and write
+/// instead of memory while the line is cached. Caches aren't kept coherent: a write another CPU
+/// or ALU makes to a cached word isn't seen until the line is evicted, and the writes to a
+/// `WriteBack` line only reach main memory once the dirty line is evicted or flushed.
+pub struct Cache {
+    config      : CacheConfig,
+    /// `associativity` lines per set
+    lines       : Vec<CacheLine>,
+    accesses    : u64,
+    pub stats   : CacheStats,
+}
+
+impl Cache {
+    pub fn new(config: CacheConfig) -> Self {
+        Self {
+            config,
+            lines       : vec![
+                CacheLine { data: vec![0; config.line_size.get()], ..CacheLine::default() };
+                config.set_count() * config.associativity.get()
+            ],
+            accesses    : 0,
+            stats       : CacheStats::default(),
+        }
+    }
+
+    pub fn config(&self) -> CacheConfig {
+        self.config
+    }
+
+    fn set_and_tag(&self, addr: usize) -> (usize, usize) {
+        let line_addr = addr / self.config.line_size.get();
+        (line_addr % self.config.set_count(), line_addr / self.config.set_count())
+    }
+
+    fn set_lines(&mut self, set: usize) -> &mut [CacheLine] {
+        let associativity = self.config.associativity.get();
+        &mut self.lines[set * associativity..(set + 1) * associativity]
+    }
+
+    fn line_addr(&self, set: usize, tag: usize) -> usize {
+        (tag * self.config.set_count() + set) * self.config.line_size.get()
+    }
+
+    fn cached_line(&mut self, addr: usize) -> Option<&mut CacheLine> {
+        let (set, tag) = self.set_and_tag(addr);
+        self.set_lines(set).iter_mut().find(|line| line.valid && line.tag == tag)
+    }
+
+    /// The cached word at `addr`, `None` if no line holds it
+    pub fn read(&mut self, addr: usize) -> Option<Word> {
+        let offset = addr % self.config.line_size.get();
+        self.cached_line(addr).map(|line| line.data[offset])
+    }
+
+    /// Stores `value` in the line holding `addr`, returning false if no line holds it. A
+    /// `WriteThrough` cache leaves storing the word in memory to the caller.
+    pub fn write(&mut self, addr: usize, value: Word) -> bool {
+        let offset = addr % self.config.line_size.get();
+        let write_back = self.config.write_policy == WritePolicy::WriteBack;
+        let Some(line) = self.cached_line(addr) else {
+            return false;
+        };
+        line.data[offset] = value;
+        line.dirty |= write_back;
+        true
+    }
+
+    /// Takes the writes out of every dirty line, which stays cached but clean
+    pub fn flush(&mut self) -> Vec<WrittenBackLine> {
+        let set_count = self.config.set_count();
+        let associativity = self.config.associativity.get();
+        let line_size = self.config.line_size.get();
+        self.lines.iter_mut()
+            .enumerate()
+            .filter(|(_, line)| line.valid && line.dirty)
+            .map(|(ix, line)| {
+                line.dirty = false;
+                WrittenBackLine {
+                    addr    : (line.tag * set_count + ix / associativity) * line_size,
+                    data    : line.data.clone(),
+                }
+            })
+            .collect()
+    }
+
+    /// What an access would do, without changing the cache
+    pub fn lookup(&self, addr: usize, kind: MemoryAccessKind) -> CacheLookup {
+        let (set, tag) = self.set_and_tag(addr);
+        let associativity = self.config.associativity.get();
+        let lines = &self.lines[set * associativity..(set + 1) * associativity];
+        if lines.iter().any(|line| line.valid && line.tag == tag) {
+            return CacheLookup::Hit;
+        }
+        match (kind, self.config.write_policy) {
+            (MemoryAccessKind::ReadWrite, _) | (MemoryAccessKind::Write, WritePolicy::WriteThrough)
+                => CacheLookup::Bypass,
+            _   => CacheLookup::Miss { write_back: lru_line(lines).valid && lru_line(lines).dirty },
+        }
+    }
+
+    /// Performs an access whose `lookup` was `lookup`, updating the lines and the statistics. A
+    /// miss fills the line with the words `load` reads from memory, returning the dirty line it
+    /// evicted if any. The word itself is read or written through `read` and `write` afterwards.
+    pub fn access(
+        &mut self,
+        addr    : usize,
+        lookup  : CacheLookup,
+        load    : impl Fn(usize) -> Word,
+    ) -> Option<WrittenBackLine> {
+        match lookup {
+            CacheLookup::Hit                    => self.stats.hits += 1,
+            CacheLookup::Miss { write_back }    => {
+                self.stats.misses += 1;
+                self.stats.write_backs += write_back as u64;
+            }
+            CacheLookup::Bypass                 => {
+                self.stats.bypasses += 1;
+                return None;
+            }
+        }
+
+        self.accesses += 1;
+        let last_used = self.accesses;
+        let (set, tag) = self.set_and_tag(addr);
+        let line_addr = self.line_addr(set, tag);
+        let lines = self.set_lines(set);
+        if lookup == CacheLookup::Hit {
+            lines.iter_mut().find(|line| line.valid && line.tag == tag).unwrap().last_used = last_used;
+            return None;
+        }
+
+        let line = lru_line_mut(lines);
+        let evicted = (line.valid && line.dirty).then(|| (line.tag, line.data.clone()));
+        line.valid = true;
+        line.dirty = false;
+        line.tag = tag;
+        line.last_used = last_used;
+        for (offset, word) in line.data.iter_mut().enumerate() {
+            *word = load(line_addr + offset);
+        }
+        evicted.map(|(evicted_tag, data)| WrittenBackLine { addr: self.line_addr(set, evicted_tag), data })
+    }
+}
+
+/// The line to replace: an invalid one if any, else the least recently used
+fn lru_line(lines: &[CacheLine]) -> &CacheLine {
+    lines.iter().min_by_key(|line| (line.valid, line.last_used)).unwrap()
+}
+
+fn lru_line_mut(lines: &mut [CacheLine]) -> &mut CacheLine {
+    lines.iter_mut().min_by_key(|line| (line.valid, line.last_used)).unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::application::simulation::main_memory::MainMemory;
+
+    /// Two lines of 4 words, addresses 8 apart sharing a line
+    fn direct_mapped(write_policy: WritePolicy) -> CacheConfig {
+        CacheConfig {
+            size            : NonZeroUsize::new(8).unwrap(),
+            line_size       : NonZeroUsize::new(4).unwrap(),
+            associativity   : NonZeroUsize::MIN,
+            write_policy,
+            hit_latency     : 1,
+            miss_latency    : 5,
+        }
+    }
+
+    #[test]
+    fn misses_take_the_miss_latency_without_a_timing_model() {
+        let memory = MainMemory::new(vec![0; 16]);
+        let mut io = memory.get_io();
+        io.set_cache(Some(direct_mapped(WritePolicy::WriteBack)));
+
+        assert_eq!(io.start_access(0, MemoryAccessKind::Read, 0), Ok(Some(6)));
+        assert_eq!(io.start_access(1, MemoryAccessKind::Read, 1), Ok(Some(1)));
+        assert_eq!(io.start_access(12, MemoryAccessKind::ReadWrite, 2), Ok(Some(5)));
+    }
+
+    #[test]
+    fn hits_read_the_line_the_miss_filled() {
+        let memory = MainMemory::new((0..16).collect());
+        let mut io = memory.get_io();
+        io.set_cache(Some(direct_mapped(WritePolicy::WriteBack)));
+        assert_eq!(io.start_access(1, MemoryAccessKind::Read, 0), Ok(Some(6)));
+        assert_eq!(io.read(1, 0), Ok(1));
+
+        // another writer isn't seen while the line stays cached
+        memory.get_io().write(2, 20, 1).unwrap();
+        assert_eq!(io.start_access(2, MemoryAccessKind::Read, 2), Ok(Some(1)));
+        assert_eq!(io.read(2, 2), Ok(2));
+        assert_eq!(io.cache.as_ref().unwrap().stats, CacheStats { hits: 1, misses: 1, ..CacheStats::default() });
+    }
+
+    #[test]
+    fn write_back_holds_writes_until_the_line_is_evicted() {
+        let memory = MainMemory::new(vec![0; 16]);
+        let mut io = memory.get_io();
+        io.set_cache(Some(direct_mapped(WritePolicy::WriteBack)));
+        assert_eq!(io.start_access(0, MemoryAccessKind::Write, 0), Ok(Some(6)));
+        io.write(0, 7, 0).unwrap();
+        assert_eq!(io.start_access(1, MemoryAccessKind::ReadWrite, 1), Ok(Some(1)));
+        assert_eq!(io.fetch_add(1, 3, 1), Ok(0));
+        assert_eq!(memory.contents()[..2], [0, 0]);
+
+        // address 8 shares the dirty line's set, whose words reach memory as it leaves
+        assert_eq!(io.start_access(8, MemoryAccessKind::Read, 2), Ok(Some(11)));
+        assert_eq!(memory.contents()[..2], [7, 3]);
+        assert_eq!(io.read(8, 2), Ok(0));
+        let stats = io.cache.as_ref().unwrap().stats;
+        assert_eq!((stats.misses, stats.write_backs), (2, 1));
+
+        io.start_access(9, MemoryAccessKind::Write, 3).unwrap();
+        io.write(9, 5, 3).unwrap();
+        assert_eq!(memory.contents()[9], 0);
+        io.flush_cache();
+        assert_eq!(memory.contents()[9], 5);
+    }
+
+    #[test]
+    fn write_through_stores_every_write_in_memory() {
+        let memory = MainMemory::new(vec![0; 16]);
+        let mut io = memory.get_io();
+        io.set_cache(Some(direct_mapped(WritePolicy::WriteThrough)));
+        assert_eq!(io.start_access(0, MemoryAccessKind::Write, 0), Ok(Some(5)));
+        io.write(0, 7, 0).unwrap();
+        assert_eq!(io.cache.as_ref().unwrap().stats.bypasses, 1);
+
+        io.start_access(1, MemoryAccessKind::Read, 1).unwrap();
+        assert_eq!(io.start_access(0, MemoryAccessKind::Write, 2), Ok(Some(5)));
+        io.write(0, 9, 2).unwrap();
+        assert_eq!(io.read(0, 3), Ok(9));
+        assert_eq!(memory.contents()[0], 9);
+
+        io.start_access(8, MemoryAccessKind::Read, 4).unwrap();
+        assert_eq!(io.cache.as_ref().unwrap().stats.write_backs, 0);
+    }
+}
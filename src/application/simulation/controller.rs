@@ -1,15 +1,28 @@
-use crate::application::simulation::alu::{AluAddress, AluOperation, AluBank};
-use crate::application::simulation::cpu_registers::{CpuRegisterDataReader, CpuRegisterDataWriter};
+use crate::application::simulation::alu::{AluAddress, AluOperation, AluBank, CustomOps, ALU_COUNT};
+use crate::application::simulation::cpu_registers::{CpuRegisterDataReader, CpuRegisterDataWriter, REGISTER_COUNT};
 use crate::application::simulation::instruction::Instruction;
 use crate::application::simulation::instruction_reader::IncrementCmd::{GoTo, Increment, NoIncrement};
 use crate::application::simulation::instruction_reader::{InstructionMemory, InstructionReader};
-use crate::word::ToBool;
+use crate::application::simulation::encoding::{decode_instruction, ENCODED_INSTRUCTION_LEN};
+use crate::application::simulation::error::{ControllerFault, SimulationError};
+use crate::application::simulation::machine::UnitKind;
+use crate::application::simulation::main_memory::{MainMemory, MainMemoryIo};
+use crate::application::simulation::performance::{ControllerCounters, MemoryTraffic};
+use crate::application::simulation::scheduling::{mask_bits, AluMask, ALL_ALUS};
+use crate::word::{ToBool, Word, WordWidth};
+use crate::Step;
 use std::fmt::Debug;
 
 #[derive( PartialEq, Eq, Copy, Clone, Debug, )]
 pub enum ControllerExecutionState {
 	Running,
 	WaitingForActivation,
+	/// Copying the instructions of a `LoadInstructions`
+	LoadingInstructions{
+		source_addr	: usize,
+		target_addr	: usize,
+		remaining	: usize,
+	},
 }
 pub struct Controller{
 	pub cpu_registers_reader	: CpuRegisterDataReader,
@@ -18,13 +31,27 @@ pub struct Controller{
 	pub alu_config_writer		: AluConfigWriter	,
 	pub state					: ControllerExecutionState,
 	pub instruction_reader  	: InstructionReader,
+	pub counters				: ControllerCounters,
+	/// The unit in each ALU slot, checked before configuring an ALU
+	pub units					: [UnitKind; ALU_COUNT],
+	/// Literals are wrapped to it as they're written
+	pub word_width				: WordWidth,
+	/// The operations `Custom` configurations can select, checked before configuring an ALU
+	pub custom_ops				: CustomOps,
 	
+	/// The handler of a vectored interrupt to jump to in the next cycle
+	pending_vector				: Option<usize>,
+	/// The program counter to return to, while an interrupt handler runs
+	saved_pc					: Option<Word>,
 	previous_instruction		: Option<Instruction>,
+	instruction_memory			: InstructionMemory,
+	main_memory					: MainMemoryIo,
 }
 
 impl Controller{
 	pub fn new(
 		instruction_memory	: &InstructionMemory,
+		main_memory			: &MainMemory,
 	) -> Self {
 		let instruction_reader = InstructionReader::new(
 			instruction_memory,
@@ -38,7 +65,15 @@ impl Controller{
 			cpu_registers_writer: CpuRegisterDataWriter::new(),
 			alu_config_writer   : configurator,
 			instruction_reader,
-			state				: ControllerExecutionState::Running
+			counters			: ControllerCounters::default(),
+			units				: [UnitKind::General; ALU_COUNT],
+			word_width			: WordWidth::default(),
+			custom_ops			: CustomOps::new(),
+			pending_vector		: None,
+			saved_pc			: None,
+			state				: ControllerExecutionState::Running,
+			instruction_memory	: instruction_memory.clone(),
+			main_memory			: main_memory.get_io(),
 		}	
 	}
 
@@ -47,16 +82,136 @@ impl Controller{
 		self.cpu_registers_writer = CpuRegisterDataWriter::Deactivated;
 	}
 
-	#[must_use]
-	pub fn execute(&mut self) -> bool {
+	pub fn memory_traffic(&self) -> MemoryTraffic {
+		self.main_memory.traffic
+	}
+
+	/// Whether a vectored interrupt can be taken: no handler is running or about to, and no
+	/// `LoadInstructions` is copying
+	pub fn accepts_interrupt(&self) -> bool {
+		self.pending_vector.is_none()
+			&& self.saved_pc.is_none()
+			&& !matches!(self.state, ControllerExecutionState::LoadingInstructions { .. })
+	}
+
+	/// Jumps to `handler` in the next cycle instead of executing an instruction, saving the
+	/// program counter for `ReturnFromInterrupt`
+	pub fn vector_interrupt(&mut self, handler: usize) {
+		self.pending_vector = Some(handler);
+	}
+
+	/// The program counter a running interrupt handler returns to
+	pub fn saved_pc(&self) -> Option<Word> {
+		self.saved_pc
+	}
+
+	fn read_instruction(&mut self, source_addr: usize, cycle: Step) -> Result<Instruction, ControllerFault> {
+		let mut encoded = [0; ENCODED_INSTRUCTION_LEN];
+		for (offset, word) in encoded.iter_mut().enumerate() {
+			*word = self.main_memory.read(source_addr.wrapping_add(offset), cycle).map_err(ControllerFault::Memory)?;
+		}
+		decode_instruction(&encoded).map_err(|error| ControllerFault::Decode { addr: source_addr, error })
+	}
+
+	/// Reads a configuration frame, later entries for an ALU replacing earlier ones
+	fn read_config_frame(
+		&mut self,
+		source_addr	: usize,
+		count		: usize,
+		cycle		: Step,
+	) -> Result<Box<[Option<AluOperation>; ALU_COUNT]>, ControllerFault> {
+		let mut frame = Box::new([None; ALU_COUNT]);
+		for entry_ix in 0..count {
+			let entry_addr = source_addr.wrapping_add(entry_ix * ENCODED_INSTRUCTION_LEN);
+			match self.read_instruction(entry_addr, cycle)? {
+				entry @ Instruction::SetAluConfig { alu_config, alu_addr }
+					if entry.in_range(ALU_COUNT, REGISTER_COUNT) => {
+					frame[alu_addr] = Some(alu_config);
+				}
+				_ => return Err(ControllerFault::InvalidConfigFrameEntry { addr: entry_addr }),
+			}
+		}
+		Ok(frame)
+	}
+
+	/// Replicates `op` over the ALUs of `mask`, ignoring bits past the last ALU
+	fn broadcast_frame(
+		op		: AluOperation,
+		mask	: AluMask,
+		stride	: usize,
+	) -> Result<Box<[Option<AluOperation>; ALU_COUNT]>, ControllerFault> {
+		let mut frame = Box::new([None; ALU_COUNT]);
+		for (lane, alu) in mask_bits(mask & ALL_ALUS).enumerate() {
+			let lane_op = stride.checked_mul(lane)
+				.and_then(|offset| op.offset_registers(offset, REGISTER_COUNT))
+				.ok_or(ControllerFault::BroadcastRegisterOutOfRange { alu })?;
+			frame[alu] = Some(lane_op);
+		}
+		Ok(frame)
+	}
+
+	fn check_supported(&self, alu: AluAddress, op: &AluOperation) -> Result<(), ControllerFault> {
+		let unit = self.units.get(alu).copied().unwrap_or_default();
+		if !unit.supports(op.kind()) {
+			Err(ControllerFault::UnsupportedOperation { alu, unit, kind: op.kind() })
+		} else if !op.kind().runs_at(self.word_width) {
+			Err(ControllerFault::OperationNeedsWiderWords { alu, kind: op.kind(), width: self.word_width })
+		} else if let Some(id) = op.custom_id() && self.custom_ops.get(id).is_none() {
+			Err(ControllerFault::UnknownCustomOperation { alu, id })
+		} else {
+			Ok(())
+		}
+	}
+
+	fn check_frame_supported(&self, frame: &[Option<AluOperation>; ALU_COUNT]) -> Result<(), ControllerFault> {
+		for (alu, op) in frame.iter().enumerate() {
+			if let Some(op) = op {
+				self.check_supported(alu, op)?;
+			}
+		}
+		Ok(())
+	}
+
+	/// Copies one encoded instruction from main memory to instruction memory
+	fn load_instruction(&mut self, source_addr: usize, target_addr: usize, cycle: Step) -> Result<(), ControllerFault> {
+		let instruction = self.read_instruction(source_addr, cycle)?;
+		if !instruction.in_range(ALU_COUNT, REGISTER_COUNT) {
+			return Err(ControllerFault::InstructionOutOfRange { addr: source_addr });
+		}
+		if !self.instruction_memory.write(target_addr, instruction) {
+			return Err(ControllerFault::InstructionAddressOutOfRange { addr: target_addr });
+		}
+		self.counters.instructions_loaded += 1;
+		Ok(())
+	}
+
+	/// Returns whether the program is still running
+	pub fn execute(&mut self, cycle: Step) -> Result<bool, SimulationError> {
+		// outputs only hold for the cycle of the instruction that set them
+		self.reset_outputs();
+
+		if let Some(handler) = self.pending_vector.take() {
+			// an interrupted wait is executed again once the handler returns
+			self.saved_pc = self.instruction_reader.program_counter_reader.read();
+			self.state = ControllerExecutionState::Running;
+			self.counters.interrupts_taken += 1;
+			self.instruction_reader.set_increment_cmd(GoTo(handler as Word));
+			self.instruction_reader.step(self.word_width);
+			return Ok(true);
+		}
+
 		match self.state {
 			ControllerExecutionState::Running => {
-				let Some(current_instruction) = self.instruction_reader.read().map(|i| i.to_owned()) else
+				let Some(current_instruction) = self.instruction_reader.read()
+					.map_err(|fault| SimulationError::ControllerFault { cycle, fault })? else
 				{return
-					false};
+					Ok(false)};
 
+				self.counters.instructions_executed += 1;
 				match current_instruction {
 					Instruction::SetAluConfig {  alu_config, alu_addr, } => {
+						self.check_supported(alu_addr, &alu_config)
+							.map_err(|fault| SimulationError::ControllerFault { cycle, fault })?;
 						self.alu_config_writer = AluConfigWriter::WritingToSingle{
 							target: alu_addr,
 							op: alu_config
@@ -66,7 +221,7 @@ impl Controller{
 					}
 					Instruction::SetLiteral {  literal , register,} => {
 						self.cpu_registers_writer.set_connection(Some(register));
-						self.cpu_registers_writer.write(literal);
+						self.cpu_registers_writer.write(self.word_width.wrap(literal));
 						self.instruction_reader.set_increment_cmd(Increment);
 					}
 					Instruction::WaitForActivationSignal { register_index } => {
@@ -85,6 +240,51 @@ impl Controller{
 					Instruction::NoOp => {
 						self.instruction_reader.set_increment_cmd(Increment);
 					}
+					Instruction::ReturnFromInterrupt => {
+						let return_pc = self.saved_pc.take()
+							.ok_or(SimulationError::ControllerFault { cycle, fault: ControllerFault::ReturnOutsideInterrupt })?;
+						self.instruction_reader.set_increment_cmd(GoTo(return_pc));
+					}
+					Instruction::LoadConfigFrame { source_addr, count } => {
+						let frame = self.read_config_frame(source_addr as usize, count.max(0) as usize, cycle)
+							.and_then(|frame| self.check_frame_supported(&frame).map(|_| frame))
+							.map_err(|fault| SimulationError::ControllerFault { cycle, fault })?;
+						self.alu_config_writer = AluConfigWriter::WritingFrame { ops: frame };
+						self.instruction_reader.set_increment_cmd(Increment);
+					}
+					Instruction::BroadcastAluConfig { alu_config, alu_mask, register_stride } => {
+						let frame = Self::broadcast_frame(alu_config, alu_mask, register_stride)
+							.and_then(|frame| self.check_frame_supported(&frame).map(|_| frame))
+							.map_err(|fault| SimulationError::ControllerFault { cycle, fault })?;
+						self.alu_config_writer = AluConfigWriter::WritingFrame { ops: frame };
+						self.instruction_reader.set_increment_cmd(Increment);
+					}
+					Instruction::LoadInstructions { count: ..=0, .. } => {
+						self.instruction_reader.set_increment_cmd(Increment);
+					}
+					Instruction::LoadInstructions { source_addr, target_addr, count } => {
+						self.state = ControllerExecutionState::LoadingInstructions {
+							source_addr	: source_addr as usize,
+							target_addr	: target_addr as usize,
+							remaining	: count as usize,
+						};
+						self.instruction_reader.set_increment_cmd(NoIncrement);
+					}
+				}
+			}
+			ControllerExecutionState::LoadingInstructions { source_addr, target_addr, remaining } => {
+				self.load_instruction(source_addr, target_addr, cycle)
+					.map_err(|fault| SimulationError::ControllerFault { cycle, fault })?;
+				if remaining > 1 {
+					self.state = ControllerExecutionState::LoadingInstructions {
+						source_addr	: source_addr.wrapping_add(ENCODED_INSTRUCTION_LEN),
+						target_addr	: target_addr + 1,
+						remaining	: remaining - 1,
+					};
+					self.instruction_reader.set_increment_cmd(NoIncrement);
+				} else {
+					self.state = ControllerExecutionState::Running;
+					self.instruction_reader.set_increment_cmd(Increment);
 				}
 			}
 			ControllerExecutionState::WaitingForActivation => {
@@ -93,13 +293,14 @@ impl Controller{
 					self.instruction_reader.set_increment_cmd(Increment);
 					self.state =  ControllerExecutionState::Running;
 				} else {
+					self.counters.stall_cycles += 1;
 					self.instruction_reader.set_increment_cmd(NoIncrement);
 				}
 			}
 		}
 
-		self.instruction_reader.step();
-		true
+		self.instruction_reader.step(self.word_width);
+		Ok(true)
 	}
 }
 
@@ -111,24 +312,221 @@ pub enum AluConfigWriter{
 	},
 	WritingToAll{
 		op		: AluOperation,
-	}
+	},
+	/// Configures every ALU with an operation in the frame, leaving the others as they are. Also
+	/// written by `BroadcastAluConfig`.
+	WritingFrame{
+		ops		: Box<[Option<AluOperation>; ALU_COUNT]>,
+	},
 }
 
 
 
 impl AluConfigWriter{
-	pub fn configure_alus(&self, alu_bank: &mut AluBank){
+	/// Returns whether any ALU was configured
+	pub fn configure_alus(&self, alu_bank: &mut AluBank) -> bool {
 		match &self{
-			AluConfigWriter::Deactivated => {}
+			AluConfigWriter::Deactivated => {
+				return false;
+			}
 			AluConfigWriter::WritingToSingle { target, op } => {
-				alu_bank.components[*target].set_new_operation(op.clone());
+				alu_bank.components[*target].set_new_operation(*op);
 			}
 			AluConfigWriter::WritingToAll { op } => {
 				for alu in alu_bank.components.iter_mut() {
-					alu.set_new_operation(op.clone());
+					alu.set_new_operation(*op);
+				}
+			}
+			AluConfigWriter::WritingFrame { ops } => {
+				for (alu, op) in alu_bank.components.iter_mut().zip(ops.iter()) {
+					if let Some(op) = op {
+						alu.set_new_operation(*op);
+					}
 				}
 			}
 		}
+		true
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use crate::application::simulation::alu::{AluOpKind, AluOperation, ALU_COUNT};
+	use crate::application::simulation::encoding::{encode_config_frame, encode_program};
+	use crate::application::simulation::error::{ControllerFault, SimulationError};
+	use crate::application::simulation::instruction::Instruction;
+	use crate::application::simulation::machine::UnitKind;
+	use crate::application::simulation::simulation::Cpu;
+	use crate::word::{Word, WordWidth};
+
+	#[test]
+	fn literals_and_resets_only_hold_for_their_cycle() {
+		let not = AluOperation::Not { activation_input: 0, data_input: 1, data_output: 2, activation_output: None };
+		let program = vec![
+			Instruction::SetLiteral { literal: 5, register: 10 },
+			Instruction::ResetAll,
+			Instruction::WaitForActivationSignal { register_index: 11 },
+		];
+		let mut cpu = Cpu::new(program, vec![]);
+		cpu.run(4).unwrap();
+		cpu.register_bank.components[10].value = 7;
+		cpu.alu_bank.components[3].set_new_operation(not);
+		cpu.run(4).unwrap();
+		assert_eq!(cpu.register_bank.components[10].value, 7);
+		assert_eq!(cpu.alu_bank.components[3].operation, not);
+	}
+
+	fn load_fault(loaded: Instruction) -> Option<ControllerFault> {
+		let program = vec![Instruction::LoadInstructions { source_addr: 0, target_addr: 1, count: 1 }];
+		let mut cpu = Cpu::new(program, encode_program(&[loaded]).unwrap());
+		match cpu.run(4) {
+			Err(SimulationError::ControllerFault { fault, .. })	=> Some(fault),
+			result												=> { result.unwrap(); None }
+		}
+	}
+
+	#[test]
+	fn loaded_instructions_past_the_last_alu_or_register_fault() {
+		let out_of_range = Some(ControllerFault::InstructionOutOfRange { addr: 0 });
+		assert_eq!(load_fault(Instruction::SetLiteral { literal: 1, register: 64 }), out_of_range);
+		assert_eq!(load_fault(Instruction::WaitForActivationSignal { register_index: 200 }), out_of_range);
+		assert_eq!(load_fault(Instruction::SetAluConfig { alu_addr: 32, alu_config: AluOperation::NoOp }), out_of_range);
+		assert_eq!(load_fault(Instruction::SetAluConfig { alu_addr: 0, alu_config: AluOperation::Not {
+			activation_input: 0, data_input: 70, data_output: 1, activation_output: None,
+		}}), out_of_range);
+		assert_eq!(load_fault(Instruction::BroadcastAluConfig {
+			alu_config		: AluOperation::Not {
+				activation_input: 0, data_input: 1, data_output: 40, activation_output: None,
+			},
+			alu_mask		: 0b111,
+			register_stride	: 20,
+		}), out_of_range);
+		assert_eq!(load_fault(Instruction::SetLiteral { literal: 1, register: 63 }), None);
+	}
+
+	#[test]
+	fn frame_entries_wired_past_the_last_register_are_invalid() {
+		let frame = encode_config_frame(&[
+			(0, AluOperation::Not { activation_input: 0, data_input: 1, data_output: 2, activation_output: None }),
+			(1, AluOperation::Not { activation_input: 0, data_input: 1, data_output: 64, activation_output: None }),
+		]).unwrap();
+		let program = vec![Instruction::LoadConfigFrame { source_addr: 0, count: 2 }];
+		let mut cpu = Cpu::new(program, frame);
+		assert!(matches!(
+			cpu.run(4),
+			Err(SimulationError::ControllerFault { fault: ControllerFault::InvalidConfigFrameEntry { addr: 4 }, .. }),
+		));
+	}
+
+	#[test]
+	fn float_operations_need_32_bit_words() {
+		let fadd = AluOperation::FAdd {
+			activation_input: 0, data_input_0: 1, data_input_1: 2, data_output_0: 3,
+			flags_output: None, activation_output: None,
+		};
+		for width in WordWidth::ALL {
+			let mut cpu = Cpu::new(vec![Instruction::SetAluConfig { alu_addr: 0, alu_config: fadd }], vec![]);
+			cpu.set_word_width(width);
+			let fault = match cpu.run(2) {
+				Err(SimulationError::ControllerFault { fault, .. })	=> Some(fault),
+				result												=> { result.unwrap(); None }
+			};
+			if width.bits() < 32 {
+				assert_eq!(fault, Some(ControllerFault::OperationNeedsWiderWords { alu: 0, kind: AluOpKind::FAdd, width }));
+			} else {
+				assert_eq!(fault, None);
+			}
+		}
+	}
+
+	fn not(activation_input: usize, data_input: usize, data_output: usize) -> AluOperation {
+		AluOperation::Not { activation_input, data_input, data_output, activation_output: None }
+	}
+
+	#[test]
+	fn broadcasts_move_the_registers_of_each_lane_up_by_the_stride() {
+		let program = vec![
+			Instruction::SetLiteral { literal: 1, register: 1 },
+			Instruction::SetLiteral { literal: 2, register: 4 },
+			Instruction::SetLiteral { literal: 3, register: 7 },
+			Instruction::BroadcastAluConfig { alu_config: not(0, 1, 2), alu_mask: 0b1010_0100, register_stride: 3 },
+			Instruction::SetLiteral { literal: !0, register: 0 },
+			Instruction::SetLiteral { literal: !0, register: 3 },
+			Instruction::SetLiteral { literal: !0, register: 6 },
+			Instruction::NoOp,
+		];
+		let mut cpu = Cpu::new(program, vec![]);
+		cpu.run(10).unwrap();
+		let configured = cpu.alu_bank.components.iter()
+			.enumerate()
+			.filter(|(_, alu)| alu.operation != AluOperation::NoOp)
+			.map(|(ix, alu)| (ix, alu.operation))
+			.collect::<Vec<_>>();
+		assert_eq!(configured, vec![(2, not(0, 1, 2)), (5, not(3, 4, 5)), (7, not(6, 7, 8))]);
+		let outputs = [2, 5, 8].map(|register| cpu.register_bank.components[register].value);
+		assert_eq!(outputs, [!1, !2, !3]);
+	}
 
+	#[test]
+	fn broadcast_lanes_past_the_last_register_fault() {
+		let program = vec![
+			Instruction::BroadcastAluConfig { alu_config: not(0, 1, 2), alu_mask: 0b1011, register_stride: 31 },
+		];
+		let mut cpu = Cpu::new(program, vec![]);
+		assert_eq!(
+			cpu.run(2),
+			Err(SimulationError::ControllerFault { cycle: 0, fault: ControllerFault::BroadcastRegisterOutOfRange { alu: 3 } }),
+		);
+		assert!(cpu.alu_bank.components.iter().all(|alu| alu.operation == AluOperation::NoOp));
 	}
-}
\ No newline at end of file
+
+	#[test]
+	fn operations_the_unit_of_a_slot_cant_run_fault() {
+		let mut units = [UnitKind::Integer; ALU_COUNT];
+		units[3] = UnitKind::Fpu;
+		units[4] = UnitKind::MemoryPort;
+		let mul = AluOperation::Mul {
+			activation_input: 0, data_input_0: 1, data_input_1: 2, first_word_output: 3, second_word_output: None,
+			activation_output: None,
+		};
+		let read = AluOperation::ReadFromMem {
+			activation_input: 0, data_input_0: 1, data_output_0: 2, activation_output: None,
+		};
+		let fault = |program: Vec<Instruction>, data: Vec<Word>| {
+			let mut cpu = Cpu::new(program, data);
+			cpu.set_units(units);
+			match cpu.run(4) {
+				Err(SimulationError::ControllerFault { fault, .. })	=> Some(fault),
+				result												=> { result.unwrap(); None }
+			}
+		};
+		let unsupported = |alu, unit, kind| Some(ControllerFault::UnsupportedOperation { alu, unit, kind });
+
+		assert_eq!(
+			fault(vec![Instruction::SetAluConfig { alu_addr: 0, alu_config: mul }], vec![]),
+			unsupported(0, UnitKind::Integer, AluOpKind::Mul),
+		);
+		assert_eq!(
+			fault(
+				vec![Instruction::LoadConfigFrame { source_addr: 0, count: 2 }],
+				encode_config_frame(&[(4, read), (3, read)]).unwrap(),
+			),
+			unsupported(3, UnitKind::Fpu, AluOpKind::ReadFromMem),
+		);
+		assert_eq!(
+			fault(
+				vec![Instruction::BroadcastAluConfig { alu_config: not(0, 1, 2), alu_mask: 0b1_0011, register_stride: 0 }],
+				vec![],
+			),
+			unsupported(4, UnitKind::MemoryPort, AluOpKind::Not),
+		);
+		assert_eq!(
+			fault(vec![
+				Instruction::SetAluConfig { alu_addr: 4, alu_config: read },
+				Instruction::SetAluConfig { alu_addr: 1, alu_config: not(0, 1, 2) },
+				Instruction::ResetAll,
+			], vec![]),
+			None,
+		);
+	}
+}
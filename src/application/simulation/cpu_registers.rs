@@ -12,6 +12,40 @@ pub type CpuRegisterAddress = usize;
 pub const REGISTER_COUNT: CpuRegisterAddress = 64;
 pub type CpuRegisterBank = ComponentBank<CpuRegister, REGISTER_COUNT>;
 
+/// One bit per register, bit `n` standing for register `n`
+pub type RegisterMask = u64;
+const _: () = assert!(REGISTER_COUNT <= RegisterMask::BITS as usize);
+
+pub fn register_mask(registers: impl IntoIterator<Item=Option<CpuRegisterAddress>>) -> RegisterMask {
+    registers
+        .into_iter()
+        .flatten()
+        .fold(0, |mask, register| mask | (1 << register))
+}
+
+/// Remembers the register values seen at the previous step to report which ones changed since
+pub struct RegisterChangeTracker {
+    last_values : [Word; REGISTER_COUNT],
+}
+
+impl RegisterChangeTracker {
+    pub fn new(register_bank: &CpuRegisterBank) -> Self {
+        Self {
+            last_values: std::array::from_fn(|ix| register_bank.components[ix].value),
+        }
+    }
+    pub fn take_changed(&mut self, register_bank: &CpuRegisterBank) -> RegisterMask {
+        let mut changed = 0;
+        for (ix, (last, register)) in self.last_values.iter_mut().zip(register_bank.components.iter()).enumerate() {
+            if *last != register.value {
+                *last = register.value;
+                changed |= 1 << ix;
+            }
+        }
+        changed
+    }
+}
+
 impl CpuRegisterBank {
     pub fn new() -> Self{
         let registers = (0..REGISTER_COUNT).into_iter().map(|address|CpuRegister::new(address))
@@ -151,6 +185,12 @@ impl CpuRegisterDataReader {
             None
         }
     }
+    /// Stores the value read from the source register, if connected
+    pub fn latch(&mut self, new_value: Word) {
+        if let Connected{ value, .. } = self {
+            *value = Some(new_value);
+        }
+    }
     pub fn get_read_request(&mut self) -> Option<CpuRegisterReadRequest> {
         if let Connected{ source:source, value} = self{
             Some(CpuRegisterReadRequest{
@@ -201,6 +241,13 @@ impl CpuRegisterDataWriter{
         } else {
         }
     }
+    pub fn value(&self) -> Option<Word> {
+        if let CpuRegisterDataWriter::Connected { value, .. } = self {
+            *value
+        } else {
+            None
+        }
+    }
     pub fn get_write_request(&self) -> Option<CpuRegisterWriteRequest>{
         if let  CpuRegisterDataWriter::Connected {
                 target,
@@ -258,6 +305,9 @@ impl CpuRegisterActReader{
     pub fn get_read_request<'a>(&'a mut self) ->  Option<CpuRegisterReadRequest<'a>>{
        self.inner.get_read_request() 
     }
+    pub fn latch(&mut self, new_value: Word) {
+        self.inner.latch(new_value);
+    }
     pub fn read(&self) -> Option<bool>{
         self.inner.read().map(|val| val.to_bool())
     }
@@ -281,6 +331,9 @@ impl CpuRegisterActWriter {
     pub fn get_write_request(&self) -> Option<CpuRegisterWriteRequest>{
         self.inner.get_write_request()
     }
+    pub fn value(&self) -> Option<Word> {
+        self.inner.value()
+    }
     pub fn deactivate(&mut self){
         self.inner.deactivate();
     }
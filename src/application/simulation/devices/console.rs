@@ -0,0 +1,82 @@
+use std::io::Write;
+use crate::Step;
+use crate::word::Word;
+use super::{DeviceError, MemoryMappedDevice};
+
+pub const CONSOLE_CHAR_OFFSET   : usize = 0;
+pub const CONSOLE_NUMBER_OFFSET : usize = 1;
+
+/// Prints what gets written to it.
+///
+/// | offset | write                                        |
+/// |--------|----------------------------------------------|
+/// | 0      | the low byte of the word, as a character     |
+/// | 1      | the word as a decimal number and a newline   |
+///
+/// Reads return 0.
+pub struct ConsoleOutput {
+    output  : Box<dyn Write + Send>,
+}
+
+impl ConsoleOutput {
+    pub fn stdout() -> Self {
+        Self::new(Box::new(std::io::stdout()))
+    }
+    pub fn new(output: Box<dyn Write + Send>) -> Self {
+        Self { output }
+    }
+}
+
+impl MemoryMappedDevice for ConsoleOutput {
+    fn name(&self) -> &str {
+        "console output"
+    }
+
+    fn read(&mut self, _offset: usize, _cycle: Step) -> Word {
+        0
+    }
+
+    fn write(&mut self, offset: usize, value: Word, _cycle: Step) -> Result<(), DeviceError> {
+        // a closed output loses the text, like a disconnected terminal
+        let _ = match offset {
+            CONSOLE_CHAR_OFFSET     => self.output.write_all(&[value as u8]),
+            CONSOLE_NUMBER_OFFSET   => writeln!(self.output, "{}", value),
+            _                       => Ok(()),
+        };
+        let _ = self.output.flush();
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+    use super::*;
+
+    /// Collects what the console prints, for the test to look at
+    #[derive(Clone, Default)]
+    struct Printed(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Printed {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn prints_characters_and_numbers() {
+        let printed = Printed::default();
+        let mut console = ConsoleOutput::new(Box::new(printed.clone()));
+        console.write(CONSOLE_CHAR_OFFSET, 'h' as Word, 0).unwrap();
+        console.write(CONSOLE_CHAR_OFFSET, 0x100 + 'i' as Word, 1).unwrap();
+        console.write(CONSOLE_NUMBER_OFFSET, -42, 2).unwrap();
+        console.write(5, 7, 3).unwrap();
+        assert_eq!(String::from_utf8(printed.0.lock().unwrap().clone()).unwrap(), "hi-42\n");
+
+        assert_eq!(console.read(CONSOLE_CHAR_OFFSET, 4), 0);
+        assert!(!console.interrupt_requested(5));
+    }
+}
@@ -0,0 +1,98 @@
+use std::collections::VecDeque;
+use std::io::{BufReader, Read};
+use std::sync::mpsc::{channel, Receiver};
+use std::thread;
+use crate::Step;
+use crate::word::Word;
+use super::{DeviceError, MemoryMappedDevice};
+
+pub const KEYBOARD_DATA_OFFSET      : usize = 0;
+pub const KEYBOARD_STATUS_OFFSET    : usize = 1;
+
+/// Hands out the bytes of an input stream, read on a background thread so the simulation never
+/// blocks waiting for input.
+///
+/// | offset | read                                             |
+/// |--------|--------------------------------------------------|
+/// | 0      | the next byte, or -1 if none is available yet    |
+/// | 1      | how many bytes are available                     |
+///
+/// Writes are ignored. Mapped with an interrupt line, it requests interrupts while bytes are
+/// available.
+pub struct KeyboardInput {
+    incoming    : Receiver<u8>,
+    available   : VecDeque<u8>,
+}
+
+impl KeyboardInput {
+    pub fn stdin() -> Self {
+        Self::from_reader(std::io::stdin())
+    }
+
+    pub fn from_reader(reader: impl Read + Send + 'static) -> Self {
+        let (sender, incoming) = channel();
+        thread::spawn(move || {
+            for byte in BufReader::new(reader).bytes() {
+                let Ok(byte) = byte else { break };
+                if sender.send(byte).is_err() {
+                    break;
+                }
+            }
+        });
+        Self { incoming, available: VecDeque::new() }
+    }
+
+    fn receive(&mut self) {
+        self.available.extend(self.incoming.try_iter());
+    }
+}
+
+impl MemoryMappedDevice for KeyboardInput {
+    fn name(&self) -> &str {
+        "keyboard input"
+    }
+
+    fn read(&mut self, offset: usize, _cycle: Step) -> Word {
+        self.receive();
+        match offset {
+            KEYBOARD_DATA_OFFSET    => self.available.pop_front().map_or(-1, |byte| byte as Word),
+            KEYBOARD_STATUS_OFFSET  => self.available.len() as Word,
+            _                       => 0,
+        }
+    }
+
+    fn write(&mut self, _offset: usize, _value: Word, _cycle: Step) -> Result<(), DeviceError> {
+        Ok(())
+    }
+
+    fn interrupt_requested(&mut self, _cycle: Step) -> bool {
+        self.receive();
+        !self.available.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+    use std::time::{Duration, Instant};
+    use super::*;
+
+    #[test]
+    fn hands_out_the_bytes_read_and_interrupts_while_any_are_left() {
+        let mut keyboard = KeyboardInput::from_reader(Cursor::new(b"hi".to_vec()));
+        // the bytes arrive from a background thread
+        let start = Instant::now();
+        while keyboard.read(KEYBOARD_STATUS_OFFSET, 0) < 2 {
+            assert!(start.elapsed() < Duration::from_secs(10), "the input never arrived");
+            thread::yield_now();
+        }
+
+        assert!(keyboard.interrupt_requested(1));
+        keyboard.write(KEYBOARD_DATA_OFFSET, 'x' as Word, 1).unwrap();
+        assert_eq!(keyboard.read(KEYBOARD_DATA_OFFSET, 2), 'h' as Word);
+        assert_eq!(keyboard.read(KEYBOARD_STATUS_OFFSET, 2), 1);
+        assert_eq!(keyboard.read(KEYBOARD_DATA_OFFSET, 3), 'i' as Word);
+        assert_eq!(keyboard.read(KEYBOARD_DATA_OFFSET, 4), -1);
+        assert!(!keyboard.interrupt_requested(5));
+    }
+}
@@ -0,0 +1,112 @@
+pub mod console;
+pub mod timer;
+pub mod keyboard;
+
+pub use console::ConsoleOutput;
+pub use timer::CycleTimer;
+pub use keyboard::KeyboardInput;
+
+use std::fmt::{Display, Formatter};
+use std::ops::Range;
+use std::sync::Mutex;
+use crate::Step;
+use crate::application::simulation::interrupts::{InterruptLine, InterruptMask, INTERRUPT_LINE_COUNT};
+use crate::word::Word;
+
+/// Why a device refused a write, which faults the access
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum DeviceError {
+    /// The register at the offset can't hold the value written
+    InvalidValue,
+}
+
+impl Display for DeviceError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DeviceError::InvalidValue => write!(f, "invalid value"),
+        }
+    }
+}
+
+/// A peripheral answering the accesses to a range of main-memory addresses.
+///
+/// `offset` is relative to the start of the range the device was mapped at.
+pub trait MemoryMappedDevice: Send {
+    fn name(&self) -> &str;
+    fn read(&mut self, offset: usize, cycle: Step) -> Word;
+    fn write(&mut self, offset: usize, value: Word, cycle: Step) -> Result<(), DeviceError>;
+
+    /// Whether the device requests an interrupt in `cycle`. Only polled for devices mapped with
+    /// an interrupt line, once per cycle by every CPU on the memory.
+    fn interrupt_requested(&mut self, _cycle: Step) -> bool {
+        false
+    }
+}
+
+pub struct MappedDevice {
+    pub addr_range      : Range<usize>,
+    pub device          : Mutex<Box<dyn MemoryMappedDevice>>,
+    pub interrupt_line  : Option<InterruptLine>,
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum DeviceMappingError {
+    EmptyRange,
+    InterruptLineOutOfRange {
+        line        : InterruptLine,
+    },
+    Overlap {
+        device      : String,
+        addr_range  : Range<usize>,
+    },
+}
+
+#[derive(Default)]
+pub struct DeviceMap {
+    pub devices : Vec<MappedDevice>,
+}
+
+impl DeviceMap {
+    pub fn map(
+        &mut self,
+        addr_range      : Range<usize>,
+        device          : Box<dyn MemoryMappedDevice>,
+        interrupt_line  : Option<InterruptLine>,
+    ) -> Result<(), DeviceMappingError> {
+        if addr_range.is_empty() {
+            return Err(DeviceMappingError::EmptyRange);
+        }
+        if let Some(line) = interrupt_line && line >= INTERRUPT_LINE_COUNT {
+            return Err(DeviceMappingError::InterruptLineOutOfRange { line });
+        }
+        if let Some(mapped) = self.devices.iter().find(|mapped|
+            mapped.addr_range.start < addr_range.end && addr_range.start < mapped.addr_range.end
+        ) {
+            return Err(DeviceMappingError::Overlap {
+                device      : mapped.device.lock().unwrap().name().to_owned(),
+                addr_range  : mapped.addr_range.clone(),
+            });
+        }
+        self.devices.push(MappedDevice { addr_range, device: Mutex::new(device), interrupt_line });
+        Ok(())
+    }
+
+    /// Runs `access` on the device mapped at `addr` with the offset into its range, if any
+    pub fn with_device<R>(
+        &self,
+        addr    : usize,
+        access  : impl FnOnce(&mut dyn MemoryMappedDevice, usize) -> R,
+    ) -> Option<R> {
+        let mapped = self.devices.iter().find(|mapped| mapped.addr_range.contains(&addr))?;
+        let mut device = mapped.device.lock().unwrap();
+        Some(access(device.as_mut(), addr - mapped.addr_range.start))
+    }
+
+    /// The lines of the devices requesting an interrupt in `cycle`
+    pub fn poll_interrupts(&self, cycle: Step) -> InterruptMask {
+        self.devices.iter()
+            .filter_map(|mapped| mapped.interrupt_line.map(|line| (line, &mapped.device)))
+            .filter(|(_, device)| device.lock().unwrap().interrupt_requested(cycle))
+            .fold(0, |raised, (line, _)| raised | 1 << line)
+    }
+}
@@ -0,0 +1,111 @@
+use crate::Step;
+use crate::word::Word;
+use super::{DeviceError, MemoryMappedDevice};
+
+pub const TIMER_CYCLE_OFFSET    : usize = 0;
+pub const TIMER_ELAPSED_OFFSET  : usize = 1;
+pub const TIMER_PERIOD_OFFSET   : usize = 2;
+
+/// Exposes the simulation cycle.
+///
+/// | offset | read                                  | write                    |
+/// |--------|---------------------------------------|--------------------------|
+/// | 0      | the current cycle                     | ignored                  |
+/// | 1      | cycles elapsed since the last restart | restarts the stopwatch   |
+/// | 2      | the interrupt period                  | sets it, 0 disabling it  |
+///
+/// Writing a negative period, or one past `Step::MAX`, faults.
+/// Mapped with an interrupt line, it requests an interrupt every `period` cycles after the last
+/// restart.
+#[derive(Default)]
+pub struct CycleTimer {
+    started_at  : Step,
+    period      : Step,
+}
+
+impl CycleTimer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl MemoryMappedDevice for CycleTimer {
+    fn name(&self) -> &str {
+        "cycle timer"
+    }
+
+    fn read(&mut self, offset: usize, cycle: Step) -> Word {
+        match offset {
+            TIMER_CYCLE_OFFSET      => cycle as Word,
+            TIMER_ELAPSED_OFFSET    => cycle.wrapping_sub(self.started_at) as Word,
+            TIMER_PERIOD_OFFSET     => self.period as Word,
+            _                       => 0,
+        }
+    }
+
+    fn write(&mut self, offset: usize, value: Word, cycle: Step) -> Result<(), DeviceError> {
+        match offset {
+            TIMER_ELAPSED_OFFSET    => self.started_at = cycle,
+            TIMER_PERIOD_OFFSET     => self.period = Step::try_from(value).map_err(|_| DeviceError::InvalidValue)?,
+            _                       => {}
+        }
+        Ok(())
+    }
+
+    fn interrupt_requested(&mut self, cycle: Step) -> bool {
+        let elapsed = cycle.wrapping_sub(self.started_at);
+        self.period != 0 && elapsed != 0 && elapsed.is_multiple_of(self.period)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::application::simulation::error::{MemoryFault, MemoryFaultKind};
+    use crate::application::simulation::main_memory::MainMemory;
+    use crate::application::simulation::memory_timing::MemoryAccessKind;
+
+    #[test]
+    fn reads_cycles_and_restarts_the_stopwatch() {
+        let mut timer = CycleTimer::new();
+        assert_eq!(timer.read(TIMER_CYCLE_OFFSET, 7), 7);
+        assert_eq!(timer.read(TIMER_ELAPSED_OFFSET, 7), 7);
+        timer.write(TIMER_ELAPSED_OFFSET, 0, 5).unwrap();
+        assert_eq!(timer.read(TIMER_ELAPSED_OFFSET, 9), 4);
+        timer.write(TIMER_CYCLE_OFFSET, 100, 10).unwrap();
+        assert_eq!(timer.read(TIMER_CYCLE_OFFSET, 10), 10);
+    }
+
+    #[test]
+    fn requests_an_interrupt_every_period_after_the_restart() {
+        let mut timer = CycleTimer::new();
+        assert!((0..10).all(|cycle| !timer.interrupt_requested(cycle)));
+
+        timer.write(TIMER_PERIOD_OFFSET, 3, 0).unwrap();
+        timer.write(TIMER_ELAPSED_OFFSET, 0, 2).unwrap();
+        assert_eq!(timer.read(TIMER_PERIOD_OFFSET, 2), 3);
+        let requested = (2..12).filter(|cycle| timer.interrupt_requested(*cycle)).collect::<Vec<_>>();
+        assert_eq!(requested, vec![5, 8, 11]);
+
+        timer.write(TIMER_PERIOD_OFFSET, 0, 12).unwrap();
+        assert!(!timer.interrupt_requested(14));
+    }
+
+    #[test]
+    fn negative_periods_fault() {
+        let memory = MainMemory::new(vec![]);
+        memory.map_device(100..103, CycleTimer::new()).unwrap();
+        let mut io = memory.get_io();
+        io.write(100 + TIMER_PERIOD_OFFSET, 4, 0).unwrap();
+        assert_eq!(
+            io.write(100 + TIMER_PERIOD_OFFSET, -1, 1),
+            Err(MemoryFault {
+                kind    : MemoryFaultKind::Device(DeviceError::InvalidValue),
+                access  : MemoryAccessKind::Write,
+                addr    : 100 + TIMER_PERIOD_OFFSET,
+            }),
+        );
+        // the period the faulting write would have replaced holds
+        assert_eq!(io.read(100 + TIMER_PERIOD_OFFSET, 2), Ok(4));
+    }
+}
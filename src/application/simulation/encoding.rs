@@ -0,0 +1,292 @@
+use std::fmt::{Display, Formatter};
+use crate::application::simulation::alu::{AluAddress, AluOpKind, AluOperation, AluPortsConfig, CustomOpId};
+use crate::application::simulation::cpu_registers::CpuRegisterAddress;
+use crate::application::simulation::instruction::Instruction;
+use crate::application::simulation::scheduling::AluMask;
+use crate::word::Word;
+
+/// Words taken by every encoded instruction. Only ALUs below 32 fit the mask of an encoded
+/// `BroadcastAluConfig`.
+pub const ENCODED_INSTRUCTION_LEN: usize = 4;
+
+pub type EncodedInstruction = [Word; ENCODED_INSTRUCTION_LEN];
+
+/// Encoded instructions only survive memories of words at least this wide, narrower words
+/// truncating their header
+pub const MIN_ENCODED_WORD_BITS: u32 = 32;
+
+// the first word holds the opcode in bits 0..8, the operation kind of a `SetAluConfig` or
+// `BroadcastAluConfig` in bits 8..16 and the ALU or register the instruction targets in bits
+// 16..32, or the count of a `LoadInstructions` or `LoadConfigFrame`, or the register stride of a
+// `BroadcastAluConfig`. Ports take a byte each, 0xff standing for an unconnected one.
+const OPCODE_NOOP           : u32 = 0;
+const OPCODE_SET_ALU_CONFIG : u32 = 1;
+const OPCODE_SET_LITERAL    : u32 = 2;
+const OPCODE_WAIT           : u32 = 3;
+const OPCODE_JUMP           : u32 = 4;
+const OPCODE_RESET_ALL      : u32 = 5;
+const OPCODE_LOAD           : u32 = 6;
+const OPCODE_LOAD_FRAME     : u32 = 7;
+const OPCODE_BROADCAST      : u32 = 8;
+const OPCODE_RETURN         : u32 = 9;
+
+/// Stands for an unconnected port in a packed port word
+const NO_PORT: u32 = 0xff;
+
+/// The largest ALU, register, count or register stride the header holds
+const MAX_TARGET: usize = 0xffff;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum EncodeError {
+    /// The ALU, register or register stride doesn't fit the 16 bits of the header
+    TargetOutOfRange(usize),
+    /// The count of a `LoadInstructions` or `LoadConfigFrame` is negative or doesn't fit the 16
+    /// bits of the header
+    CountOutOfRange(Word),
+    /// A port doesn't fit its byte of a packed port word
+    PortOutOfRange(CpuRegisterAddress),
+    /// A `BroadcastAluConfig` selects ALUs past the 32 of the encoded mask
+    MaskOutOfRange(AluMask),
+}
+
+impl Display for EncodeError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            EncodeError::TargetOutOfRange(target)   => write!(f, "target {} doesn't fit 16 bits", target),
+            EncodeError::CountOutOfRange(count)     => write!(f, "count {} doesn't fit 16 unsigned bits", count),
+            EncodeError::PortOutOfRange(port)       => write!(f, "register {} can't be encoded as a port", port),
+            EncodeError::MaskOutOfRange(mask)       => write!(f, "ALU mask {:#x} selects ALUs past 31", mask),
+        }
+    }
+}
+
+impl std::error::Error for EncodeError {}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum DecodeError {
+    UnknownOpcode(u32),
+    UnknownAluOpKind(u32),
+    /// The ports of a `SetAluConfig` or `BroadcastAluConfig` don't fit its operation kind
+    InvalidPorts(AluOpKind),
+}
+
+impl Display for DecodeError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DecodeError::UnknownOpcode(opcode)  => write!(f, "unknown opcode {}", opcode),
+            DecodeError::UnknownAluOpKind(kind) => write!(f, "unknown ALU operation {}", kind),
+            DecodeError::InvalidPorts(kind)     => write!(f, "ports don't fit a {} operation", kind.name()),
+        }
+    }
+}
+
+impl std::error::Error for DecodeError {}
+
+fn header(opcode: u32, kind: u32, target: usize) -> Result<Word, EncodeError> {
+    if target > MAX_TARGET {
+        return Err(EncodeError::TargetOutOfRange(target));
+    }
+    Ok((opcode | kind << 8 | (target as u32) << 16) as Word)
+}
+
+fn count_target(count: Word) -> Result<usize, EncodeError> {
+    usize::try_from(count).ok()
+        .filter(|count| *count <= MAX_TARGET)
+        .ok_or(EncodeError::CountOutOfRange(count))
+}
+
+/// Packs up to 3 ports in the low 24 bits of a word
+fn pack_ports(ports: [Option<CpuRegisterAddress>; 3]) -> Result<Word, EncodeError> {
+    let mut word = 0_u32;
+    for (ix, port) in ports.iter().enumerate() {
+        let packed = match *port {
+            Some(port) if port >= NO_PORT as usize  => return Err(EncodeError::PortOutOfRange(port)),
+            Some(port)                              => port as u32,
+            None                                    => NO_PORT,
+        };
+        word |= packed << (ix * 8);
+    }
+    Ok(word as Word)
+}
+
+/// The id of a `Custom` operation takes the bits 24..32 of the input ports word
+fn custom_id_bits(alu_config: &AluOperation) -> Word {
+    ((alu_config.custom_id().unwrap_or(0) as u32) << 24) as Word
+}
+
+fn unpack_ports(word: Word) -> [Option<CpuRegisterAddress>; 3] {
+    std::array::from_fn(|ix| match (word as u32 >> (ix * 8)) & 0xff {
+        NO_PORT => None,
+        port    => Some(port as CpuRegisterAddress),
+    })
+}
+
+/// Encodes `instruction`, failing on the fields too wide for the encoding rather than
+/// truncating them, so that decoding always gives `instruction` back
+pub fn encode_instruction(instruction: &Instruction) -> Result<EncodedInstruction, EncodeError> {
+    Ok(match *instruction {
+        Instruction::NoOp => [header(OPCODE_NOOP, 0, 0)?, 0, 0, 0],
+        Instruction::SetAluConfig { alu_config, alu_addr } => {
+            let ports = alu_config.get_ports_config();
+            [
+                header(OPCODE_SET_ALU_CONFIG, alu_config.kind() as u32, alu_addr)?,
+                pack_ports([ports.data_input_0, ports.data_input_1, ports.activation_input])? | custom_id_bits(&alu_config),
+                pack_ports([ports.data_output_0, ports.data_output_1, ports.activation_output])?,
+                0,
+            ]
+        }
+        Instruction::BroadcastAluConfig { alu_config, alu_mask, register_stride } => {
+            let ports = alu_config.get_ports_config();
+            let encoded_mask = u32::try_from(alu_mask).map_err(|_| EncodeError::MaskOutOfRange(alu_mask))?;
+            [
+                header(OPCODE_BROADCAST, alu_config.kind() as u32, register_stride)?,
+                pack_ports([ports.data_input_0, ports.data_input_1, ports.activation_input])? | custom_id_bits(&alu_config),
+                pack_ports([ports.data_output_0, ports.data_output_1, ports.activation_output])?,
+                encoded_mask as Word,
+            ]
+        }
+        Instruction::SetLiteral { literal, register } =>
+            [header(OPCODE_SET_LITERAL, 0, register)?, literal, 0, 0],
+        Instruction::WaitForActivationSignal { register_index } =>
+            [header(OPCODE_WAIT, 0, register_index)?, 0, 0, 0],
+        Instruction::Jump { addr } =>
+            [header(OPCODE_JUMP, 0, 0)?, addr, 0, 0],
+        Instruction::ResetAll =>
+            [header(OPCODE_RESET_ALL, 0, 0)?, 0, 0, 0],
+        Instruction::ReturnFromInterrupt =>
+            [header(OPCODE_RETURN, 0, 0)?, 0, 0, 0],
+        Instruction::LoadInstructions { source_addr, target_addr, count } =>
+            [header(OPCODE_LOAD, 0, count_target(count)?)?, source_addr, target_addr, 0],
+        Instruction::LoadConfigFrame { source_addr, count } =>
+            [header(OPCODE_LOAD_FRAME, 0, count_target(count)?)?, source_addr, 0, 0],
+    })
+}
+
+fn decode_alu_config(kind: u32, encoded: &EncodedInstruction) -> Result<AluOperation, DecodeError> {
+    let kind = AluOpKind::from_index(kind as usize).ok_or(DecodeError::UnknownAluOpKind(kind))?;
+    let [data_input_0, data_input_1, activation_input] = unpack_ports(encoded[1]);
+    let [data_output_0, data_output_1, activation_output] = unpack_ports(encoded[2]);
+    let ports = AluPortsConfig {
+        data_input_0, data_input_1, activation_input,
+        data_output_0, data_output_1, activation_output,
+    };
+    AluOperation::from_ports_config(kind, &ports)
+        .map(|alu_config| alu_config.with_custom_id(Some((encoded[1] as u32 >> 24) as CustomOpId)))
+        .ok_or(DecodeError::InvalidPorts(kind))
+}
+
+pub fn decode_instruction(encoded: &EncodedInstruction) -> Result<Instruction, DecodeError> {
+    let header = encoded[0] as u32;
+    let opcode = header & 0xff;
+    let kind = (header >> 8) & 0xff;
+    let target = (header >> 16) as usize;
+
+    Ok(match opcode {
+        OPCODE_NOOP => Instruction::NoOp,
+        OPCODE_SET_ALU_CONFIG => Instruction::SetAluConfig {
+            alu_config  : decode_alu_config(kind, encoded)?,
+            alu_addr    : target,
+        },
+        OPCODE_BROADCAST => Instruction::BroadcastAluConfig {
+            alu_config      : decode_alu_config(kind, encoded)?,
+            alu_mask        : encoded[3] as u32 as AluMask,
+            register_stride : target,
+        },
+        OPCODE_SET_LITERAL  => Instruction::SetLiteral { literal: encoded[1], register: target },
+        OPCODE_WAIT         => Instruction::WaitForActivationSignal { register_index: target },
+        OPCODE_JUMP         => Instruction::Jump { addr: encoded[1] },
+        OPCODE_RESET_ALL    => Instruction::ResetAll,
+        OPCODE_RETURN       => Instruction::ReturnFromInterrupt,
+        OPCODE_LOAD         => Instruction::LoadInstructions {
+            source_addr : encoded[1],
+            target_addr : encoded[2],
+            count       : target as Word,
+        },
+        OPCODE_LOAD_FRAME   => Instruction::LoadConfigFrame {
+            source_addr : encoded[1],
+            count       : target as Word,
+        },
+        opcode              => return Err(DecodeError::UnknownOpcode(opcode)),
+    })
+}
+
+/// Encodes the configurations a `LoadConfigFrame` applies, failing with the index of the first
+/// one that can't be encoded
+pub fn encode_config_frame(frame: &[(AluAddress, AluOperation)]) -> Result<Vec<Word>, (usize, EncodeError)> {
+    let entries = frame.iter()
+        .map(|(alu_addr, alu_config)| Instruction::SetAluConfig { alu_config: *alu_config, alu_addr: *alu_addr })
+        .collect::<Vec<_>>();
+    encode_program(&entries)
+}
+
+/// Encodes whole instructions, failing with the index of the first one that can't be encoded
+pub fn encode_program(program: &[Instruction]) -> Result<Vec<Word>, (usize, EncodeError)> {
+    let mut words = Vec::with_capacity(program.len() * ENCODED_INSTRUCTION_LEN);
+    for (ix, instruction) in program.iter().enumerate() {
+        words.extend(encode_instruction(instruction).map_err(|error| (ix, error))?);
+    }
+    Ok(words)
+}
+
+/// Decodes whole instructions, failing with the index of the first invalid one. Trailing words
+/// that don't make a whole instruction are ignored.
+pub fn decode_program(words: &[Word]) -> Result<Vec<Instruction>, (usize, DecodeError)> {
+    words.chunks_exact(ENCODED_INSTRUCTION_LEN)
+        .enumerate()
+        .map(|(ix, encoded)| decode_instruction(encoded.try_into().unwrap()).map_err(|error| (ix, error)))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn not(data_input: CpuRegisterAddress) -> AluOperation {
+        AluOperation::Not { activation_input: 0, data_input, data_output: 1, activation_output: None }
+    }
+
+    #[test]
+    fn the_widest_fields_round_trip() {
+        let program = [
+            Instruction::SetLiteral { literal: Word::MIN, register: MAX_TARGET },
+            Instruction::SetAluConfig { alu_config: not(254), alu_addr: MAX_TARGET },
+            Instruction::BroadcastAluConfig { alu_config: not(0), alu_mask: u32::MAX as AluMask, register_stride: MAX_TARGET },
+            Instruction::LoadInstructions { source_addr: -1, target_addr: Word::MAX, count: MAX_TARGET as Word },
+            Instruction::LoadConfigFrame { source_addr: 0, count: 0 },
+        ];
+        assert_eq!(decode_program(&encode_program(&program).unwrap()).unwrap(), program);
+    }
+
+    #[test]
+    fn fields_too_wide_for_the_encoding_are_rejected() {
+        let encode = |instruction| encode_instruction(&instruction).map(|_| ());
+        assert_eq!(
+            encode(Instruction::WaitForActivationSignal { register_index: MAX_TARGET + 1 }),
+            Err(EncodeError::TargetOutOfRange(MAX_TARGET + 1)),
+        );
+        assert_eq!(
+            encode(Instruction::BroadcastAluConfig { alu_config: not(0), alu_mask: 1, register_stride: 1 << 16 }),
+            Err(EncodeError::TargetOutOfRange(1 << 16)),
+        );
+        assert_eq!(
+            encode(Instruction::BroadcastAluConfig { alu_config: not(0), alu_mask: 1 << 32, register_stride: 0 }),
+            Err(EncodeError::MaskOutOfRange(1 << 32)),
+        );
+        assert_eq!(
+            encode(Instruction::SetAluConfig { alu_config: not(255), alu_addr: 0 }),
+            Err(EncodeError::PortOutOfRange(255)),
+        );
+        assert_eq!(
+            encode(Instruction::LoadInstructions { source_addr: 0, target_addr: 0, count: -1 }),
+            Err(EncodeError::CountOutOfRange(-1)),
+        );
+        assert_eq!(
+            encode(Instruction::LoadConfigFrame { source_addr: 0, count: 1 << 16 }),
+            Err(EncodeError::CountOutOfRange(1 << 16)),
+        );
+        assert_eq!(
+            encode_program(&[Instruction::NoOp, Instruction::SetLiteral { literal: 0, register: 1 << 20 }]),
+            Err((1, EncodeError::TargetOutOfRange(1 << 20))),
+        );
+    }
+}
@@ -0,0 +1,187 @@
+use std::fmt::{Display, Formatter};
+use crate::Step;
+use crate::application::simulation::alu::{AluAddress, AluOpKind, CustomOpId};
+use crate::application::simulation::cpu_registers::CpuRegisterAddress;
+use crate::application::simulation::devices::DeviceError;
+use crate::application::simulation::encoding::DecodeError;
+use crate::application::simulation::machine::UnitKind;
+use crate::application::simulation::memory_timing::MemoryAccessKind;
+use crate::application::simulation::scheduling::{mask_bits, AluMask};
+use crate::word::{Word, WordWidth};
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum MemoryFaultKind {
+    /// Neither a memory cell nor a mapped device lives at the address
+    OutOfRange,
+    /// The address lies in a no-access region
+    NoAccess,
+    /// A write to a read-only region
+    ReadOnly,
+    /// The device mapped at the address refused the write
+    Device(DeviceError),
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct MemoryFault {
+    pub kind    : MemoryFaultKind,
+    pub access  : MemoryAccessKind,
+    pub addr    : usize,
+}
+
+impl Display for MemoryFault {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let reason = match self.kind {
+            MemoryFaultKind::OutOfRange     => "out of range".to_owned(),
+            MemoryFaultKind::NoAccess       => "in a no-access region".to_owned(),
+            MemoryFaultKind::ReadOnly       => "in a read-only region".to_owned(),
+            MemoryFaultKind::Device(error)  => format!("mapped to a device that refused it: {}", error),
+        };
+        write!(f, "{:?} access to address {:#x}, which is {}", self.access, self.addr, reason)
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ControllerFault {
+    Memory(MemoryFault),
+    /// The words loaded from `addr` don't encode an instruction
+    Decode {
+        addr    : usize,
+        error   : DecodeError,
+    },
+    /// An instruction was loaded past the end of the instruction memory
+    InstructionAddressOutOfRange {
+        addr    : usize,
+    },
+    /// The instruction loaded from `addr` names an ALU or a register past the last one
+    InstructionOutOfRange {
+        addr    : usize,
+    },
+    /// A configuration frame entry isn't a `SetAluConfig` of an existing ALU wired to existing
+    /// registers
+    InvalidConfigFrameEntry {
+        addr    : usize,
+    },
+    /// A broadcast would wire a port of `alu` past the last register
+    BroadcastRegisterOutOfRange {
+        alu     : AluAddress,
+    },
+    /// The unit of `alu` can't run operations of kind `kind`
+    UnsupportedOperation {
+        alu     : AluAddress,
+        unit    : UnitKind,
+        kind    : AluOpKind,
+    },
+    /// `alu` was configured with an operation of kind `kind`, which can't run on words of `width`
+    OperationNeedsWiderWords {
+        alu     : AluAddress,
+        kind    : AluOpKind,
+        width   : WordWidth,
+    },
+    /// `alu` was configured with a custom operation the CPU wasn't built with
+    UnknownCustomOperation {
+        alu     : AluAddress,
+        id      : CustomOpId,
+    },
+    /// A `ReturnFromInterrupt` outside an interrupt handler
+    ReturnOutsideInterrupt,
+    /// The program counter is negative or past the end of the program, which it may only reach
+    ProgramCounterOutOfRange {
+        pc      : Word,
+    },
+}
+
+impl Display for ControllerFault {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ControllerFault::Memory(fault) =>
+                write!(f, "{}", fault),
+            ControllerFault::Decode { addr, error } =>
+                write!(f, "invalid instruction at address {:#x}: {}", addr, error),
+            ControllerFault::InstructionAddressOutOfRange { addr } =>
+                write!(f, "instruction address {:#x} is past the end of the instruction memory", addr),
+            ControllerFault::InstructionOutOfRange { addr } =>
+                write!(f, "the instruction at address {:#x} names an ALU or register past the last one", addr),
+            ControllerFault::InvalidConfigFrameEntry { addr } =>
+                write!(f, "the configuration frame entry at address {:#x} doesn't configure an ALU", addr),
+            ControllerFault::BroadcastRegisterOutOfRange { alu } =>
+                write!(f, "the broadcast configuration of ALU {} uses registers past the last one", alu),
+            ControllerFault::UnsupportedOperation { alu, unit, kind } =>
+                write!(f, "the {} unit of ALU {} can't run {}", unit.name(), alu, kind.name()),
+            ControllerFault::OperationNeedsWiderWords { alu, kind, width } =>
+                write!(f, "ALU {} can't run {} on {}-bit words", alu, kind.name(), width.bits()),
+            ControllerFault::UnknownCustomOperation { alu, id } =>
+                write!(f, "ALU {} was configured with unknown custom operation {}", alu, id),
+            ControllerFault::ReturnOutsideInterrupt =>
+                write!(f, "returned from an interrupt outside an interrupt handler"),
+            ControllerFault::ProgramCounterOutOfRange { pc } =>
+                write!(f, "program counter {} is out of the program", pc),
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum SimulationError {
+    MemoryFault {
+        alu     : AluAddress,
+        cycle   : Step,
+        fault   : MemoryFault,
+    },
+    ControllerFault {
+        cycle   : Step,
+        fault   : ControllerFault,
+    },
+    /// An ALU was configured with a custom operation the CPU wasn't built with
+    UnknownCustomOperation {
+        alu     : AluAddress,
+        cycle   : Step,
+        id      : CustomOpId,
+    },
+    /// Combinational ALUs kept changing each other's inputs without settling
+    CombinationalLoop {
+        cycle   : Step,
+        alus    : AluMask,
+    },
+    /// Combinational ALUs drove the same register with different values
+    WriteConflict {
+        cycle       : Step,
+        register    : CpuRegisterAddress,
+        alus        : AluMask,
+    },
+}
+
+impl SimulationError {
+    /// The ALU that faulted, if the fault came from an ALU
+    pub fn alu(&self) -> Option<AluAddress> {
+        match self {
+            SimulationError::MemoryFault { alu, .. }            => Some(*alu),
+            SimulationError::UnknownCustomOperation { alu, .. } => Some(*alu),
+            SimulationError::ControllerFault { .. }             => None,
+            SimulationError::CombinationalLoop { .. }           => None,
+            SimulationError::WriteConflict { .. }               => None,
+        }
+    }
+}
+
+impl Display for SimulationError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SimulationError::MemoryFault { alu, cycle, fault } =>
+                write!(f, "memory fault on ALU {} at cycle {}: {}", alu, cycle, fault),
+            SimulationError::ControllerFault { cycle, fault } =>
+                write!(f, "controller fault at cycle {}: {}", cycle, fault),
+            SimulationError::UnknownCustomOperation { alu, cycle, id } =>
+                write!(f, "ALU {} runs unknown custom operation {} at cycle {}", alu, id, cycle),
+            SimulationError::CombinationalLoop { cycle, alus } =>
+                write!(f, "combinational loop through ALUs {:?} at cycle {}", mask_bits(*alus).collect::<Vec<_>>(), cycle),
+            SimulationError::WriteConflict { cycle, register, alus } => write!(
+                f,
+                "ALUs {:?} drive register {} with different values at cycle {}",
+                mask_bits(*alus).collect::<Vec<_>>(),
+                register,
+                cycle,
+            ),
+        }
+    }
+}
+
+impl std::error::Error for SimulationError {}
@@ -1,5 +1,6 @@
 use crate::application::simulation::cpu_registers::CpuRegisterAddress;
 use crate::application::simulation::alu::AluOperation;
+use crate::application::simulation::scheduling::{alu_range_mask, AluMask};
 use crate::word::Word;
 
 pub const CONTROLLER_INSTRUCTION_SIZE   		: usize = 64;
@@ -36,10 +37,63 @@ pub enum Instruction {
     
     ResetAll,
 
+    /// Copies `count` encoded instructions from main memory, starting at `source_addr`, to
+    /// instruction memory starting at `target_addr`, one instruction per cycle. Encoded, `count`
+    /// is limited to 16 bits.
+    LoadInstructions{
+        source_addr     : Word,
+        target_addr     : Word,
+        count           : Word,
+    },
+
+    /// Configures the ALUs of the frame of `count` encoded `SetAluConfig`s at `source_addr` in
+    /// main memory, all in one cycle. Encoded, `count` is limited to 16 bits.
+    LoadConfigFrame{
+        source_addr     : Word,
+        count           : Word,
+    },
+
+    /// Configures every ALU in `alu_mask` with `alu_config`, the ports of the `n`th selected ALU
+    /// moved `n * register_stride` registers up. Encoded, `register_stride` is limited to 16 bits.
+    BroadcastAluConfig{
+        alu_config      : AluOperation,
+        alu_mask        : AluMask,
+        register_stride : usize,
+    },
+
+    /// Ends an interrupt handler, jumping back to the instruction the interrupt was taken at
+    ReturnFromInterrupt,
+
     #[default]
     NoOp,
 }
 
+impl Instruction {
+    /// Whether every ALU and register the instruction names exists on a machine with `alu_count`
+    /// ALUs and `register_count` registers, including the registers of every broadcast lane
+    pub fn in_range(&self, alu_count: usize, register_count: usize) -> bool {
+        match self {
+            Instruction::SetAluConfig { alu_config, alu_addr } =>
+                *alu_addr < alu_count && alu_config.offset_registers(0, register_count).is_some(),
+            Instruction::BroadcastAluConfig { alu_config, alu_mask, register_stride } => {
+                let lanes = alu_mask.count_ones() as usize;
+                *alu_mask & !alu_range_mask(0..alu_count) == 0
+                    && (lanes == 0 || register_stride.checked_mul(lanes - 1)
+                        .and_then(|offset| alu_config.offset_registers(offset, register_count))
+                        .is_some())
+            }
+            Instruction::SetLiteral { register, .. }                => *register < register_count,
+            Instruction::WaitForActivationSignal { register_index } => *register_index < register_count,
+            Instruction::Jump { .. }
+            | Instruction::ResetAll
+            | Instruction::ReturnFromInterrupt
+            | Instruction::NoOp
+            | Instruction::LoadInstructions { .. }
+            | Instruction::LoadConfigFrame { .. }                   => true,
+        }
+    }
+}
+
 #[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
 pub enum HorizontalDir{
     Left,
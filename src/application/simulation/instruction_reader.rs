@@ -1,26 +1,53 @@
-use std::ops::Deref;
-use std::sync::Arc;
-use crate::{Step,  PROGRAM_COUNTER_REGISTER_ADDR};
+use std::sync::{Arc, RwLock};
+use crate::PROGRAM_COUNTER_REGISTER_ADDR;
 use crate::application::simulation::cpu_registers::{CpuRegisterDataReader, CpuRegisterDataWriter, };
 use crate::application::simulation::instruction::Instruction;
-use crate::application::simulation::main_memory::MainMemory;
-use crate::application::simulation::cpu_registers::CpuRegisterBank;
-use crate::application::simulation::main_memory::MainMemoryIo;
+use crate::application::simulation::error::ControllerFault;
+use crate::word::{Word, WordWidth};
 
 #[derive(Debug, Ord, PartialOrd, Eq, PartialEq, Clone, Copy)]
 pub enum IncrementCmd{
 	NoIncrement,
 	Increment,
-	GoTo(i32),
+	GoTo(Word),
 }
 
+/// Cloning hands out another handle to the same memory, so what the controller writes to it is
+/// seen by every handle
+#[derive(Clone)]
 pub struct InstructionMemory(
-	pub Arc<Vec<Instruction>>,
+	pub Arc<RwLock<Vec<Instruction>>>,
 );
 
 impl InstructionMemory{
 	pub fn new(program: Vec<Instruction>) -> Self {
-		Self(Arc::new(program))
+		Self(Arc::new(RwLock::new(program)))
+	}
+
+	pub fn read(&self, addr: usize) -> Option<Instruction> {
+		self.0.read().unwrap().get(addr).copied()
+	}
+
+	pub fn len(&self) -> usize {
+		self.0.read().unwrap().len()
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.len() == 0
+	}
+
+	/// Overwrites the instruction at `addr`, or appends it if `addr` is the length of the memory.
+	/// Returns false for addresses further out.
+	pub fn write(&self, addr: usize, instruction: Instruction) -> bool {
+		let mut instructions = self.0.write().unwrap();
+		if addr < instructions.len() {
+			instructions[addr] = instruction;
+		} else if addr == instructions.len() {
+			instructions.push(instruction);
+		} else {
+			return false;
+		}
+		true
 	}
 }
 
@@ -28,7 +55,7 @@ pub struct InstructionReader{
 	pub program_counter_reader	: CpuRegisterDataReader,
 	pub program_counter_writer  : CpuRegisterDataWriter,
 	increment_cmd				: IncrementCmd,
-	instruction_memory			: Arc<Vec<Instruction>>,
+	instruction_memory			: InstructionMemory,
 }
 
 impl InstructionReader{
@@ -36,10 +63,11 @@ impl InstructionReader{
 		instruction_memory	: &InstructionMemory,
 	) -> InstructionReader {
 		Self {
-			instruction_memory		: instruction_memory.0.clone(),
+			instruction_memory		: instruction_memory.clone(),
 			program_counter_reader	: CpuRegisterDataReader::Connected {source:
 			PROGRAM_COUNTER_REGISTER_ADDR, value: None},
-			program_counter_writer	: CpuRegisterDataWriter::Deactivated,
+			program_counter_writer	: CpuRegisterDataWriter::Connected {target:
+			PROGRAM_COUNTER_REGISTER_ADDR, value: None},
 			increment_cmd			: IncrementCmd::Increment,
 		}
 	}
@@ -50,21 +78,84 @@ impl InstructionReader{
 		self.increment_cmd = cmd;
 	}
 
-	pub fn read<'a>(&'a self) -> Option<impl Deref<Target=Instruction> + 'a>{
-		let addr = self.program_counter_reader.read().unwrap() as usize;
-		Some(self.instruction_memory.get(addr).unwrap())
+	/// The instruction at the program counter, `None` once the program counter reached the end
+	/// of the program. Fails for a program counter further out.
+	pub fn read(&self) -> Result<Option<Instruction>, ControllerFault>{
+		let pc = self.program_counter_reader.read().unwrap();
+		match usize::try_from(pc) {
+			Ok(addr) if addr <= self.instruction_memory.len() => Ok(self.instruction_memory.read(addr)),
+			_ => Err(ControllerFault::ProgramCounterOutOfRange { pc }),
+		}
 	}
 
-	pub fn step(&mut self) {
+	/// Moves the program counter as commanded, wrapping it to `word_width` like any register
+	pub fn step(&mut self, word_width: WordWidth) {
 		match self.increment_cmd {
 		    IncrementCmd::Increment => {
 				let current_pc = self.program_counter_reader.read().unwrap() ;
-				self.program_counter_writer.write(current_pc+1);
+				self.program_counter_writer.write(word_width.wrap(current_pc+1));
 			},
 			IncrementCmd::NoIncrement => {},
 			IncrementCmd::GoTo(new_pc) => {
-				self.program_counter_writer.write(new_pc );
+				self.program_counter_writer.write(word_width.wrap(new_pc));
 			}
 		}
 	}
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+	use crate::application::simulation::error::{ControllerFault, SimulationError};
+	use crate::application::simulation::instruction::Instruction;
+	use crate::application::simulation::simulation::Cpu;
+	use crate::word::WordWidth;
+	use crate::PROGRAM_COUNTER_REGISTER_ADDR;
+
+	fn pc_fault(program: Vec<Instruction>) -> Option<ControllerFault> {
+		match Cpu::new(program, vec![]).run(10) {
+			Err(SimulationError::ControllerFault { fault, .. })	=> Some(fault),
+			result												=> { result.unwrap(); None }
+		}
+	}
+
+	#[test]
+	fn the_program_counter_advances_and_faults_outside_the_program() {
+		let mut cpu = Cpu::new(vec![Instruction::NoOp, Instruction::NoOp], vec![]);
+		cpu.execute().unwrap();
+		assert_eq!(cpu.register_bank.components[PROGRAM_COUNTER_REGISTER_ADDR].value, 1);
+
+		// the end of the program halts it
+		assert_eq!(pc_fault(vec![Instruction::NoOp, Instruction::Jump { addr: 2 }]), None);
+		assert_eq!(
+			pc_fault(vec![Instruction::NoOp, Instruction::Jump { addr: 3 }]),
+			Some(ControllerFault::ProgramCounterOutOfRange { pc: 3 }),
+		);
+		assert_eq!(
+			pc_fault(vec![Instruction::Jump { addr: -1 }]),
+			Some(ControllerFault::ProgramCounterOutOfRange { pc: -1 }),
+		);
+	}
+
+	#[test]
+	fn the_program_counter_wraps_to_the_word_width() {
+		let run = |program: Vec<Instruction>| {
+			let mut cpu = Cpu::new(program, vec![]);
+			cpu.set_word_width(WordWidth::W8);
+			match cpu.run(200) {
+				Err(SimulationError::ControllerFault { fault, .. })	=> Some(fault),
+				result												=> { result.unwrap(); None }
+			}
+		};
+		assert_eq!(
+			run(vec![Instruction::NoOp; 128]),
+			Some(ControllerFault::ProgramCounterOutOfRange { pc: -0x80 }),
+		);
+		assert_eq!(
+			run(vec![Instruction::Jump { addr: 0x101 }, Instruction::NoOp]),
+			None,
+		);
+		assert_eq!(
+			run(vec![Instruction::Jump { addr: 0xff }]),
+			Some(ControllerFault::ProgramCounterOutOfRange { pc: -1 }),
+		);
+	}
+}
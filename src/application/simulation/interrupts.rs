@@ -0,0 +1,177 @@
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU16, Ordering};
+use crate::application::simulation::cpu_registers::{CpuRegisterAddress, RegisterMask, REGISTER_COUNT};
+
+pub const INTERRUPT_LINE_COUNT: usize = 16;
+
+pub type InterruptLine = usize;
+/// One bit per interrupt line
+pub type InterruptMask = u16;
+
+/// What the CPU does when an interrupt line is raised
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default, Hash)]
+pub enum InterruptAction {
+    /// The line is ignored
+    #[default]
+    Masked,
+    /// Sets the activation register, waking a controller or ALUs waiting on it
+    Activate {
+        register    : CpuRegisterAddress,
+    },
+    /// Saves the program counter and jumps to the instruction at `handler`, which returns with
+    /// `ReturnFromInterrupt`. Handlers don't nest: the line stays pending while another handler
+    /// runs, and raising it again before it's taken doesn't queue a second call.
+    Vector {
+        handler     : usize,
+    },
+}
+
+/// The lines the host raises. Cloning hands out another handle to the same lines, e.g. for
+/// another thread.
+#[derive(Clone, Default, Debug)]
+pub struct InterruptLines(Arc<AtomicU16>);
+
+impl InterruptLines {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Lines past the last one are ignored
+    pub fn raise(&self, line: InterruptLine) {
+        if line < INTERRUPT_LINE_COUNT {
+            self.0.fetch_or(1 << line, Ordering::AcqRel);
+        }
+    }
+
+    /// The lines raised since the last call
+    pub fn take(&self) -> InterruptMask {
+        self.0.swap(0, Ordering::AcqRel)
+    }
+}
+
+/// Routes the raised lines of a CPU to their actions
+#[derive(Clone, Default, Debug)]
+pub struct InterruptController {
+    pub actions : [InterruptAction; INTERRUPT_LINE_COUNT],
+    pub lines   : InterruptLines,
+    /// Raised vectored lines not taken yet
+    pending     : InterruptMask,
+}
+
+impl InterruptController {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Latches the vectored lines of `raised`, returning the registers the others activate.
+    /// Registers past the last one are left out. A line already pending stays latched once.
+    pub fn raise(&mut self, raised: InterruptMask) -> RegisterMask {
+        let mut activated = 0;
+        for line in (0..INTERRUPT_LINE_COUNT).filter(|line| raised & 1 << line != 0) {
+            match self.actions[line] {
+                InterruptAction::Masked                 => {}
+                InterruptAction::Activate { register }  => {
+                    if register < REGISTER_COUNT {
+                        activated |= 1 << register;
+                    }
+                }
+                InterruptAction::Vector { .. }          => self.pending |= 1 << line,
+            }
+        }
+        activated
+    }
+
+    /// Takes the pending vectored line with the lowest number, returning its handler
+    pub fn take_vector(&mut self) -> Option<usize> {
+        let line = self.pending.trailing_zeros() as usize;
+        if line >= INTERRUPT_LINE_COUNT {
+            return None;
+        }
+        self.pending &= !(1 << line);
+        match self.actions[line] {
+            InterruptAction::Vector { handler } => Some(handler),
+            // the line was reassigned since it was raised
+            _                                   => self.take_vector(),
+        }
+    }
+
+    pub fn pending(&self) -> InterruptMask {
+        self.pending
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::application::simulation::instruction::Instruction;
+    use crate::application::simulation::simulation::Cpu;
+    use crate::PROGRAM_COUNTER_REGISTER_ADDR;
+
+    /// Waits on register 5, with a handler setting register 7 at 1 and one setting register 8 at 3
+    fn waiting_program() -> Vec<Instruction> {
+        vec![
+            Instruction::Jump { addr: 5 },
+            Instruction::SetLiteral { literal: 1, register: 7 },
+            Instruction::ReturnFromInterrupt,
+            Instruction::SetLiteral { literal: 1, register: 8 },
+            Instruction::ReturnFromInterrupt,
+            Instruction::WaitForActivationSignal { register_index: 5 },
+            Instruction::SetLiteral { literal: 1, register: 6 },
+        ]
+    }
+
+    #[test]
+    fn lines_are_masked_activate_registers_or_vector_to_handlers() {
+        let mut cpu = Cpu::new(waiting_program(), vec![]);
+        cpu.interrupts.actions[1] = InterruptAction::Activate { register: 5 };
+        cpu.interrupts.actions[2] = InterruptAction::Vector { handler: 1 };
+        let lines = cpu.interrupt_lines();
+        cpu.run(5).unwrap();
+
+        lines.raise(0);
+        cpu.run(5).unwrap();
+        assert_eq!(cpu.register_bank.components[PROGRAM_COUNTER_REGISTER_ADDR].value, 5);
+        assert_eq!(cpu.controller.counters.interrupts_taken, 0);
+
+        // the interrupted wait is executed again once the handler returns
+        lines.raise(2);
+        cpu.execute().unwrap();
+        assert_eq!(cpu.controller.saved_pc(), Some(5));
+        cpu.run(5).unwrap();
+        assert_eq!(cpu.controller.saved_pc(), None);
+        assert_eq!(cpu.register_bank.components[PROGRAM_COUNTER_REGISTER_ADDR].value, 5);
+        assert_eq!(cpu.register_bank.components[7].value, 1);
+        assert_eq!(cpu.register_bank.components[6].value, 0);
+
+        lines.raise(1);
+        assert!(cpu.run(10).unwrap() < 10);
+        assert_eq!(cpu.register_bank.components[6].value, 1);
+        assert_eq!(cpu.controller.counters.interrupts_taken, 1);
+    }
+
+    #[test]
+    fn lines_raised_in_a_handler_stay_pending_until_it_returns() {
+        let mut cpu = Cpu::new(waiting_program(), vec![]);
+        cpu.interrupts.actions[2] = InterruptAction::Vector { handler: 1 };
+        cpu.interrupts.actions[3] = InterruptAction::Vector { handler: 3 };
+        let lines = cpu.interrupt_lines();
+        cpu.run(5).unwrap();
+
+        lines.raise(2);
+        cpu.execute().unwrap();
+        lines.raise(3);
+        lines.raise(2);
+        cpu.execute().unwrap();
+        assert_eq!(cpu.controller.saved_pc(), Some(5));
+        lines.raise(2);
+        cpu.execute().unwrap();
+        assert_eq!(cpu.interrupts.pending(), 0b1100);
+
+        // the lowest line is taken first, and the line raised twice calls its handler once
+        cpu.run(20).unwrap();
+        assert_eq!(cpu.interrupts.pending(), 0);
+        assert_eq!(cpu.controller.counters.interrupts_taken, 3);
+        assert_eq!(cpu.register_bank.components[8].value, 1);
+        assert_eq!(cpu.register_bank.components[PROGRAM_COUNTER_REGISTER_ADDR].value, 5);
+    }
+}
@@ -0,0 +1,166 @@
+use std::fmt::{Display, Formatter};
+use crate::PROGRAM_COUNTER_REGISTER_ADDR;
+use crate::application::simulation::alu::{AluAddress, AluOpKind, ALU_COUNT};
+use crate::application::simulation::cpu_registers::{CpuRegisterAddress, REGISTER_COUNT};
+use crate::word::WordWidth;
+
+/// The type of function unit in an ALU slot, limiting the operations it can be configured with
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default, Hash)]
+pub enum UnitKind {
+    /// Runs every operation
+    #[default]
+    General,
+    /// Logic, comparisons, shifts, additions and the stateful integer operations
+    Integer,
+    /// An integer unit that also multiplies and divides
+    Multiplier,
+    /// Memory accesses only
+    MemoryPort,
+    /// Floating-point operations only
+    Fpu,
+}
+
+impl UnitKind {
+    pub const ALL: [UnitKind; 5] = [
+        UnitKind::General,
+        UnitKind::Integer,
+        UnitKind::Multiplier,
+        UnitKind::MemoryPort,
+        UnitKind::Fpu,
+    ];
+
+    pub fn from_index(index: usize) -> Option<UnitKind> {
+        Self::ALL.get(index).copied()
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            UnitKind::General       => "general",
+            UnitKind::Integer       => "integer",
+            UnitKind::Multiplier    => "multiplier",
+            UnitKind::MemoryPort    => "memory port",
+            UnitKind::Fpu           => "FPU",
+        }
+    }
+
+    /// Every unit runs `NoOp`, and only general units run custom operations
+    pub fn supports(&self, op: AluOpKind) -> bool {
+        let multiplies = matches!(op, AluOpKind::Mul | AluOpKind::Div | AluOpKind::Rem);
+        let floating = matches!(
+            op,
+            AluOpKind::FAdd
+            | AluOpKind::FSub
+            | AluOpKind::FMul
+            | AluOpKind::FDiv
+            | AluOpKind::FCmp
+            | AluOpKind::IntToFloat
+            | AluOpKind::FloatToInt
+        );
+        let integer = !multiplies && !floating && !op.accesses_memory() && op != AluOpKind::Custom;
+        match self {
+            _ if op == AluOpKind::NoOp  => true,
+            UnitKind::General           => true,
+            UnitKind::Integer           => integer,
+            UnitKind::Multiplier        => integer || multiplies,
+            UnitKind::MemoryPort        => op.accesses_memory(),
+            UnitKind::Fpu               => floating,
+        }
+    }
+
+    /// Whether a unit of this kind runs everything a unit of kind `other` does
+    pub fn covers(&self, other: UnitKind) -> bool {
+        AluOpKind::ALL.iter().all(|op| !other.supports(*op) || self.supports(*op))
+    }
+}
+
+/// The parameters of a STruCC machine a program depends on
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct MachineConfig {
+    pub alu_count                   : usize,
+    pub register_count              : usize,
+    pub word_bits                   : u32,
+    pub program_counter_register    : CpuRegisterAddress,
+    /// The unit in each ALU slot, only the first `alu_count` mattering
+    pub units                       : [UnitKind; ALU_COUNT],
+}
+
+impl MachineConfig {
+    /// The machine this simulator builds by default, see `with_word_width` for the others
+    pub const CURRENT: MachineConfig = MachineConfig {
+        alu_count                   : ALU_COUNT,
+        register_count              : REGISTER_COUNT,
+        word_bits                   : WordWidth::W32.bits(),
+        program_counter_register    : PROGRAM_COUNTER_REGISTER_ADDR,
+        units                       : [UnitKind::General; ALU_COUNT],
+    };
+
+    pub const fn with_word_width(self, word_width: WordWidth) -> MachineConfig {
+        MachineConfig { word_bits: word_width.bits(), ..self }
+    }
+
+    /// `None` for a width the simulator doesn't support
+    pub fn word_width(&self) -> Option<WordWidth> {
+        WordWidth::from_bits(self.word_bits)
+    }
+
+    /// The unit of ALU `alu`, general past the declared ones
+    pub fn unit(&self, alu: AluAddress) -> UnitKind {
+        self.units.get(alu).copied().unwrap_or_default()
+    }
+
+    /// Checks that a program targeting `self` can run on `machine`, which may be larger
+    pub fn check_runs_on(&self, machine: &MachineConfig) -> Result<(), MachineMismatch> {
+        let mismatch = |field, required, available| Err(MachineMismatch::Parameter { field, required, available });
+        if self.word_bits != machine.word_bits {
+            return mismatch("word bits", self.word_bits as usize, machine.word_bits as usize);
+        }
+        if self.program_counter_register != machine.program_counter_register {
+            return mismatch("program counter register", self.program_counter_register, machine.program_counter_register);
+        }
+        if self.alu_count > machine.alu_count {
+            return mismatch("ALU count", self.alu_count, machine.alu_count);
+        }
+        if self.register_count > machine.register_count {
+            return mismatch("register count", self.register_count, machine.register_count);
+        }
+        for alu in 0..self.alu_count {
+            let (required, available) = (self.unit(alu), machine.unit(alu));
+            if !available.covers(required) {
+                return Err(MachineMismatch::Unit { alu, required, available });
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Default for MachineConfig {
+    fn default() -> Self {
+        Self::CURRENT
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum MachineMismatch {
+    Parameter {
+        field       : &'static str,
+        required    : usize,
+        available   : usize,
+    },
+    /// The unit of `alu` can't run all the operations of the unit the program expects there
+    Unit {
+        alu         : AluAddress,
+        required    : UnitKind,
+        available   : UnitKind,
+    },
+}
+
+impl Display for MachineMismatch {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MachineMismatch::Parameter { field, required, available } =>
+                write!(f, "the program needs {} {}, the machine has {}", field, required, available),
+            MachineMismatch::Unit { alu, required, available } =>
+                write!(f, "the program needs unit kind {} at ALU {}, the machine has {}", required.name(), alu, available.name()),
+        }
+    }
+}
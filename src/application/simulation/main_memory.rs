@@ -1,32 +1,544 @@
-use std::ops::Deref;
-use std::sync::{Arc, RwLock};
-use crate::application::simulation::memory_primitives::register::Register;
+use std::ops::Range;
+use std::sync::{Arc, Mutex, RwLock};
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
 use crate::{ Step};
-use crate::word::{Word};
+use crate::word::{AtomicWord, Word, WordWidth};
+use crate::application::simulation::devices::{DeviceError, DeviceMap, DeviceMappingError, MemoryMappedDevice};
+use crate::application::simulation::interrupts::{InterruptLine, InterruptMask};
+use crate::application::simulation::error::{MemoryFault, MemoryFaultKind};
+use crate::application::simulation::cache::{Cache, CacheConfig, CacheLookup, WritePolicy, WrittenBackLine};
+use crate::application::simulation::memory_timing::{MemoryAccessKind, MemoryBanks, MemoryTiming};
+use crate::application::simulation::performance::MemoryTraffic;
 pub const MAIN_MEMORY_LEN: usize = 1024;
 
-type MainMemoryInner = Arc<RwLock<Vec<Word>>>;
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum MemoryProtection {
+    NoAccess,
+    ReadOnly,
+    ReadWrite,
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct ProtectionRegion {
+    pub addr_range  : Range<usize>,
+    pub protection  : MemoryProtection,
+}
+
+struct MainMemoryShared {
+    cells       : Box<[AtomicWord]>,
+    /// The `WordWidth::bits` of the words, which stores are wrapped to
+    word_bits   : AtomicU32,
+    devices     : RwLock<DeviceMap>,
+    /// Lets accesses skip the device map entirely while nothing is mapped
+    has_devices : AtomicBool,
+    /// `None` while accesses complete instantly
+    banks       : Mutex<Option<MemoryBanks>>,
+    has_timing  : AtomicBool,
+    /// The cycle ports are claimed in, instead of the accessing CPU's own cycle
+    clock       : AtomicU32,
+    has_clock   : AtomicBool,
+    bus         : Mutex<BusGrant>,
+    arbitrated  : AtomicBool,
+    /// Later regions take precedence over earlier ones they overlap. Addresses outside every
+    /// region are read-write.
+    regions     : RwLock<Vec<ProtectionRegion>>,
+    has_regions : AtomicBool,
+}
+
+/// The CPU an arbitrated memory serves in a system cycle
+#[derive(Default)]
+struct BusGrant {
+    cycle   : Step,
+    owner   : Option<usize>,
+}
+
+type MainMemoryInner = Arc<MainMemoryShared>;
+
+/// Cloning hands out another handle to the same memory
+#[derive(Clone)]
 pub struct MainMemory(MainMemoryInner);
 
 impl MainMemory{
+    /// Memory of words of the default width, `content` being wrapped to it
     pub fn new(content: Vec<Word>) -> Self{
-        MainMemory(Arc::new(RwLock::new(content)))
+        Self::with_word_width(content, WordWidth::default())
+    }
+
+    /// Memory of words of `word_width`, `content` being wrapped to it
+    pub fn with_word_width(content: Vec<Word>, word_width: WordWidth) -> Self {
+        MainMemory(Arc::new(MainMemoryShared {
+            cells       : content.into_iter().map(|word| AtomicWord::new(word_width.wrap(word))).collect(),
+            word_bits   : AtomicU32::new(word_width.bits()),
+            devices     : RwLock::new(DeviceMap::default()),
+            has_devices : AtomicBool::new(false),
+            banks       : Mutex::new(None),
+            has_timing  : AtomicBool::new(false),
+            clock       : AtomicU32::new(0),
+            has_clock   : AtomicBool::new(false),
+            bus         : Mutex::new(BusGrant::default()),
+            arbitrated  : AtomicBool::new(false),
+            regions     : RwLock::new(Vec::new()),
+            has_regions : AtomicBool::new(false),
+        }))
+    }
+
+    /// Wraps the words stored from now on, and those stored already, to `word_width`
+    pub fn set_word_width(&self, word_width: WordWidth) {
+        self.0.word_bits.store(word_width.bits(), Ordering::Release);
+        for cell in self.0.cells.iter() {
+            cell.store(word_width.wrap(cell.load(Ordering::Relaxed)), Ordering::Relaxed);
+        }
+    }
+
+    pub fn word_width(&self) -> WordWidth {
+        WordWidth::from_bits(self.0.word_bits.load(Ordering::Acquire)).unwrap()
+    }
+
+    /// A snapshot of the memory cells, e.g. to dump them
+    pub fn contents(&self) -> Vec<Word> {
+        self.0.cells.iter().map(|cell| cell.load(Ordering::Relaxed)).collect()
+    }
+
+    /// Sets the protection of `addr_range`, over whatever was declared for it before
+    pub fn protect(&self, addr_range: Range<usize>, protection: MemoryProtection) {
+        self.0.regions.write().unwrap().push(ProtectionRegion { addr_range, protection });
+        self.0.has_regions.store(true, Ordering::Release);
+    }
+
+    pub fn protection_regions(&self) -> Vec<ProtectionRegion> {
+        self.0.regions.read().unwrap().clone()
+    }
+
+    /// Makes memory accesses go through a banked timing model, or complete instantly for `None`
+    pub fn set_timing(&self, timing: Option<MemoryTiming>) {
+        *self.0.banks.lock().unwrap() = timing.map(MemoryBanks::new);
+        self.0.has_timing.store(timing.is_some(), Ordering::Release);
+    }
+
+    /// Makes the timing model claim ports in `cycle` rather than in the cycle of the CPU
+    /// accessing the memory, for CPUs sharing the memory whose own cycles drift apart. `None`
+    /// goes back to the CPU's cycle.
+    pub fn set_system_cycle(&self, cycle: Option<Step>) {
+        self.0.clock.store(cycle.unwrap_or(0), Ordering::Release);
+        self.0.has_clock.store(cycle.is_some(), Ordering::Release);
+    }
+
+    /// Makes the memory serve a single CPU per system cycle, see `set_system_cycle`. The first
+    /// CPU reaching the memory in a cycle holds it for the cycle, and the accesses the others
+    /// start stall until a later one. CPUs are told apart by `MainMemoryIo::requester`. Cache
+    /// hits don't reach the memory, and controller loads aren't arbitrated.
+    pub fn set_arbitrated(&self, arbitrated: bool) {
+        self.0.arbitrated.store(arbitrated, Ordering::Release);
+    }
+
+    pub fn timing(&self) -> Option<MemoryTiming> {
+        self.0.banks.lock().unwrap().as_ref().map(MemoryBanks::timing)
+    }
+
+    /// Routes the accesses to `addr_range` to `device` instead of the memory cells. The range
+    /// may lie past the end of the cells.
+    pub fn map_device(
+        &self,
+        addr_range  : Range<usize>,
+        device      : impl MemoryMappedDevice + 'static,
+    ) -> Result<(), DeviceMappingError> {
+        self.0.devices.write().unwrap().map(addr_range, Box::new(device), None)?;
+        self.0.has_devices.store(true, Ordering::Release);
+        Ok(())
+    }
+
+    /// Maps `device` like `map_device`, raising `interrupt_line` of every CPU on the memory in the
+    /// cycles the device requests an interrupt
+    pub fn map_device_with_interrupt(
+        &self,
+        addr_range      : Range<usize>,
+        device          : impl MemoryMappedDevice + 'static,
+        interrupt_line  : InterruptLine,
+    ) -> Result<(), DeviceMappingError> {
+        self.0.devices.write().unwrap().map(addr_range, Box::new(device), Some(interrupt_line))?;
+        self.0.has_devices.store(true, Ordering::Release);
+        Ok(())
+    }
+
+    /// The interrupt lines the mapped devices raise in `cycle`
+    pub fn poll_interrupts(&self, cycle: Step) -> InterruptMask {
+        if !self.0.has_devices.load(Ordering::Acquire) {
+            return 0;
+        }
+        self.0.devices.read().unwrap().poll_interrupts(cycle)
     }
 }
 
-pub struct MainMemoryIo(MainMemoryInner);
+pub struct MainMemoryIo{
+    inner           : MainMemoryInner,
+    pub traffic     : MemoryTraffic,
+    pub cache       : Option<Cache>,
+    /// The CPU the accesses are made for, which an arbitrated memory grants them to
+    pub requester   : usize,
+}
 
 impl MainMemory{
     pub fn get_io(&self) -> MainMemoryIo {
-        MainMemoryIo(self.0.clone())
+        MainMemoryIo{
+            inner   : self.0.clone(),
+            traffic     : MemoryTraffic::default(),
+            cache       : None,
+            requester   : 0,
+        }
     }
 }
 
 impl MainMemoryIo {
-    pub fn read(& self, addr: usize) -> Word{
-        self.0.read().unwrap().get(addr).unwrap().clone()
+    fn with_device<R>(
+        &self,
+        addr    : usize,
+        access  : impl FnOnce(&mut dyn MemoryMappedDevice, usize) -> R,
+    ) -> Option<R> {
+        if !self.inner.has_devices.load(Ordering::Acquire) {
+            return None;
+        }
+        self.inner.devices.read().unwrap().with_device(addr, access)
+    }
+
+    /// Puts a cache in front of the memory, or removes it for `None`, writing back the dirty
+    /// lines of the cache it replaces
+    pub fn set_cache(&mut self, config: Option<CacheConfig>) {
+        self.flush_cache();
+        self.cache = config.map(Cache::new);
+    }
+
+    /// Writes the dirty lines of the cache back to memory
+    pub fn flush_cache(&mut self) {
+        let lines = self.cache.as_mut().map(Cache::flush).unwrap_or_default();
+        for line in lines {
+            self.store_line(line);
+        }
+    }
+
+    fn store_line(&self, line: WrittenBackLine) {
+        for (offset, word) in line.data.into_iter().enumerate() {
+            if let Some(cell) = self.inner.cells.get(line.addr + offset) {
+                cell.store(word, Ordering::Relaxed);
+            }
+        }
+    }
+
+    fn read_cached(&mut self, addr: usize) -> Option<Word> {
+        self.cache.as_mut()?.read(addr)
+    }
+
+    /// Stores `value` in the cache line holding `addr`, and in memory too under `WriteThrough`,
+    /// returning false if no line holds it
+    fn write_cached(&mut self, addr: usize, value: Word) -> bool {
+        let Some(cache) = &mut self.cache else {
+            return false;
+        };
+        let write_through = cache.config().write_policy == WritePolicy::WriteThrough;
+        if !cache.write(addr, value) {
+            return false;
+        }
+        if write_through {
+            self.cell(addr).store(value, Ordering::Relaxed);
+        }
+        true
+    }
+
+    pub fn protection(&self, addr: usize) -> MemoryProtection {
+        if !self.inner.has_regions.load(Ordering::Acquire) {
+            return MemoryProtection::ReadWrite;
+        }
+        self.inner.regions.read().unwrap()
+            .iter()
+            .rev()
+            .find(|region| region.addr_range.contains(&addr))
+            .map_or(MemoryProtection::ReadWrite, |region| region.protection)
+    }
+
+    pub fn check_access(&self, addr: usize, access: MemoryAccessKind) -> Result<(), MemoryFault> {
+        let fault_kind = match self.protection(addr) {
+            MemoryProtection::NoAccess
+                => Some(MemoryFaultKind::NoAccess),
+            MemoryProtection::ReadOnly if access != MemoryAccessKind::Read
+                => Some(MemoryFaultKind::ReadOnly),
+            _ if addr >= self.inner.cells.len() && !self.maps_device(addr)
+                => Some(MemoryFaultKind::OutOfRange),
+            _   => None,
+        };
+        match fault_kind {
+            Some(kind)  => Err(MemoryFault { kind, access, addr }),
+            None        => Ok(()),
+        }
+    }
+
+    /// Starts an access at `cycle`, returning the cycles until it completes, or `None` if it
+    /// can't start this cycle because the memory bank it needs has no free port
+    pub fn start_access(
+        &mut self,
+        addr    : usize,
+        kind    : MemoryAccessKind,
+        cycle   : Step,
+    ) -> Result<Option<Step>, MemoryFault> {
+        self.check_access(addr, kind)?;
+        Ok(self.start_checked_access(addr, kind, cycle))
+    }
+
+    fn start_checked_access(&mut self, addr: usize, kind: MemoryAccessKind, cycle: Step) -> Option<Step> {
+        let Some(cache) = &self.cache else {
+            return self.claim_port(addr, kind, cycle);
+        };
+        let lookup = if self.maps_device(addr) {
+            CacheLookup::Bypass
+        } else {
+            cache.lookup(addr, kind)
+        };
+        let hit_latency = cache.config().hit_latency;
+        let miss_latency = cache.config().miss_latency;
+        let write_through = cache.config().write_policy == WritePolicy::WriteThrough;
+
+        let latency = match lookup {
+            CacheLookup::Hit if kind == MemoryAccessKind::Write && write_through
+                => self.claim_port_past_cache(addr, kind, cycle, miss_latency)?.max(hit_latency),
+            CacheLookup::Hit
+                => hit_latency,
+            CacheLookup::Miss { write_back }
+                => {
+                    let write_back_latency = match (write_back, self.timing()) {
+                        (false, _)              => 0,
+                        (true, Some(timing))    => timing.write_latency,
+                        (true, None)            => miss_latency,
+                    };
+                    hit_latency
+                        + self.claim_port_past_cache(addr, kind, cycle, miss_latency)?
+                        + write_back_latency
+                }
+            CacheLookup::Bypass
+                => self.claim_port_past_cache(addr, kind, cycle, miss_latency)?,
+        };
+        let cells = &self.inner.cells;
+        let load = |addr: usize| cells.get(addr).map_or(0, |cell| cell.load(Ordering::Relaxed));
+        if let Some(line) = self.cache.as_mut().unwrap().access(addr, lookup, load) {
+            self.store_line(line);
+        }
+        Some(latency)
+    }
+
+    fn timing(&self) -> Option<MemoryTiming> {
+        if !self.inner.has_timing.load(Ordering::Acquire) {
+            return None;
+        }
+        self.inner.banks.lock().unwrap().as_ref().map(MemoryBanks::timing)
+    }
+
+    /// Like `claim_port`, for an access the cache sends to memory, which takes `miss_latency`
+    /// without a timing model
+    fn claim_port_past_cache(
+        &mut self,
+        addr            : usize,
+        kind            : MemoryAccessKind,
+        cycle           : Step,
+        miss_latency    : Step,
+    ) -> Option<Step> {
+        if !self.claim_bus() {
+            return None;
+        }
+        if !self.inner.has_timing.load(Ordering::Acquire) {
+            return Some(miss_latency);
+        }
+        self.claim_port(addr, kind, cycle)
+    }
+
+    /// Whether an arbitrated memory serves `requester` in the current system cycle, taking it
+    /// for the cycle if no other CPU did
+    fn claim_bus(&mut self) -> bool {
+        if !self.inner.arbitrated.load(Ordering::Acquire) {
+            return true;
+        }
+        let cycle = self.inner.clock.load(Ordering::Acquire);
+        let mut bus = self.inner.bus.lock().unwrap();
+        if bus.cycle != cycle || bus.owner.is_none() {
+            *bus = BusGrant { cycle, owner: Some(self.requester) };
+        }
+        let granted = bus.owner == Some(self.requester);
+        if !granted {
+            self.traffic.arbitration_stalls += 1;
+        }
+        granted
+    }
+
+    fn claim_port(&mut self, addr: usize, kind: MemoryAccessKind, cycle: Step) -> Option<Step> {
+        if !self.claim_bus() {
+            return None;
+        }
+        if !self.inner.has_timing.load(Ordering::Acquire) {
+            return Some(0);
+        }
+        let cycle = if self.inner.has_clock.load(Ordering::Acquire) {
+            self.inner.clock.load(Ordering::Acquire)
+        } else {
+            cycle
+        };
+        let latency = self.inner.banks.lock().unwrap()
+            .as_mut()
+            .map_or(Some(0), |banks| banks.claim_port(addr, kind, cycle));
+        if latency.is_none() {
+            self.traffic.bank_conflicts += 1;
+        }
+        latency
+    }
+
+    fn maps_device(&self, addr: usize) -> bool {
+        self.with_device(addr, |_, _| ()).is_some()
     }
-    pub fn write(&self, addr: usize, value: Word) {
-        *self.0.write().unwrap().get_mut(addr).unwrap() = value;
+
+    fn cell(&self, addr: usize) -> &AtomicWord {
+        &self.inner.cells[addr]
+    }
+
+    fn word_width(&self) -> WordWidth {
+        WordWidth::from_bits(self.inner.word_bits.load(Ordering::Acquire)).unwrap()
+    }
+
+    pub fn read(&mut self, addr: usize, cycle: Step) -> Result<Word, MemoryFault> {
+        self.check_access(addr, MemoryAccessKind::Read)?;
+        self.traffic.reads += 1;
+        if let Some(word) = self.read_cached(addr) {
+            return Ok(word);
+        }
+        Ok(
+            self.with_device(addr, |device, offset| device.read(offset, cycle))
+                .unwrap_or_else(|| self.cell(addr).load(Ordering::Relaxed))
+        )
+    }
+    pub fn write(&mut self, addr: usize, value: Word, cycle: Step) -> Result<(), MemoryFault> {
+        self.check_access(addr, MemoryAccessKind::Write)?;
+        self.traffic.writes += 1;
+        let value = self.word_width().wrap(value);
+        if self.write_cached(addr, value) {
+            return Ok(());
+        }
+        match self.with_device(addr, |device, offset| device.write(offset, value, cycle)) {
+            Some(result)    => result.map_err(|error| device_fault(error, MemoryAccessKind::Write, addr)),
+            None            => {
+                self.cell(addr).store(value, Ordering::Relaxed);
+                Ok(())
+            }
+        }
+    }
+    /// Adds `value` to the word at `addr` without any other access in between, returning the
+    /// previous word
+    pub fn fetch_add(&mut self, addr: usize, value: Word, cycle: Step) -> Result<Word, MemoryFault> {
+        self.check_access(addr, MemoryAccessKind::ReadWrite)?;
+        self.traffic.reads += 1;
+        self.traffic.writes += 1;
+        let width = self.word_width();
+        if let Some(previous) = self.read_cached(addr) {
+            self.write_cached(addr, width.wrap(previous.wrapping_add(value)));
+            return Ok(previous);
+        }
+        self.with_device(addr, |device, offset| {
+            let previous = device.read(offset, cycle);
+            device.write(offset, width.wrap(previous.wrapping_add(value)), cycle).map(|()| previous)
+        })
+            .unwrap_or_else(|| Ok(
+                self.cell(addr)
+                    .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |word| Some(width.wrap(word.wrapping_add(value))))
+                    .unwrap()
+            ))
+            .map_err(|error| device_fault(error, MemoryAccessKind::ReadWrite, addr))
+    }
+    /// Replaces the word at `addr` without any other access in between, returning the previous
+    /// word
+    pub fn swap(&mut self, addr: usize, value: Word, cycle: Step) -> Result<Word, MemoryFault> {
+        self.check_access(addr, MemoryAccessKind::ReadWrite)?;
+        self.traffic.reads += 1;
+        self.traffic.writes += 1;
+        let value = self.word_width().wrap(value);
+        if let Some(previous) = self.read_cached(addr) {
+            self.write_cached(addr, value);
+            return Ok(previous);
+        }
+        self.with_device(addr, |device, offset| {
+            let previous = device.read(offset, cycle);
+            device.write(offset, value, cycle).map(|()| previous)
+        })
+            .unwrap_or_else(|| Ok(self.cell(addr).swap(value, Ordering::Relaxed)))
+            .map_err(|error| device_fault(error, MemoryAccessKind::ReadWrite, addr))
+    }
+}
+
+fn device_fault(error: DeviceError, access: MemoryAccessKind, addr: usize) -> MemoryFault {
+    MemoryFault { kind: MemoryFaultKind::Device(error), access, addr }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::application::simulation::alu::AluOperation;
+    use crate::application::simulation::error::SimulationError;
+    use crate::application::simulation::instruction::Instruction;
+    use crate::application::simulation::simulation::Cpu;
+
+    #[test]
+    fn stores_wrap_to_the_word_width() {
+        let memory = MainMemory::with_word_width(vec![0x1ff, 0, 0x7f], WordWidth::W8);
+        assert_eq!(memory.contents(), vec![-1, 0, 0x7f]);
+
+        let mut io = memory.get_io();
+        io.write(1, 0x180, 0).unwrap();
+        assert_eq!(io.fetch_add(2, 1, 0), Ok(0x7f));
+        assert_eq!(io.swap(0, 0x100, 0), Ok(-1));
+        assert_eq!(memory.contents(), vec![0, -0x80, -0x80]);
+
+        memory.set_word_width(WordWidth::W64);
+        io.write(0, Word::MAX, 0).unwrap();
+        assert_eq!(io.fetch_add(0, 1, 0), Ok(Word::MAX));
+        assert_eq!(memory.contents()[0], Word::MIN);
+    }
+
+    #[test]
+    fn protection_faults_name_the_access() {
+        let memory = MainMemory::new(vec![0; 8]);
+        memory.protect(0..8, MemoryProtection::ReadOnly);
+        memory.protect(2..4, MemoryProtection::NoAccess);
+        memory.protect(6..8, MemoryProtection::ReadWrite);
+        let mut io = memory.get_io();
+        let fault = |kind, access, addr| MemoryFault { kind, access, addr };
+
+        assert_eq!(io.read(0, 0), Ok(0));
+        assert_eq!(io.write(0, 1, 0), Err(fault(MemoryFaultKind::ReadOnly, MemoryAccessKind::Write, 0)));
+        assert_eq!(io.fetch_add(1, 1, 0), Err(fault(MemoryFaultKind::ReadOnly, MemoryAccessKind::ReadWrite, 1)));
+        assert_eq!(io.read(2, 0), Err(fault(MemoryFaultKind::NoAccess, MemoryAccessKind::Read, 2)));
+        assert_eq!(io.write(3, 1, 0), Err(fault(MemoryFaultKind::NoAccess, MemoryAccessKind::Write, 3)));
+        assert_eq!(io.swap(3, 1, 0), Err(fault(MemoryFaultKind::NoAccess, MemoryAccessKind::ReadWrite, 3)));
+        assert_eq!(io.swap(7, 1, 0), Ok(0));
+        assert_eq!(io.read(8, 0), Err(fault(MemoryFaultKind::OutOfRange, MemoryAccessKind::Read, 8)));
+        assert_eq!(
+            io.start_access(9, MemoryAccessKind::Write, 0),
+            Err(fault(MemoryFaultKind::OutOfRange, MemoryAccessKind::Write, 9)),
+        );
+        assert_eq!(memory.contents(), vec![0, 0, 0, 0, 0, 0, 0, 1]);
+    }
+
+    #[test]
+    fn alus_fault_on_protected_words() {
+        let program = vec![
+            Instruction::SetLiteral { literal: 4, register: 1 },
+            Instruction::SetAluConfig { alu_addr: 2, alu_config: AluOperation::WriteToMem {
+                activation_input: 0, address_input: 1, data_input: 1, activation_output: None,
+            }},
+            Instruction::SetLiteral { literal: !0, register: 0 },
+            Instruction::NoOp,
+        ];
+        let mut cpu = Cpu::new(program, vec![0; 8]);
+        cpu.main_memory.protect(4..5, MemoryProtection::ReadOnly);
+        assert!(matches!(
+            cpu.run(10),
+            Err(SimulationError::MemoryFault {
+                alu     : 2,
+                fault   : MemoryFault { kind: MemoryFaultKind::ReadOnly, access: MemoryAccessKind::Write, addr: 4 },
+                ..
+            }),
+        ));
+        assert_eq!(cpu.main_memory.contents()[4], 0);
     }
 }
@@ -0,0 +1,372 @@
+use std::fmt::{Display, Formatter};
+use std::fmt::Write as _;
+use std::path::Path;
+use crate::word::{Word, WordWidth};
+
+// Images hold words of the width they're loaded and dumped with, sign-extended as they're
+// loaded. Dumping writes the low bits of each word, which hold all of it once it's wrapped to
+// the width as main memory does.
+/// Data bytes per record written by `dump_intel_hex`
+const INTEL_HEX_RECORD_LEN: usize = 16;
+/// Words per line written by `dump_text_hex`
+const TEXT_HEX_LINE_WORDS: usize = 8;
+/// The largest image the loaders build, so a stray address can't make them allocate gigabytes
+pub const MAX_IMAGE_WORDS: usize = 1 << 24;
+
+/// Byte order of the words in a byte-oriented image
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum Endianness {
+    #[default]
+    Little,
+    Big,
+}
+
+#[derive(Debug)]
+pub enum MemoryImageError {
+    Io(std::io::Error),
+    /// A raw binary whose length isn't a whole number of words
+    PartialWord {
+        len         : usize,
+        word_bytes  : usize,
+    },
+    /// A malformed line, numbered from 1
+    Syntax {
+        line    : usize,
+        reason  : &'static str,
+    },
+    Checksum {
+        line    : usize,
+    },
+    UnsupportedRecordType {
+        line        : usize,
+        record_type : u8,
+    },
+    MissingEndOfFile,
+    /// An address at or past `MAX_IMAGE_WORDS` words
+    TooLarge {
+        line    : usize,
+    },
+    UnknownFormat {
+        extension   : Option<String>,
+    },
+}
+
+impl Display for MemoryImageError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MemoryImageError::Io(error) =>
+                write!(f, "{}", error),
+            MemoryImageError::PartialWord { len, word_bytes } =>
+                write!(f, "{} bytes isn't a whole number of {}-byte words", len, word_bytes),
+            MemoryImageError::Syntax { line, reason } =>
+                write!(f, "line {}: {}", line, reason),
+            MemoryImageError::Checksum { line } =>
+                write!(f, "line {}: bad checksum", line),
+            MemoryImageError::UnsupportedRecordType { line, record_type } =>
+                write!(f, "line {}: unsupported record type {:02X}", line, record_type),
+            MemoryImageError::MissingEndOfFile =>
+                write!(f, "missing end-of-file record"),
+            MemoryImageError::TooLarge { line } =>
+                write!(f, "line {}: address past the largest image of {} words", line, MAX_IMAGE_WORDS),
+            MemoryImageError::UnknownFormat { extension } =>
+                write!(f, "unknown memory image format {:?}", extension),
+        }
+    }
+}
+
+impl std::error::Error for MemoryImageError {}
+
+impl From<std::io::Error> for MemoryImageError {
+    fn from(error: std::io::Error) -> Self {
+        MemoryImageError::Io(error)
+    }
+}
+
+fn word_bytes(width: WordWidth) -> usize {
+    width.bits() as usize / 8
+}
+
+pub fn words_from_bytes(bytes: &[u8], endianness: Endianness, width: WordWidth) -> Result<Vec<Word>, MemoryImageError> {
+    let word_bytes = word_bytes(width);
+    if !bytes.len().is_multiple_of(word_bytes) {
+        return Err(MemoryImageError::PartialWord { len: bytes.len(), word_bytes });
+    }
+    Ok(
+        bytes.chunks_exact(word_bytes)
+            .map(|chunk| {
+                let mut word = [0; size_of::<Word>()];
+                let word = match endianness {
+                    Endianness::Little  => {
+                        word[..word_bytes].copy_from_slice(chunk);
+                        Word::from_le_bytes(word)
+                    }
+                    Endianness::Big     => {
+                        word[size_of::<Word>() - word_bytes..].copy_from_slice(chunk);
+                        Word::from_be_bytes(word)
+                    }
+                };
+                width.wrap(word)
+            })
+            .collect()
+    )
+}
+
+pub fn bytes_from_words(words: &[Word], endianness: Endianness, width: WordWidth) -> Vec<u8> {
+    let word_bytes = word_bytes(width);
+    let mut bytes = Vec::with_capacity(words.len() * word_bytes);
+    for word in words {
+        match endianness {
+            Endianness::Little  => bytes.extend_from_slice(&word.to_le_bytes()[..word_bytes]),
+            Endianness::Big     => bytes.extend_from_slice(&word.to_be_bytes()[size_of::<Word>() - word_bytes..]),
+        }
+    }
+    bytes
+}
+
+pub fn load_binary(bytes: &[u8], endianness: Endianness, width: WordWidth) -> Result<Vec<Word>, MemoryImageError> {
+    words_from_bytes(bytes, endianness, width)
+}
+
+pub fn dump_binary(words: &[Word], endianness: Endianness, width: WordWidth) -> Vec<u8> {
+    bytes_from_words(words, endianness, width)
+}
+
+/// Loads an Intel HEX image, whose addresses count bytes. Gaps are zero-filled, and a trailing
+/// partial word is padded with zeroes.
+pub fn load_intel_hex(text: &str, endianness: Endianness, width: WordWidth) -> Result<Vec<Word>, MemoryImageError> {
+    let word_bytes = word_bytes(width);
+    let mut bytes = Vec::new();
+    let mut base_addr = 0_usize;
+
+    for (line_ix, line) in text.lines().enumerate() {
+        let line_number = line_ix + 1;
+        let syntax = |reason| MemoryImageError::Syntax { line: line_number, reason };
+
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        let record = line.strip_prefix(':').ok_or(syntax("records must start with ':'"))?;
+        if !record.bytes().all(|byte| byte.is_ascii_hexdigit()) {
+            return Err(syntax("invalid hex digit"));
+        }
+        if !record.len().is_multiple_of(2) {
+            return Err(syntax("odd number of hex digits"));
+        }
+        let record = record.as_bytes()
+            .chunks_exact(2)
+            .map(|digits| hex_digit(digits[0]) << 4 | hex_digit(digits[1]))
+            .collect::<Vec<_>>();
+
+        let [len, addr_high, addr_low, record_type, ..] = record[..] else {
+            return Err(syntax("record too short"));
+        };
+        let len = len as usize;
+        if record.len() != len + 5 {
+            return Err(syntax("record length doesn't match its byte count"));
+        }
+        if record.iter().fold(0_u8, |sum, byte| sum.wrapping_add(*byte)) != 0 {
+            return Err(MemoryImageError::Checksum { line: line_number });
+        }
+        let data = &record[4..4 + len];
+
+        match record_type {
+            0x00 => {
+                let start = base_addr + u16::from_be_bytes([addr_high, addr_low]) as usize;
+                if start + len > MAX_IMAGE_WORDS * word_bytes {
+                    return Err(MemoryImageError::TooLarge { line: line_number });
+                }
+                if bytes.len() < start + len {
+                    bytes.resize(start + len, 0);
+                }
+                bytes[start..start + len].copy_from_slice(data);
+            }
+            0x01 => {
+                bytes.resize(bytes.len().next_multiple_of(word_bytes), 0);
+                return words_from_bytes(&bytes, endianness, width);
+            }
+            0x02 | 0x04 => {
+                let [high, low] = data[..] else {
+                    return Err(syntax("address records hold 2 bytes"));
+                };
+                let segment = u16::from_be_bytes([high, low]) as usize;
+                base_addr = if record_type == 0x02 { segment << 4 } else { segment << 16 };
+            }
+            // start addresses mean nothing to the fabric
+            0x03 | 0x05 => {}
+            record_type => {
+                return Err(MemoryImageError::UnsupportedRecordType { line: line_number, record_type });
+            }
+        }
+    }
+    Err(MemoryImageError::MissingEndOfFile)
+}
+
+/// The value of an ASCII hex digit
+fn hex_digit(digit: u8) -> u8 {
+    (digit as char).to_digit(16).unwrap() as u8
+}
+
+pub fn dump_intel_hex(words: &[Word], endianness: Endianness, width: WordWidth) -> String {
+    fn write_record(text: &mut String, addr: u16, record_type: u8, data: &[u8]) {
+        let [addr_high, addr_low] = addr.to_be_bytes();
+        let header = [data.len() as u8, addr_high, addr_low, record_type];
+        let sum = header.iter().chain(data).fold(0_u8, |sum, byte| sum.wrapping_add(*byte));
+        text.push(':');
+        for byte in header.iter().chain(data) {
+            write!(text, "{:02X}", byte).unwrap();
+        }
+        writeln!(text, "{:02X}", sum.wrapping_neg()).unwrap();
+    }
+
+    let bytes = bytes_from_words(words, endianness, width);
+    let mut text = String::new();
+    let mut upper_addr = 0;
+    for (chunk_ix, chunk) in bytes.chunks(INTEL_HEX_RECORD_LEN).enumerate() {
+        let addr = chunk_ix * INTEL_HEX_RECORD_LEN;
+        if addr >> 16 != upper_addr {
+            upper_addr = addr >> 16;
+            write_record(&mut text, 0, 0x04, &(upper_addr as u16).to_be_bytes());
+        }
+        write_record(&mut text, addr as u16, 0x00, chunk);
+    }
+    write_record(&mut text, 0, 0x01, &[]);
+    text
+}
+
+/// Loads a text hex dump: whitespace-separated words in hex, optionally preceded on a line by
+/// `<word address>:` to move to another address, with `#` starting a comment. Gaps are
+/// zero-filled.
+pub fn load_text_hex(text: &str, width: WordWidth) -> Result<Vec<Word>, MemoryImageError> {
+    let mut words = Vec::new();
+    let mut addr = 0;
+
+    for (line_ix, line) in text.lines().enumerate() {
+        let syntax = |reason| MemoryImageError::Syntax { line: line_ix + 1, reason };
+
+        let line = line.split('#').next().unwrap();
+        let line = match line.split_once(':') {
+            Some((line_addr, rest)) => {
+                addr = usize::from_str_radix(line_addr.trim(), 16)
+                    .map_err(|_| syntax("invalid address"))?;
+                rest
+            }
+            None => line,
+        };
+        for token in line.split_whitespace() {
+            let word = u64::from_str_radix(token, 16).map_err(|_| syntax("invalid word"))?;
+            if word > width.unsigned(-1) {
+                return Err(syntax("word wider than the word width"));
+            }
+            if addr >= MAX_IMAGE_WORDS {
+                return Err(MemoryImageError::TooLarge { line: line_ix + 1 });
+            }
+            if words.len() <= addr {
+                words.resize(addr + 1, 0);
+            }
+            words[addr] = width.wrap(word as Word);
+            addr += 1;
+        }
+    }
+    Ok(words)
+}
+
+pub fn dump_text_hex(words: &[Word], width: WordWidth) -> String {
+    let digits = width.bits() as usize / 4;
+    let mut text = String::new();
+    for (line_ix, line) in words.chunks(TEXT_HEX_LINE_WORDS).enumerate() {
+        write!(text, "{:04x}:", line_ix * TEXT_HEX_LINE_WORDS).unwrap();
+        for word in line {
+            write!(text, " {:0digits$x}", width.unsigned(*word)).unwrap();
+        }
+        text.push('\n');
+    }
+    text
+}
+
+/// Loads a file picking the format from its extension: `.hex`/`.ihex` for Intel HEX, `.bin`
+/// for a little-endian raw binary and `.txt` for a text hex dump, holding words of `width`
+pub fn load_memory_image(path: impl AsRef<Path>, width: WordWidth) -> Result<Vec<Word>, MemoryImageError> {
+    let path = path.as_ref();
+    let extension = path.extension().and_then(|extension| extension.to_str());
+    match extension {
+        Some("hex" | "ihex")    => load_intel_hex(&std::fs::read_to_string(path)?, Endianness::Little, width),
+        Some("bin")             => load_binary(&std::fs::read(path)?, Endianness::Little, width),
+        Some("txt")             => load_text_hex(&std::fs::read_to_string(path)?, width),
+        _                       => Err(MemoryImageError::UnknownFormat { extension: extension.map(str::to_owned) }),
+    }
+}
+
+/// Writes `words` of `width` in the format `load_memory_image` would pick for `path`
+pub fn dump_memory_image(path: impl AsRef<Path>, words: &[Word], width: WordWidth) -> Result<(), MemoryImageError> {
+    let path = path.as_ref();
+    let extension = path.extension().and_then(|extension| extension.to_str());
+    match extension {
+        Some("hex" | "ihex")    => std::fs::write(path, dump_intel_hex(words, Endianness::Little, width))?,
+        Some("bin")             => std::fs::write(path, dump_binary(words, Endianness::Little, width))?,
+        Some("txt")             => std::fs::write(path, dump_text_hex(words, width))?,
+        _                       => return Err(MemoryImageError::UnknownFormat { extension: extension.map(str::to_owned) }),
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn intel_hex_round_trips() {
+        let words = vec![1, -2, 0x1234_5678, 0, 7];
+        let text = dump_intel_hex(&words, Endianness::Little, WordWidth::W32);
+        assert_eq!(load_intel_hex(&text, Endianness::Little, WordWidth::W32).unwrap(), words);
+    }
+
+    #[test]
+    fn every_format_round_trips_words_of_every_width() {
+        for width in WordWidth::ALL {
+            let words = vec![width.min(), width.max(), -1, 0, 0x5a];
+            for endianness in [Endianness::Little, Endianness::Big] {
+                let bytes = dump_binary(&words, endianness, width);
+                assert_eq!(bytes.len(), words.len() * width.bits() as usize / 8);
+                assert_eq!(load_binary(&bytes, endianness, width).unwrap(), words);
+                let text = dump_intel_hex(&words, endianness, width);
+                assert_eq!(load_intel_hex(&text, endianness, width).unwrap(), words);
+            }
+            assert_eq!(load_text_hex(&dump_text_hex(&words, width), width).unwrap(), words);
+        }
+        assert!(matches!(load_text_hex("100\n", WordWidth::W8), Err(MemoryImageError::Syntax { line: 1, .. })));
+    }
+
+    #[test]
+    fn non_ascii_intel_hex_records_are_syntax_errors() {
+        assert!(matches!(
+            load_intel_hex(":aé1\n:00000001FF\n", Endianness::Little, WordWidth::W32),
+            Err(MemoryImageError::Syntax { line: 1, .. }),
+        ));
+    }
+
+    #[test]
+    fn intel_hex_records_with_a_bad_checksum_are_rejected() {
+        let valid = ":0400000001000000FB\n:00000001FF\n";
+        assert_eq!(load_intel_hex(valid, Endianness::Little, WordWidth::W32).unwrap(), vec![1]);
+        assert!(matches!(
+            load_intel_hex(":0400000001000000FB\n:0400040002000000FC\n:00000001FF\n", Endianness::Little, WordWidth::W32),
+            Err(MemoryImageError::Checksum { line: 2 }),
+        ));
+        assert!(matches!(
+            load_intel_hex(":0400000001000000FB\n:00000001FE\n", Endianness::Little, WordWidth::W32),
+            Err(MemoryImageError::Checksum { line: 2 }),
+        ));
+    }
+
+    #[test]
+    fn addresses_past_the_largest_image_are_rejected() {
+        // an extended linear address of 0xffff, then a data byte
+        let text = ":02000004FFFFFC\n:0100000000FF\n:00000001FF\n";
+        assert!(matches!(
+            load_intel_hex(text, Endianness::Little, WordWidth::W32),
+            Err(MemoryImageError::TooLarge { line: 2 }),
+        ));
+        assert!(matches!(load_text_hex("ffffffff: 1\n", WordWidth::W32), Err(MemoryImageError::TooLarge { line: 1 })));
+    }
+}
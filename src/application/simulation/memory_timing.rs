@@ -0,0 +1,113 @@
+use std::num::NonZeroUsize;
+use crate::Step;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum MemoryAccessKind {
+    Read,
+    Write,
+    /// Atomic read-modify-write
+    ReadWrite,
+}
+
+/// Timing of a banked main memory. Consecutive words are interleaved across the banks, and each
+/// bank has `ports_per_bank` ports, a port being taken for `port_busy_cycles` from the cycle an
+/// access starts (1 for fully pipelined banks). An access completes `read_latency` or
+/// `write_latency` cycles after it started, 0 meaning within the same cycle. ALUs contending for
+/// a bank get its ports in address order.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct MemoryTiming {
+    pub banks               : NonZeroUsize,
+    pub ports_per_bank      : NonZeroUsize,
+    pub port_busy_cycles    : NonZeroUsize,
+    pub read_latency        : Step,
+    pub write_latency       : Step,
+}
+
+impl MemoryTiming {
+    pub fn bank_of(&self, addr: usize) -> usize {
+        addr % self.banks.get()
+    }
+
+    pub fn latency(&self, kind: MemoryAccessKind) -> Step {
+        match kind {
+            MemoryAccessKind::Read      => self.read_latency,
+            MemoryAccessKind::Write     => self.write_latency,
+            MemoryAccessKind::ReadWrite => self.read_latency.max(self.write_latency),
+        }
+    }
+}
+
+/// Cycles `start..end` during which a port is taken
+#[derive(Clone, Copy, Default)]
+struct PortOccupation {
+    start   : Step,
+    end     : Step,
+}
+
+impl PortOccupation {
+    fn is_taken(&self, cycle: Step) -> bool {
+        (self.start..self.end).contains(&cycle)
+    }
+}
+
+pub struct MemoryBanks {
+    timing  : MemoryTiming,
+    /// `ports_per_bank` entries per bank
+    ports   : Vec<PortOccupation>,
+}
+
+impl MemoryBanks {
+    pub fn new(timing: MemoryTiming) -> Self {
+        Self {
+            timing,
+            ports   : vec![PortOccupation::default(); timing.banks.get() * timing.ports_per_bank.get()],
+        }
+    }
+
+    pub fn timing(&self) -> MemoryTiming {
+        self.timing
+    }
+
+    /// Takes a port of the bank holding `addr` for an access starting at `cycle`, returning the
+    /// access latency, or `None` if all the ports of the bank are taken
+    pub fn claim_port(&mut self, addr: usize, kind: MemoryAccessKind, cycle: Step) -> Option<Step> {
+        let ports_per_bank = self.timing.ports_per_bank.get();
+        let first_port = self.timing.bank_of(addr) * ports_per_bank;
+        let port = self.ports[first_port..first_port + ports_per_bank]
+            .iter_mut()
+            .find(|port| !port.is_taken(cycle))?;
+        *port = PortOccupation {
+            start   : cycle,
+            end     : cycle.saturating_add(self.timing.port_busy_cycles.get() as Step),
+        };
+        Some(self.timing.latency(kind))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::application::simulation::main_memory::MainMemory;
+
+    #[test]
+    fn accesses_to_a_busy_bank_stall_until_a_port_frees_up() {
+        let memory = MainMemory::new(vec![0; 8]);
+        memory.set_timing(Some(MemoryTiming {
+            banks               : NonZeroUsize::new(2).unwrap(),
+            ports_per_bank      : NonZeroUsize::MIN,
+            port_busy_cycles    : NonZeroUsize::new(2).unwrap(),
+            read_latency        : 3,
+            write_latency       : 1,
+        }));
+        let (mut first, mut second) = (memory.get_io(), memory.get_io());
+
+        assert_eq!(first.start_access(0, MemoryAccessKind::Read, 0), Ok(Some(3)));
+        assert_eq!(second.start_access(2, MemoryAccessKind::Write, 0), Ok(None));
+        assert_eq!(second.start_access(1, MemoryAccessKind::Write, 0), Ok(Some(1)));
+        assert_eq!(second.start_access(4, MemoryAccessKind::ReadWrite, 1), Ok(None));
+        assert_eq!(second.start_access(4, MemoryAccessKind::ReadWrite, 2), Ok(Some(3)));
+
+        assert_eq!(first.traffic.bank_conflicts, 0);
+        assert_eq!(second.traffic.bank_conflicts, 2);
+    }
+}
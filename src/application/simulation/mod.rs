@@ -6,4 +6,20 @@ pub mod instruction_reader;
 pub mod main_memory;
 pub mod simulation;
 pub mod component_bank;
-pub mod memory_primitives;
\ No newline at end of file
+pub mod memory_primitives;
+pub mod performance;
+pub mod scheduling;
+pub mod parallel;
+pub mod system;
+pub mod devices;
+pub mod memory_timing;
+pub mod cache;
+pub mod error;
+pub mod memory_image;
+pub mod encoding;
+pub mod machine;
+pub mod program_image;
+pub mod propagation;
+pub mod interrupts;
+#[cfg(test)]
+mod test_support;
@@ -0,0 +1,296 @@
+use std::num::NonZeroUsize;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::mpsc::{sync_channel, Receiver, SyncSender};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+use crate::application::simulation::alu::{AluBank, AluCore, ALU_COUNT};
+use crate::application::simulation::scheduling::{mask_bits, AluMask};
+use crate::application::simulation::error::SimulationError;
+use crate::Step;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum AluExecutor {
+    #[default]
+    Sequential,
+    /// Splits the bank in `threads` contiguous chunks evaluated concurrently. ALUs accessing
+    /// memory are still evaluated one at a time in address order, and outputs are written back
+    /// in address order, so results match the sequential executor. Handing a cycle to the
+    /// workers costs more than evaluating a bank of cheap operations, so the pool times both
+    /// ways now and then and only uses the workers while they're faster.
+    Parallel {
+        threads : NonZeroUsize,
+    },
+}
+
+/// A chunk of the ALU bank lent to a worker for the duration of one `AluWorkerPool::execute`
+struct Job {
+    alus    : *mut AluCore,
+    len     : usize,
+    mask    : AluMask,
+    cycle   : Step,
+}
+
+// SAFETY: the chunks handed to different workers are disjoint, and `AluWorkerPool::dispatch`
+// doesn't return before every worker reported its chunk as done
+unsafe impl Send for Job {}
+
+/// Cycles a probe evaluates each way, first on the calling thread alone and then on the workers
+const PROBE_CYCLES: u32 = 16;
+/// Cycles from the start of one probe to the start of the next
+const PROBE_PERIOD: u32 = 1 << 14;
+
+struct Worker {
+    jobs    : Option<SyncSender<Job>>,
+    done    : Receiver<Result<(), SimulationError>>,
+    handle  : Option<JoinHandle<()>>,
+}
+
+/// Threads kept alive across steps, so a parallel step doesn't pay for spawning them
+pub struct AluWorkerPool {
+    threads         : NonZeroUsize,
+    chunk_len       : usize,
+    workers         : Vec<Worker>,
+    /// Cycles since the current probe started
+    probe_phase     : u32,
+    sequential_time : Duration,
+    parallel_time   : Duration,
+    /// Whether the last probe found the workers faster than the calling thread alone
+    use_workers     : bool,
+}
+
+impl AluWorkerPool {
+    pub fn new(threads: NonZeroUsize) -> Self {
+        let chunk_len = ALU_COUNT.div_ceil(threads.get());
+        let chunk_count = ALU_COUNT.div_ceil(chunk_len);
+
+        // the calling thread evaluates the first chunk itself
+        let workers = (1..chunk_count)
+            .map(|_| {
+                let (jobs, job_receiver) = sync_channel::<Job>(1);
+                let (done_sender, done) = sync_channel(1);
+                let handle = thread::spawn(move || {
+                    for job in job_receiver {
+                        // SAFETY: `job.alus` points at the `job.len` ALUs of a chunk of the bank
+                        // that `dispatch` keeps mutably borrowed, and hands to no other thread,
+                        // until this worker reports back through `done_sender`
+                        let alus = unsafe { std::slice::from_raw_parts_mut(job.alus, job.len) };
+                        let result = execute_masked(alus, job.mask, job.cycle);
+                        if done_sender.send(result).is_err() {
+                            break;
+                        }
+                    }
+                });
+                Worker { jobs: Some(jobs), done, handle: Some(handle) }
+            })
+            .collect();
+
+        Self {
+            threads,
+            chunk_len,
+            workers,
+            probe_phase     : 0,
+            sequential_time : Duration::ZERO,
+            parallel_time   : Duration::ZERO,
+            use_workers     : false,
+        }
+    }
+
+    pub fn threads(&self) -> NonZeroUsize {
+        self.threads
+    }
+
+    /// Reports the fault of the lowest faulting ALU address, if any
+    pub fn execute(
+        &mut self,
+        alu_bank    : &mut AluBank,
+        mask        : AluMask,
+        cycle       : Step,
+    ) -> Result<(), SimulationError> {
+        let phase = self.probe_phase;
+        self.probe_phase = (self.probe_phase + 1) % PROBE_PERIOD;
+        if phase >= 2 * PROBE_CYCLES {
+            return if self.use_workers {
+                self.dispatch(alu_bank, mask, cycle)
+            } else {
+                execute_masked(&mut alu_bank.components[..], mask, cycle)
+            };
+        }
+
+        let start = Instant::now();
+        if phase < PROBE_CYCLES {
+            let result = execute_masked(&mut alu_bank.components[..], mask, cycle);
+            self.sequential_time += start.elapsed();
+            result
+        } else {
+            let result = self.dispatch(alu_bank, mask, cycle);
+            self.parallel_time += start.elapsed();
+            if phase == 2 * PROBE_CYCLES - 1 {
+                self.use_workers = self.parallel_time < self.sequential_time;
+                self.sequential_time = Duration::ZERO;
+                self.parallel_time = Duration::ZERO;
+            }
+            result
+        }
+    }
+
+    /// Evaluates the chunks of `mask` on the workers, the first one on the calling thread
+    fn dispatch(
+        &mut self,
+        alu_bank    : &mut AluBank,
+        mask        : AluMask,
+        cycle       : Step,
+    ) -> Result<(), SimulationError> {
+        let mut chunks = alu_bank.components.chunks_mut(self.chunk_len).enumerate();
+        let (_, own_chunk) = chunks.next().unwrap();
+
+        let mut dispatched = [false; ALU_COUNT];
+        for ((chunk_ix, chunk), worker) in chunks.zip(self.workers.iter()) {
+            let chunk_mask = chunk_mask(mask, chunk_ix * self.chunk_len, chunk.len());
+            if chunk_mask == 0 {
+                continue;
+            }
+            worker.jobs.as_ref().unwrap()
+                .send(Job { alus: chunk.as_mut_ptr(), len: chunk.len(), mask: chunk_mask, cycle })
+                .expect("ALU worker thread stopped");
+            dispatched[chunk_ix] = true;
+        }
+
+        // the workers must be done with their chunks before a panic can unwind past this borrow
+        let own_result = panic::catch_unwind(AssertUnwindSafe(||
+            execute_masked(own_chunk, chunk_mask(mask, 0, own_chunk.len()), cycle)
+        ));
+
+        let mut workers_ok = true;
+        let mut worker_results = Ok(());
+        for (worker_ix, worker) in self.workers.iter().enumerate() {
+            if dispatched[worker_ix + 1] {
+                match worker.done.recv() {
+                    Ok(result)  => worker_results = worker_results.and(result),
+                    Err(_)      => workers_ok = false,
+                }
+            }
+        }
+
+        let own_result = match own_result {
+            Ok(own_result)  => own_result,
+            Err(payload)    => panic::resume_unwind(payload),
+        };
+        assert!(workers_ok, "ALU worker thread panicked");
+        own_result.and(worker_results)
+    }
+}
+
+impl Drop for AluWorkerPool {
+    fn drop(&mut self) {
+        for worker in &mut self.workers {
+            worker.jobs.take();
+            if let Some(handle) = worker.handle.take() {
+                let _ = handle.join();
+            }
+        }
+    }
+}
+
+fn chunk_mask(mask: AluMask, start: usize, len: usize) -> AluMask {
+    let low_bits = if len >= AluMask::BITS as usize { AluMask::MAX } else { (1 << len) - 1 };
+    (mask >> start) & low_bits
+}
+
+fn execute_masked(alus: &mut [AluCore], mask: AluMask, cycle: Step) -> Result<(), SimulationError> {
+    for ix in mask_bits(mask) {
+        alus[ix].execute(cycle)?;
+    }
+    Ok(())
+}
+
+impl AluExecutor {
+    /// Evaluates the scheduled ALUs, reusing `pool` or replacing it if it doesn't match the
+    /// executor's thread count
+    pub fn execute(
+        &self,
+        alu_bank    : &mut AluBank,
+        pool        : &mut Option<AluWorkerPool>,
+        scheduled   : AluMask,
+        memory      : AluMask,
+        cycle       : Step,
+    ) -> Result<(), SimulationError> {
+        match *self {
+            AluExecutor::Sequential => {
+                execute_masked(&mut alu_bank.components[..], scheduled, cycle)
+            }
+            AluExecutor::Parallel { threads } => {
+                if pool.as_ref().is_none_or(|pool| pool.threads() != threads) {
+                    *pool = Some(AluWorkerPool::new(threads));
+                }
+                let result = pool.as_mut().unwrap().execute(alu_bank, scheduled & !memory, cycle);
+
+                // the sequential executor stops at the first faulting ALU, so memory ALUs below
+                // it still run and their faults take precedence
+                let memory_scheduled = match result.as_ref().err().and_then(SimulationError::alu) {
+                    Some(faulting_alu)  => scheduled & memory & ((1 << faulting_alu) - 1),
+                    None                => scheduled & memory,
+                };
+                execute_masked(&mut alu_bank.components[..], memory_scheduled, cycle)?;
+                result
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::application::simulation::alu::AluOperation;
+    use crate::application::simulation::encoding::encode_config_frame;
+    use crate::application::simulation::instruction::Instruction;
+    use crate::application::simulation::simulation::Cpu;
+    use crate::application::simulation::test_support::{mixed_program, trace};
+
+    const EXECUTORS: [AluExecutor; 3] = [
+        AluExecutor::Sequential,
+        AluExecutor::Parallel { threads: NonZeroUsize::new(1).unwrap() },
+        AluExecutor::Parallel { threads: NonZeroUsize::new(4).unwrap() },
+    ];
+
+    fn cpu(program: Vec<Instruction>, executor: AluExecutor) -> Cpu {
+        let mut cpu = Cpu::new(program, vec![0; 8]);
+        cpu.executor = executor;
+        cpu
+    }
+
+    #[test]
+    fn parallel_matches_sequential_every_cycle() {
+        let sequential = trace(cpu(mixed_program(), AluExecutor::Sequential), 60);
+        for executor in EXECUTORS {
+            let parallel = trace(cpu(mixed_program(), executor), 60);
+            for (sequential, parallel) in sequential.iter().zip(&parallel) {
+                assert_eq!(sequential, parallel, "{:?}", executor);
+            }
+        }
+    }
+
+    #[test]
+    fn the_lowest_faulting_alu_is_reported() {
+        // memory faults on ALUs 3 and 20, configured in the same cycle
+        let frame = encode_config_frame(&[
+            (20, AluOperation::ReadFromMem {
+                activation_input: 0, data_input_0: 1, data_output_0: 2, activation_output: None,
+            }),
+            (3, AluOperation::ReadFromMem {
+                activation_input: 0, data_input_0: 1, data_output_0: 3, activation_output: None,
+            }),
+        ]).unwrap();
+        let program = vec![
+            Instruction::SetLiteral { literal: !0, register: 0 },
+            Instruction::SetLiteral { literal: 1000, register: 1 },
+            Instruction::LoadConfigFrame { source_addr: 0, count: 2 },
+            Instruction::Jump { addr: 3 },
+        ];
+        for executor in EXECUTORS {
+            let mut cpu = Cpu::new(program.clone(), frame.clone());
+            cpu.executor = executor;
+            let error = cpu.run(10).unwrap_err();
+            assert_eq!(error.alu(), Some(3), "{:?}: {}", executor, error);
+        }
+    }
+}
@@ -0,0 +1,214 @@
+use std::collections::BTreeMap;
+use std::fmt::{Display, Formatter};
+use crate::Step;
+use crate::application::simulation::alu::{AluAddress, AluOpKind};
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AluCounters {
+    /// Cycles in which the ALU held an operation other than `NoOp`
+    pub cycles_configured   : u64,
+    /// Cycles in which the activation input of the ALU was set
+    pub cycles_activated    : u64,
+    /// Indexed by `AluOpKind as usize`
+    pub ops_executed        : [u64; AluOpKind::COUNT],
+    /// Cycles spent waiting for a memory access to start or to complete
+    pub memory_stall_cycles : u64,
+}
+
+// arrays only derive `Default` up to 32 entries
+impl Default for AluCounters {
+    fn default() -> Self {
+        Self {
+            cycles_configured   : 0,
+            cycles_activated    : 0,
+            ops_executed        : [0; AluOpKind::COUNT],
+            memory_stall_cycles : 0,
+        }
+    }
+}
+
+impl AluCounters {
+    pub fn total_ops_executed(&self) -> u64 {
+        self.ops_executed.iter().sum()
+    }
+}
+
+#[derive(Clone, Copy, Default, Debug, PartialEq, Eq)]
+pub struct ControllerCounters {
+    pub instructions_executed   : u64,
+    /// Cycles spent in `WaitingForActivation` without the activation being set
+    pub stall_cycles            : u64,
+    /// Instructions copied from main memory by `LoadInstructions`
+    pub instructions_loaded     : u64,
+    /// Vectored interrupts taken
+    pub interrupts_taken        : u64,
+    pub memory_traffic          : MemoryTraffic,
+}
+
+#[derive(Clone, Copy, Default, Debug, PartialEq, Eq)]
+pub struct MemoryTraffic {
+    pub reads           : u64,
+    pub writes          : u64,
+    /// Accesses that couldn't start because their bank had no free port
+    pub bank_conflicts      : u64,
+    /// Accesses that couldn't start because another CPU held the arbitrated memory
+    pub arbitration_stalls  : u64,
+}
+
+impl std::ops::AddAssign for MemoryTraffic {
+    fn add_assign(&mut self, rhs: Self) {
+        self.reads          += rhs.reads;
+        self.writes         += rhs.writes;
+        self.bank_conflicts += rhs.bank_conflicts;
+        self.arbitration_stalls += rhs.arbitration_stalls;
+    }
+}
+
+#[derive(Clone, Copy, Default, Debug, PartialEq, Eq)]
+pub struct CacheStats {
+    pub hits        : u64,
+    pub misses      : u64,
+    /// Dirty lines written back to memory when evicted, not counting flushes
+    pub write_backs : u64,
+    /// Accesses that went straight to memory: atomic misses, memory-mapped devices and
+    /// write-through write misses
+    pub bypasses    : u64,
+}
+
+impl CacheStats {
+    pub fn hit_rate(&self) -> f64 {
+        ratio(self.hits, self.hits + self.misses)
+    }
+}
+
+impl std::ops::AddAssign for CacheStats {
+    fn add_assign(&mut self, rhs: Self) {
+        self.hits           += rhs.hits;
+        self.misses         += rhs.misses;
+        self.write_backs    += rhs.write_backs;
+        self.bypasses       += rhs.bypasses;
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AluReport {
+    pub addr            : AluAddress,
+    pub counters        : AluCounters,
+    pub memory_traffic  : MemoryTraffic,
+    /// `None` if the ALU accesses memory without a cache
+    pub cache           : Option<CacheStats>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PerformanceReport {
+    pub cycles      : Step,
+    pub alus        : Vec<AluReport>,
+    pub controller  : ControllerCounters,
+}
+
+impl PerformanceReport {
+    pub fn memory_traffic(&self) -> MemoryTraffic {
+        let mut total = self.controller.memory_traffic;
+        for alu in &self.alus {
+            total += alu.memory_traffic;
+        }
+        total
+    }
+
+    /// `None` if no ALU has a cache
+    pub fn cache_stats(&self) -> Option<CacheStats> {
+        let mut caches = self.alus.iter().filter_map(|alu| alu.cache).peekable();
+        caches.peek()?;
+        let mut total = CacheStats::default();
+        for cache in caches {
+            total += cache;
+        }
+        Some(total)
+    }
+
+    pub fn ops_executed(&self) -> BTreeMap<AluOpKind, u64> {
+        let mut total = BTreeMap::new();
+        for alu in &self.alus {
+            for (kind, count) in AluOpKind::ALL.iter().zip(alu.counters.ops_executed) {
+                if count > 0 {
+                    *total.entry(*kind).or_insert(0) += count;
+                }
+            }
+        }
+        total
+    }
+
+    /// Fraction of the fabric's ALU-cycles that did useful work
+    pub fn fabric_utilization(&self) -> f64 {
+        let available = self.cycles as u64 * self.alus.len() as u64;
+        let executed: u64 = self.alus.iter().map(|alu| alu.counters.total_ops_executed()).sum();
+        ratio(executed, available)
+    }
+}
+
+fn ratio(part: u64, total: u64) -> f64 {
+    if total == 0 { 0.0 } else { part as f64 / total as f64 }
+}
+
+impl Display for PerformanceReport {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let cycles = self.cycles as u64;
+        writeln!(f, "=== STruCC performance report ===")?;
+        writeln!(f, "cycles                : {}", cycles)?;
+        writeln!(f, "fabric utilization    : {:6.2}%", self.fabric_utilization() * 100.0)?;
+        writeln!(
+            f,
+            "controller            : {} instructions, {} stall cycles ({:.2}%), {} instructions loaded, {} interrupts",
+            self.controller.instructions_executed,
+            self.controller.stall_cycles,
+            ratio(self.controller.stall_cycles, cycles) * 100.0,
+            self.controller.instructions_loaded,
+            self.controller.interrupts_taken,
+        )?;
+        let traffic = self.memory_traffic();
+        writeln!(
+            f,
+            "memory traffic        : {} reads, {} writes, {} bank conflicts, {} arbitration stalls",
+            traffic.reads,
+            traffic.writes,
+            traffic.bank_conflicts,
+            traffic.arbitration_stalls,
+        )?;
+        if let Some(cache) = self.cache_stats() {
+            writeln!(
+                f,
+                "alu caches            : {} hits, {} misses ({:.2}% hit rate), {} write-backs, {} bypasses",
+                cache.hits,
+                cache.misses,
+                cache.hit_rate() * 100.0,
+                cache.write_backs,
+                cache.bypasses,
+            )?;
+        }
+
+        writeln!(f, "ops executed by type  :")?;
+        for (kind, count) in self.ops_executed() {
+            writeln!(f, "    {:<12} {}", kind.name(), count)?;
+        }
+
+        writeln!(f, " alu | configured | activated |   ops   | mem r  | mem w  | mem stall | utilization")?;
+        for alu in &self.alus {
+            if alu.counters.cycles_configured == 0 {
+                continue;
+            }
+            writeln!(
+                f,
+                " {:3} | {:10} | {:9} | {:7} | {:6} | {:6} | {:9} | {:6.2}%",
+                alu.addr,
+                alu.counters.cycles_configured,
+                alu.counters.cycles_activated,
+                alu.counters.total_ops_executed(),
+                alu.memory_traffic.reads,
+                alu.memory_traffic.writes,
+                alu.counters.memory_stall_cycles,
+                ratio(alu.counters.total_ops_executed(), cycles) * 100.0,
+            )?;
+        }
+        Ok(())
+    }
+}
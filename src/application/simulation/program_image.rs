@@ -0,0 +1,569 @@
+use std::collections::BTreeMap;
+use std::fmt::{Display, Formatter};
+use std::path::Path;
+use crate::application::simulation::cpu_registers::CpuRegisterAddress;
+use crate::application::simulation::encoding::{decode_program, encode_program, DecodeError, EncodeError, MIN_ENCODED_WORD_BITS};
+use crate::application::simulation::instruction::Instruction;
+use crate::application::simulation::interrupts::{InterruptAction, INTERRUPT_LINE_COUNT};
+use crate::application::simulation::alu::{AluOperation, CustomOps, ALU_COUNT};
+use crate::application::simulation::machine::{MachineConfig, MachineMismatch, UnitKind};
+use crate::application::simulation::main_memory::{MainMemory, MemoryProtection, ProtectionRegion};
+use crate::application::simulation::scheduling::mask_bits;
+use crate::application::simulation::simulation::Cpu;
+use crate::word::Word;
+
+// A program image file is the magic, a little-endian u32 version and u32 section count, then the
+// sections, each one a 4-byte tag, a u32 payload length in bytes and the payload. Words are
+// little-endian, and 8 bytes long on 64-bit machines, which the machine section declares. It
+// must come before the code, data and register sections. Sections with unknown tags are skipped.
+const MAGIC: &[u8; 8] = b"STRUCC\0\0";
+const VERSION: u32 = 2;
+
+const MACHINE_SECTION       : [u8; 4] = *b"MACH";
+const CODE_SECTION          : [u8; 4] = *b"CODE";
+const DATA_SECTION          : [u8; 4] = *b"DATA";
+const REGISTERS_SECTION     : [u8; 4] = *b"REGS";
+const PROTECTION_SECTION    : [u8; 4] = *b"PROT";
+const SYMBOLS_SECTION       : [u8; 4] = *b"SYMS";
+const INTERRUPTS_SECTION    : [u8; 4] = *b"INTR";
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum SymbolSection {
+    /// An instruction index
+    Code,
+    /// A main-memory address
+    Data,
+    Register,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Symbol {
+    pub section : SymbolSection,
+    pub value   : usize,
+}
+
+/// Everything needed to start a program: its code, the initial state of the memory and registers,
+/// and the machine it was built for
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct ProgramImage {
+    pub machine             : MachineConfig,
+    pub instructions        : Vec<Instruction>,
+    pub data                : Vec<Word>,
+    pub registers           : Vec<(CpuRegisterAddress, Word)>,
+    pub protection_regions  : Vec<ProtectionRegion>,
+    pub symbols             : BTreeMap<String, Symbol>,
+    pub interrupt_actions   : [InterruptAction; INTERRUPT_LINE_COUNT],
+}
+
+#[derive(Debug)]
+pub enum ProgramImageError {
+    Io(std::io::Error),
+    BadMagic,
+    UnsupportedVersion(u32),
+    Truncated,
+    MissingSection(&'static str),
+    Malformed {
+        section : &'static str,
+        reason  : &'static str,
+    },
+    Decode {
+        instruction : usize,
+        error       : DecodeError,
+    },
+    Encode {
+        instruction : usize,
+        error       : EncodeError,
+    },
+    Incompatible(MachineMismatch),
+    /// Names the first instruction, data word, register, region or symbol that doesn't fit the
+    /// machine
+    OutOfRange(String),
+}
+
+impl Display for ProgramImageError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ProgramImageError::Io(error)                    => write!(f, "{}", error),
+            ProgramImageError::BadMagic                     => write!(f, "not a STruCC program image"),
+            ProgramImageError::UnsupportedVersion(version)  => write!(f, "unsupported program image version {}", version),
+            ProgramImageError::Truncated                    => write!(f, "truncated program image"),
+            ProgramImageError::MissingSection(section)      => write!(f, "missing {} section", section),
+            ProgramImageError::Malformed { section, reason }=> write!(f, "malformed {} section: {}", section, reason),
+            ProgramImageError::Decode { instruction, error }=> write!(f, "instruction {}: {}", instruction, error),
+            ProgramImageError::Encode { instruction, error }=> write!(f, "instruction {}: {}", instruction, error),
+            ProgramImageError::Incompatible(mismatch)       => write!(f, "{}", mismatch),
+            ProgramImageError::OutOfRange(what)             => write!(f, "{} is out of range for the machine", what),
+        }
+    }
+}
+
+impl std::error::Error for ProgramImageError {}
+
+impl From<std::io::Error> for ProgramImageError {
+    fn from(error: std::io::Error) -> Self {
+        ProgramImageError::Io(error)
+    }
+}
+
+impl ProgramImage {
+    pub fn new(instructions: Vec<Instruction>, data: Vec<Word>) -> Self {
+        Self {
+            machine             : MachineConfig::CURRENT,
+            instructions,
+            data,
+            registers           : Vec::new(),
+            protection_regions  : Vec::new(),
+            symbols             : BTreeMap::new(),
+            interrupt_actions   : [InterruptAction::Masked; INTERRUPT_LINE_COUNT],
+        }
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, ProgramImageError> {
+        Self::from_bytes(&std::fs::read(path)?)
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), ProgramImageError> {
+        std::fs::write(path, self.to_bytes()?)?;
+        Ok(())
+    }
+
+    /// Checks that the image can run on `machine` and only refers to what the machine it targets
+    /// has, its `Custom` operations included
+    pub fn validate(&self, machine: &MachineConfig, custom_ops: &CustomOps) -> Result<(), ProgramImageError> {
+        self.machine.check_runs_on(machine).map_err(ProgramImageError::Incompatible)?;
+
+        let alu_count = self.machine.alu_count;
+        let register_count = self.machine.register_count;
+        let word_width = self.machine.word_width().unwrap_or_default();
+        let out_of_range = |what: String| Err(ProgramImageError::OutOfRange(what));
+        let registered = |alu_config: &AluOperation| alu_config.custom_id().is_none_or(|id| custom_ops.get(id).is_some());
+
+        for (ix, instruction) in self.instructions.iter().enumerate() {
+            let in_range = instruction.in_range(alu_count, register_count) && match instruction {
+                Instruction::SetAluConfig { alu_config, alu_addr } =>
+                    self.machine.unit(*alu_addr).supports(alu_config.kind())
+                        && alu_config.kind().runs_at(word_width)
+                        && registered(alu_config),
+                Instruction::BroadcastAluConfig { alu_config, alu_mask, .. } =>
+                    mask_bits(*alu_mask).all(|alu| self.machine.unit(alu).supports(alu_config.kind()))
+                        && alu_config.kind().runs_at(word_width)
+                        && registered(alu_config),
+                Instruction::LoadInstructions { .. } | Instruction::LoadConfigFrame { .. } =>
+                    word_width.bits() >= MIN_ENCODED_WORD_BITS,
+                _ => true,
+            };
+            if !in_range {
+                return out_of_range(format!("instruction {} ({:?})", ix, instruction));
+            }
+        }
+        // encoded instructions are the usual data that doesn't fit narrow words
+        for (addr, word) in self.data.iter().enumerate() {
+            if word_width.wrap(*word) != *word && word_width.unsigned(*word) as Word != *word {
+                return out_of_range(format!("data word {} ({:#x})", addr, word));
+            }
+        }
+        for (register, _) in &self.registers {
+            if *register >= register_count {
+                return out_of_range(format!("register {}", register));
+            }
+        }
+        for region in &self.protection_regions {
+            if region.addr_range.start > region.addr_range.end {
+                return out_of_range(format!("protection region {:?}", region.addr_range));
+            }
+        }
+        for (name, symbol) in &self.symbols {
+            let limit = match symbol.section {
+                SymbolSection::Code     => self.instructions.len(),
+                SymbolSection::Data     => self.data.len(),
+                SymbolSection::Register => register_count - 1,
+            };
+            if symbol.value > limit {
+                return out_of_range(format!("symbol {}", name));
+            }
+        }
+        for (line, action) in self.interrupt_actions.iter().enumerate() {
+            let in_range = match action {
+                InterruptAction::Masked                 => true,
+                InterruptAction::Activate { register }  => *register < register_count,
+                InterruptAction::Vector { handler }     => *handler < self.instructions.len(),
+            };
+            if !in_range {
+                return out_of_range(format!("interrupt line {} ({:?})", line, action));
+            }
+        }
+        Ok(())
+    }
+
+    /// Validates the image against the simulated machine, then builds a CPU in its initial state
+    pub fn into_cpu(self) -> Result<Cpu, ProgramImageError> {
+        self.into_cpu_with(&CustomOps::new())
+    }
+
+    /// Like `into_cpu`, for a CPU built with the operations of `custom_ops`
+    pub fn into_cpu_with(self, custom_ops: &CustomOps) -> Result<Cpu, ProgramImageError> {
+        // the simulator builds machines of every supported width
+        let word_width = self.machine.word_width().unwrap_or_default();
+        self.validate(&MachineConfig::CURRENT.with_word_width(word_width), custom_ops)?;
+
+        let main_memory = MainMemory::with_word_width(self.data, word_width);
+        for region in self.protection_regions {
+            main_memory.protect(region.addr_range, region.protection);
+        }
+        let mut cpu = Cpu::with_custom_ops(self.instructions, &main_memory, custom_ops);
+        cpu.set_units(self.machine.units);
+        cpu.set_word_width(word_width);
+        cpu.interrupts.actions = self.interrupt_actions;
+        for (register, value) in self.registers {
+            cpu.register_bank.components[register].value = word_width.wrap(value);
+        }
+        Ok(cpu)
+    }
+
+    pub fn symbol(&self, name: &str) -> Option<Symbol> {
+        self.symbols.get(name).copied()
+    }
+
+    pub fn to_bytes(&self) -> Result<Vec<u8>, ProgramImageError> {
+        let mut sections: Vec<([u8; 4], Vec<u8>)> = Vec::new();
+
+        let machine = [
+            self.machine.alu_count as u32,
+            self.machine.register_count as u32,
+            self.machine.word_bits,
+            self.machine.program_counter_register as u32,
+        ];
+        let mut machine: Vec<u8> = machine.iter().flat_map(|field| field.to_le_bytes()).collect();
+        machine.extend((0..self.machine.alu_count).map(|alu| self.machine.unit(alu) as u8));
+        sections.push((MACHINE_SECTION, machine));
+        let word_bytes = word_bytes(&self.machine);
+        let words_bytes = |words: &[Word]| words.iter()
+            .flat_map(|word| word.to_le_bytes()[..word_bytes].to_vec())
+            .collect();
+        let code = encode_program(&self.instructions)
+            .map_err(|(instruction, error)| ProgramImageError::Encode { instruction, error })?;
+        sections.push((CODE_SECTION, words_bytes(&code)));
+        sections.push((DATA_SECTION, words_bytes(&self.data)));
+
+        let mut registers = Vec::new();
+        for (register, value) in &self.registers {
+            registers.extend((*register as u32).to_le_bytes());
+            registers.extend(&value.to_le_bytes()[..word_bytes]);
+        }
+        sections.push((REGISTERS_SECTION, registers));
+
+        let mut regions = Vec::new();
+        for region in &self.protection_regions {
+            let protection = match region.protection {
+                MemoryProtection::NoAccess  => 0_u32,
+                MemoryProtection::ReadOnly  => 1,
+                MemoryProtection::ReadWrite => 2,
+            };
+            for field in [region.addr_range.start as u32, region.addr_range.end as u32, protection] {
+                regions.extend(field.to_le_bytes());
+            }
+        }
+        sections.push((PROTECTION_SECTION, regions));
+
+        let mut symbols = Vec::new();
+        for (name, symbol) in &self.symbols {
+            let section = match symbol.section {
+                SymbolSection::Code     => 0_u8,
+                SymbolSection::Data     => 1,
+                SymbolSection::Register => 2,
+            };
+            symbols.push(section);
+            symbols.extend((symbol.value as u32).to_le_bytes());
+            symbols.extend((name.len() as u16).to_le_bytes());
+            symbols.extend(name.as_bytes());
+        }
+        sections.push((SYMBOLS_SECTION, symbols));
+
+        let mut interrupts = Vec::new();
+        for (line, action) in self.interrupt_actions.iter().enumerate() {
+            let (kind, value) = match action {
+                InterruptAction::Masked                 => continue,
+                InterruptAction::Activate { register }  => (0_u8, *register),
+                InterruptAction::Vector { handler }     => (1, *handler),
+            };
+            interrupts.extend([line as u8, kind]);
+            interrupts.extend((value as u32).to_le_bytes());
+        }
+        sections.push((INTERRUPTS_SECTION, interrupts));
+
+        let mut bytes = MAGIC.to_vec();
+        bytes.extend(VERSION.to_le_bytes());
+        bytes.extend((sections.len() as u32).to_le_bytes());
+        for (tag, payload) in sections {
+            bytes.extend(tag);
+            bytes.extend((payload.len() as u32).to_le_bytes());
+            bytes.extend(payload);
+        }
+        Ok(bytes)
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, ProgramImageError> {
+        let mut reader = ByteReader { bytes };
+        if reader.take(MAGIC.len())? != MAGIC {
+            return Err(ProgramImageError::BadMagic);
+        }
+        let version = reader.u32()?;
+        if version != VERSION {
+            return Err(ProgramImageError::UnsupportedVersion(version));
+        }
+
+        let mut image = ProgramImage::new(Vec::new(), Vec::new());
+        let mut has_machine = false;
+        let mut has_code = false;
+
+        let section_count = reader.u32()?;
+        for _ in 0..section_count {
+            let tag: [u8; 4] = reader.take(4)?.try_into().unwrap();
+            let len = reader.u32()? as usize;
+            let mut payload = ByteReader { bytes: reader.take(len)? };
+            // the width of the words these sections hold is only known from the machine section
+            let needs_machine = |section| if has_machine {
+                Ok(())
+            } else {
+                Err(malformed(section, "comes before the machine section"))
+            };
+
+            match tag {
+                MACHINE_SECTION => {
+                    image.machine = MachineConfig {
+                        alu_count                   : payload.u32()? as usize,
+                        register_count              : payload.u32()? as usize,
+                        word_bits                   : payload.u32()?,
+                        program_counter_register    : payload.u32()? as usize,
+                        units                       : [UnitKind::General; ALU_COUNT],
+                    };
+                    // the unit kinds may be left out, every unit being general then
+                    for alu in 0..image.machine.alu_count.min(payload.bytes.len()) {
+                        let unit = UnitKind::from_index(payload.u8()? as usize)
+                            .ok_or(malformed("machine", "unknown unit kind"))?;
+                        if let Some(slot) = image.machine.units.get_mut(alu) {
+                            *slot = unit;
+                        }
+                    }
+                    has_machine = true;
+                }
+                CODE_SECTION => {
+                    needs_machine("code")?;
+                    let words = payload.words(word_bytes(&image.machine))
+                        .map_err(|_| malformed("code", "partial word"))?;
+                    image.instructions = decode_program(&words)
+                        .map_err(|(instruction, error)| ProgramImageError::Decode { instruction, error })?;
+                    has_code = true;
+                }
+                DATA_SECTION => {
+                    needs_machine("data")?;
+                    image.data = payload.words(word_bytes(&image.machine))
+                        .map_err(|_| malformed("data", "partial word"))?;
+                }
+                REGISTERS_SECTION => {
+                    needs_machine("registers")?;
+                    while !payload.bytes.is_empty() {
+                        let register = payload.u32()? as usize;
+                        let value = payload.word(word_bytes(&image.machine))?;
+                        image.registers.push((register, value));
+                    }
+                }
+                PROTECTION_SECTION => {
+                    while !payload.bytes.is_empty() {
+                        let start = payload.u32()? as usize;
+                        let end = payload.u32()? as usize;
+                        let protection = match payload.u32()? {
+                            0 => MemoryProtection::NoAccess,
+                            1 => MemoryProtection::ReadOnly,
+                            2 => MemoryProtection::ReadWrite,
+                            _ => return Err(malformed("protection", "unknown protection")),
+                        };
+                        image.protection_regions.push(ProtectionRegion { addr_range: start..end, protection });
+                    }
+                }
+                SYMBOLS_SECTION => {
+                    while !payload.bytes.is_empty() {
+                        let section = match payload.u8()? {
+                            0 => SymbolSection::Code,
+                            1 => SymbolSection::Data,
+                            2 => SymbolSection::Register,
+                            _ => return Err(malformed("symbols", "unknown symbol section")),
+                        };
+                        let value = payload.u32()? as usize;
+                        let name_len = payload.u16()? as usize;
+                        let name = std::str::from_utf8(payload.take(name_len)?)
+                            .map_err(|_| malformed("symbols", "name isn't UTF-8"))?;
+                        image.symbols.insert(name.to_owned(), Symbol { section, value });
+                    }
+                }
+                INTERRUPTS_SECTION => {
+                    while !payload.bytes.is_empty() {
+                        let line = payload.u8()? as usize;
+                        let kind = payload.u8()?;
+                        let value = payload.u32()? as usize;
+                        let action = match kind {
+                            0 => InterruptAction::Activate { register: value },
+                            1 => InterruptAction::Vector { handler: value },
+                            _ => return Err(malformed("interrupts", "unknown interrupt action")),
+                        };
+                        *image.interrupt_actions.get_mut(line)
+                            .ok_or(malformed("interrupts", "unknown interrupt line"))? = action;
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        if !has_machine {
+            return Err(ProgramImageError::MissingSection("machine"));
+        }
+        if !has_code {
+            return Err(ProgramImageError::MissingSection("code"));
+        }
+        Ok(image)
+    }
+}
+
+/// Bytes per word in an image for `machine`
+fn word_bytes(machine: &MachineConfig) -> usize {
+    if machine.word_bits > 32 { 8 } else { 4 }
+}
+
+fn malformed(section: &'static str, reason: &'static str) -> ProgramImageError {
+    ProgramImageError::Malformed { section, reason }
+}
+
+struct ByteReader<'a> {
+    bytes   : &'a [u8],
+}
+
+impl<'a> ByteReader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], ProgramImageError> {
+        if self.bytes.len() < len {
+            return Err(ProgramImageError::Truncated);
+        }
+        let (taken, rest) = self.bytes.split_at(len);
+        self.bytes = rest;
+        Ok(taken)
+    }
+
+    fn u8(&mut self) -> Result<u8, ProgramImageError> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16, ProgramImageError> {
+        Ok(u16::from_le_bytes(self.take(2)?.try_into().unwrap()))
+    }
+
+    fn u32(&mut self) -> Result<u32, ProgramImageError> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    /// A sign-extended word of `word_bytes` bytes
+    fn word(&mut self, word_bytes: usize) -> Result<Word, ProgramImageError> {
+        let mut bytes = [0; size_of::<Word>()];
+        bytes[..word_bytes].copy_from_slice(self.take(word_bytes)?);
+        let unused = Word::BITS - 8 * word_bytes as u32;
+        Ok(Word::from_le_bytes(bytes) << unused >> unused)
+    }
+
+    /// Reads words of `word_bytes` bytes up to the end
+    fn words(&mut self, word_bytes: usize) -> Result<Vec<Word>, ProgramImageError> {
+        if !self.bytes.len().is_multiple_of(word_bytes) {
+            return Err(ProgramImageError::Truncated);
+        }
+        let mut words = Vec::with_capacity(self.bytes.len() / word_bytes);
+        while !self.bytes.is_empty() {
+            words.push(self.word(word_bytes)?);
+        }
+        Ok(words)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::word::WordWidth;
+
+    /// An image holding `sections` in order
+    fn image_bytes(sections: &[([u8; 4], Vec<u8>)]) -> Vec<u8> {
+        let mut bytes = MAGIC.to_vec();
+        bytes.extend(VERSION.to_le_bytes());
+        bytes.extend((sections.len() as u32).to_le_bytes());
+        for (tag, payload) in sections {
+            bytes.extend(tag);
+            bytes.extend((payload.len() as u32).to_le_bytes());
+            bytes.extend(payload);
+        }
+        bytes
+    }
+
+    #[test]
+    fn word_sections_before_the_machine_section_are_rejected() {
+        let machine = [32_u32, 64, 64, 63].iter().flat_map(|field| field.to_le_bytes()).collect::<Vec<_>>();
+        let code = encode_program(&[Instruction::ResetAll]).unwrap()
+            .iter()
+            .flat_map(|word| word.to_le_bytes())
+            .collect::<Vec<_>>();
+        let image = ProgramImage::from_bytes(&image_bytes(&[(MACHINE_SECTION, machine.clone()), (CODE_SECTION, code.clone())]))
+            .unwrap();
+        assert_eq!(image.instructions, vec![Instruction::ResetAll]);
+
+        for tag in [CODE_SECTION, DATA_SECTION, REGISTERS_SECTION] {
+            assert!(matches!(
+                ProgramImage::from_bytes(&image_bytes(&[(tag, code.clone()), (MACHINE_SECTION, machine.clone())])),
+                Err(ProgramImageError::Malformed { reason: "comes before the machine section", .. }),
+            ));
+        }
+    }
+
+    #[test]
+    fn images_round_trip_and_refuse_instructions_they_cant_encode() {
+        let mut image = ProgramImage::new(vec![Instruction::SetLiteral { literal: -5, register: 2 }], vec![1, -1]);
+        image.registers.push((3, 7));
+        assert_eq!(ProgramImage::from_bytes(&image.to_bytes().unwrap()).unwrap(), image);
+
+        image.instructions.push(Instruction::LoadConfigFrame { source_addr: 0, count: -1 });
+        assert!(matches!(
+            image.to_bytes(),
+            Err(ProgramImageError::Encode { instruction: 1, error: EncodeError::CountOutOfRange(-1) }),
+        ));
+    }
+
+    #[test]
+    fn narrow_images_round_trip_without_encoded_instructions() {
+        for width in [WordWidth::W8, WordWidth::W16] {
+            let data = vec![width.max(), width.min()];
+            let mut image = ProgramImage::new(vec![Instruction::SetLiteral { literal: -5, register: 2 }], data.clone());
+            image.machine = image.machine.with_word_width(width);
+            let read = ProgramImage::from_bytes(&image.to_bytes().unwrap()).unwrap();
+            assert_eq!(read, image);
+            let mut cpu = read.into_cpu().unwrap();
+            cpu.run(2).unwrap();
+            assert_eq!(cpu.register_bank.components[2].value, -5);
+            assert_eq!(cpu.main_memory.contents(), data);
+
+            for load in [
+                Instruction::LoadInstructions { source_addr: 0, target_addr: 1, count: 1 },
+                Instruction::LoadConfigFrame { source_addr: 0, count: 1 },
+            ] {
+                let mut loading = image.clone();
+                loading.instructions.push(load);
+                assert!(matches!(loading.into_cpu(), Err(ProgramImageError::OutOfRange(_))));
+            }
+            let mut encoded = image.clone();
+            encoded.data = encode_program(&[Instruction::SetLiteral { literal: 1, register: 2 }]).unwrap();
+            assert!(matches!(encoded.into_cpu(), Err(ProgramImageError::OutOfRange(_))));
+        }
+    }
+
+    #[test]
+    fn register_values_are_wrapped_to_the_word_width() {
+        let mut image = ProgramImage::new(vec![], vec![]);
+        image.machine = image.machine.with_word_width(WordWidth::W8);
+        image.registers = vec![(1, 0x1ff), (2, 0x80)];
+        let cpu = image.into_cpu().unwrap();
+        assert_eq!(cpu.register_bank.components[1].value, -1);
+        assert_eq!(cpu.register_bank.components[2].value, -0x80);
+    }
+}
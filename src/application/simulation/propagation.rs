@@ -0,0 +1,171 @@
+use crate::application::simulation::alu::{AluAddress, AluBank, ALU_COUNT};
+use crate::application::simulation::cpu_registers::{register_mask, CpuRegisterBank, RegisterChangeTracker, REGISTER_COUNT};
+use crate::application::simulation::error::SimulationError;
+use crate::application::simulation::scheduling::{mask_bits, AluMask};
+use crate::word::Word;
+use crate::Step;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum PropagationMode {
+    /// ALUs read the registers as they were at the start of the cycle, so what an ALU writes
+    /// reaches the ALUs reading it the next cycle
+    #[default]
+    Registered,
+    /// Combinational ALUs are evaluated until their outputs settle, so values ripple through a
+    /// chain of them within one cycle. Stateful ALUs still read the registers as they were at the
+    /// start of the cycle.
+    Combinational,
+}
+
+/// Evaluates the ALUs of `mask` in address order, each one seeing what the ones before it wrote,
+/// until no register changes. Without feedback between them they settle within one pass per ALU,
+/// so still changing after that means they form a loop, reported with the ALUs on it. Once they
+/// settled, two of them driving a register with different values is a write conflict.
+pub fn settle_combinational(
+    alu_bank        : &mut AluBank,
+    register_bank   : &mut CpuRegisterBank,
+    mask            : AluMask,
+    cycle           : Step,
+) -> Result<(), SimulationError> {
+    let mut changes = RegisterChangeTracker::new(register_bank);
+    let mut changed = 0;
+    // the last pass only confirms nothing changes anymore
+    for _ in 0..=mask.count_ones() {
+        // the first ALU driving each register this pass, and the value it drove
+        let mut drivers: [Option<(AluAddress, Word)>; REGISTER_COUNT] = [None; REGISTER_COUNT];
+        let mut conflict = None;
+        for ix in mask_bits(mask) {
+            let alu = &mut alu_bank.components[ix];
+            alu.read_inputs(register_bank);
+            alu.settle(cycle)?;
+            for (register, value) in alu.driven_outputs() {
+                register_bank.components[register].write(value);
+                match drivers[register] {
+                    Some((driver, driven)) if driver != ix && driven != value  => {
+                        conflict.get_or_insert((register, driver, ix));
+                    }
+                    Some(_) => {}
+                    None    => drivers[register] = Some((ix, value)),
+                }
+            }
+        }
+        changed = changes.take_changed(register_bank);
+        if changed == 0 {
+            return match conflict {
+                Some((register, first, second)) =>
+                    Err(SimulationError::WriteConflict { cycle, register, alus: 1 << first | 1 << second }),
+                None                            => Ok(()),
+            };
+        }
+    }
+
+    let mut alus = alus_on_loops(alu_bank, mask);
+    if alus == 0 {
+        // drivers fighting over a register without a loop between them
+        alus = mask_bits(mask)
+            .filter(|ix| register_mask(alu_bank.components[*ix].port_table.outputs) & changed != 0)
+            .fold(0, |alus, ix| alus | 1 << ix);
+    }
+    Err(SimulationError::CombinationalLoop { cycle, alus })
+}
+
+/// The ALUs of `mask` that reach themselves through the registers they write and read
+fn alus_on_loops(alu_bank: &AluBank, mask: AluMask) -> AluMask {
+    let mut reach = [0 as AluMask; ALU_COUNT];
+    for from in mask_bits(mask) {
+        let outputs = register_mask(alu_bank.components[from].port_table.outputs);
+        reach[from] = mask_bits(mask)
+            .filter(|to| register_mask(alu_bank.components[*to].port_table.inputs) & outputs != 0)
+            .fold(0, |reach, to| reach | 1 << to);
+    }
+    // the transitive closure
+    loop {
+        let mut grown = false;
+        for from in mask_bits(mask) {
+            let reached = mask_bits(reach[from]).fold(reach[from], |reached, via| reached | reach[via]);
+            grown |= reached != reach[from];
+            reach[from] = reached;
+        }
+        if !grown {
+            break;
+        }
+    }
+    mask_bits(mask)
+        .filter(|ix| reach[*ix] & 1 << ix != 0)
+        .fold(0, |alus, ix| alus | 1 << ix)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::application::simulation::alu::AluOperation;
+    use crate::application::simulation::cpu_registers::CpuRegisterAddress;
+    use crate::application::simulation::instruction::Instruction;
+    use crate::application::simulation::simulation::Cpu;
+
+    fn not(alu_addr: usize, data_input: CpuRegisterAddress, data_output: CpuRegisterAddress) -> Instruction {
+        Instruction::SetAluConfig {
+            alu_addr,
+            alu_config: AluOperation::Not { activation_input: 0, data_input, data_output, activation_output: None },
+        }
+    }
+
+    /// Runs `program` combinationally, then activates its ALUs for a cycle
+    fn run(mut program: Vec<Instruction>) -> (Cpu, Result<Step, SimulationError>) {
+        program.push(Instruction::SetLiteral { literal: !0, register: 0 });
+        program.push(Instruction::NoOp);
+        let mut cpu = Cpu::new(program, vec![]);
+        cpu.propagation_mode = PropagationMode::Combinational;
+        let result = cpu.run(20);
+        (cpu, result)
+    }
+
+    #[test]
+    fn chains_settle_within_a_cycle() {
+        // wired against the address order, so each pass only moves the value one ALU down
+        let (cpu, result) = run(vec![
+            Instruction::SetLiteral { literal: 5, register: 1 },
+            not(0, 4, 5),
+            not(1, 3, 4),
+            not(2, 2, 3),
+            not(3, 1, 2),
+        ]);
+        result.unwrap();
+        let registers = &cpu.register_bank.components;
+        assert_eq!([2, 3, 4, 5].map(|register| registers[register].value), [!5, 5, !5, 5]);
+    }
+
+    #[test]
+    fn loops_name_only_the_alus_on_them() {
+        let (_, result) = run(vec![
+            not(4, 1, 2),
+            not(5, 2, 3),
+            not(6, 3, 1),
+            // fed by the loop, but not on it
+            not(7, 2, 9),
+        ]);
+        assert!(matches!(result, Err(SimulationError::CombinationalLoop { alus: 0b111_0000, .. })));
+    }
+
+    #[test]
+    fn alus_driving_a_register_with_different_values_conflict() {
+        let (_, result) = run(vec![
+            Instruction::SetLiteral { literal: 1, register: 1 },
+            Instruction::SetLiteral { literal: 2, register: 2 },
+            not(3, 1, 5),
+            not(6, 2, 5),
+            // reads the contested register, without that making a loop
+            not(4, 5, 7),
+        ]);
+        assert!(matches!(result, Err(SimulationError::WriteConflict { register: 5, alus: 0b100_1000, .. })));
+
+        let (cpu, result) = run(vec![
+            Instruction::SetLiteral { literal: 1, register: 1 },
+            Instruction::SetLiteral { literal: 1, register: 2 },
+            not(3, 1, 5),
+            not(6, 2, 5),
+        ]);
+        result.unwrap();
+        assert_eq!(cpu.register_bank.components[5].value, !1);
+    }
+}
@@ -0,0 +1,126 @@
+use std::iter;
+use std::ops::Range;
+use crate::application::simulation::alu::{AluBank, AluOperation, ALU_COUNT};
+use crate::application::simulation::cpu_registers::{RegisterMask, REGISTER_COUNT};
+
+/// One bit per ALU, bit `n` standing for the ALU at address `n`
+pub type AluMask = u64;
+const _: () = assert!(ALU_COUNT <= AluMask::BITS as usize);
+
+pub const ALL_ALUS: AluMask = AluMask::MAX >> (AluMask::BITS as usize - ALU_COUNT);
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum SchedulingMode {
+    /// Every ALU is evaluated every cycle
+    #[default]
+    FullScan,
+    /// Only ALUs whose input registers changed since the previous cycle, that were just
+    /// configured, or that hold a stateful operation are evaluated
+    EventDriven,
+}
+
+/// The mask selecting the ALUs of `alus`, clamped to the existing ones
+pub fn alu_range_mask(alus: Range<usize>) -> AluMask {
+    let below = |n: usize| u32::try_from(n).ok()
+        .and_then(|n| (1 as AluMask).checked_shl(n))
+        .map_or(AluMask::MAX, |bit| bit - 1);
+    below(alus.end) & !below(alus.start) & ALL_ALUS
+}
+
+/// Iterates over the positions of the set bits of a mask, lowest first
+pub fn mask_bits(mut mask: u64) -> impl Iterator<Item=usize> {
+    iter::from_fn(move || {
+        if mask == 0 {
+            return None;
+        }
+        let ix = mask.trailing_zeros() as usize;
+        mask &= mask - 1;
+        Some(ix)
+    })
+}
+
+/// Summary of the fabric's configuration, rebuilt whenever an ALU is reconfigured
+pub struct AluSchedule {
+    /// ALUs holding an operation other than `NoOp`
+    pub configured  : AluMask,
+    /// ALUs whose outputs may change even if their inputs don't
+    pub stateful    : AluMask,
+    /// ALUs configured since they were last evaluated
+    pub pending     : AluMask,
+    /// ALUs whose operation accesses main memory
+    pub memory      : AluMask,
+    /// ALUs reading each register
+    readers         : [AluMask; REGISTER_COUNT],
+}
+
+impl Default for AluSchedule {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl AluSchedule {
+    pub fn new() -> Self {
+        Self {
+            configured  : 0,
+            stateful    : 0,
+            pending     : 0,
+            memory      : 0,
+            readers     : [0; REGISTER_COUNT],
+        }
+    }
+
+    pub fn rebuild(&mut self, alu_bank: &AluBank) {
+        *self = Self::new();
+        for alu in alu_bank.components.iter() {
+            let alu_bit = 1 << alu.addr;
+            if alu.operation != AluOperation::NoOp {
+                self.configured |= alu_bit;
+            }
+            if !alu.is_combinational() {
+                self.stateful |= alu_bit;
+            }
+            if alu.operation.kind().accesses_memory() {
+                self.memory |= alu_bit;
+            }
+            if alu.pending_evaluation {
+                self.pending |= alu_bit;
+            }
+            for register in mask_bits(alu.port_table.input_mask) {
+                self.readers[register] |= alu_bit;
+            }
+        }
+    }
+
+    /// The ALUs that must be evaluated given the registers changed since the previous cycle
+    pub fn scheduled(&self, changed_registers: RegisterMask) -> AluMask {
+        mask_bits(changed_registers)
+            .fold(self.pending | self.stateful, |mask, register| mask | self.readers[register])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::application::simulation::simulation::Cpu;
+    use crate::application::simulation::test_support::{mixed_program, trace};
+
+    fn cpu(scheduling_mode: SchedulingMode) -> Cpu {
+        let mut cpu = Cpu::new(mixed_program(), vec![0; 8]);
+        cpu.scheduling_mode = scheduling_mode;
+        cpu
+    }
+
+    #[test]
+    fn event_driven_matches_full_scan_every_cycle() {
+        let full_scan = trace(cpu(SchedulingMode::FullScan), 60);
+        let event_driven = trace(cpu(SchedulingMode::EventDriven), 60);
+        for (full_scan, event_driven) in full_scan.iter().zip(&event_driven) {
+            assert_eq!(full_scan, event_driven);
+        }
+
+        let last = full_scan.last().unwrap();
+        assert!(last.memory.iter().all(|&word| word != 0), "every word was written");
+        assert_ne!(last.registers[16], 0, "the reconfigured ALU ran");
+    }
+}
@@ -1,14 +1,20 @@
-use std::{array, iter};
 use std::ops::Not;
-use itertools::Itertools;
-use crate::application::simulation::alu::{AluCore, AluOperation, AluBank, ALU_COUNT};
-use crate::application::simulation::controller::{AluConfigWriter, Controller};
-use crate::application::simulation::cpu_registers::{CpuRegisterBank, REGISTER_COUNT};
+use crate::application::simulation::alu::{AluBank, CustomOps, ALU_COUNT};
+use crate::application::simulation::cache::CacheConfig;
+use crate::application::simulation::error::SimulationError;
+use crate::application::simulation::controller::Controller;
+use crate::application::simulation::cpu_registers::{CpuRegisterBank, RegisterChangeTracker, REGISTER_COUNT};
 use crate::application::simulation::instruction::Instruction;
-use crate::application::simulation::instruction_reader::{InstructionMemory, InstructionReader};
+use crate::application::simulation::instruction_reader::InstructionMemory;
+use crate::application::simulation::interrupts::{InterruptController, InterruptLines};
+use crate::application::simulation::machine::UnitKind;
 use crate::application::simulation::main_memory::MainMemory;
+use crate::application::simulation::performance::{AluReport, ControllerCounters, MemoryTraffic, PerformanceReport};
+use crate::application::simulation::parallel::{AluExecutor, AluWorkerPool};
+use crate::application::simulation::propagation::{settle_combinational, PropagationMode};
+use crate::application::simulation::scheduling::{mask_bits, AluSchedule, SchedulingMode, ALL_ALUS};
 use crate::{Step};
-use crate::word::Word;
+use crate::word::{ToWord, Word, WordWidth};
 
 pub struct Cpu {
     pub alu_bank: AluBank,
@@ -16,11 +22,143 @@ pub struct Cpu {
     pub controller          : Controller,
     pub instruction_memory  : InstructionMemory,
     pub main_memory         : MainMemory,
+    pub cycle               : Step,
+    pub scheduling_mode     : SchedulingMode,
+    pub executor            : AluExecutor,
+    pub propagation_mode    : PropagationMode,
+    pub interrupts          : InterruptController,
+    register_changes        : RegisterChangeTracker,
+    alu_schedule            : AluSchedule,
+    worker_pool             : Option<AluWorkerPool>,
 }
 impl Cpu {
+    pub fn new(program: Vec<Instruction>, data: Vec<Word>) -> Self {
+        Self::with_main_memory(program, &MainMemory::new(data))
+    }
+
+    /// Builds a CPU over a memory that may be shared with other CPUs
+    pub fn with_main_memory(program: Vec<Instruction>, main_memory: &MainMemory) -> Self {
+        Self::with_custom_ops(program, main_memory, &CustomOps::new())
+    }
+
+    /// Builds a CPU whose ALUs can be configured with the operations of `custom_ops`
+    pub fn with_custom_ops(program: Vec<Instruction>, main_memory: &MainMemory, custom_ops: &CustomOps) -> Self {
+        let mut main_memory = main_memory.clone();
+        let instruction_memory = InstructionMemory::new(program);
+        let alu_bank = AluBank::new(&mut main_memory, custom_ops);
+        let mut controller = Controller::new(&instruction_memory, &main_memory);
+        controller.custom_ops = custom_ops.clone();
+        let register_bank = CpuRegisterBank::new();
+        let register_changes = RegisterChangeTracker::new(&register_bank);
+
+        Cpu {
+            alu_bank,
+            register_bank,
+            controller,
+            instruction_memory,
+            main_memory,
+            cycle: 0,
+            scheduling_mode: SchedulingMode::default(),
+            executor: AluExecutor::default(),
+            propagation_mode: PropagationMode::default(),
+            interrupts: InterruptController::new(),
+            register_changes,
+            alu_schedule: AluSchedule::new(),
+            worker_pool: None,
+        }
+    }
+
+    /// Executes until the controller stops or `max_cycles` is reached, returning the cycles executed
+    pub fn run(&mut self, max_cycles: Step) -> Result<Step, SimulationError> {
+        let start = self.cycle;
+        while self.cycle - start < max_cycles && self.execute()? {}
+        Ok(self.cycle - start)
+    }
+
+    /// Gives every ALU a private cache in front of main memory, or removes them for `None`. The
+    /// writes held in `WriteBack` caches only show in `main_memory` once their lines are evicted
+    /// or `flush_alu_caches` is called.
+    pub fn set_alu_caches(&mut self, config: Option<CacheConfig>) {
+        for alu in self.alu_bank.components.iter_mut() {
+            alu.main_memory.set_cache(config);
+        }
+    }
+
+    /// Writes the dirty lines of every ALU cache back to main memory
+    pub fn flush_alu_caches(&mut self) {
+        for alu in self.alu_bank.components.iter_mut() {
+            alu.main_memory.flush_cache();
+        }
+    }
+
+    /// Declares the unit in each ALU slot, the controller faulting on operations a unit can't run
+    pub fn set_units(&mut self, units: [UnitKind; ALU_COUNT]) {
+        self.controller.units = units;
+    }
+
+    /// Sets the width the ALUs compute with, wrapping the registers and main memory to it. Words
+    /// of main memory built by `MainMemory::new` were already wrapped to the default width, so
+    /// wider words need `MainMemory::with_word_width`.
+    pub fn set_word_width(&mut self, word_width: WordWidth) {
+        for alu in self.alu_bank.components.iter_mut() {
+            alu.word_width = word_width;
+        }
+        for register in self.register_bank.components.iter_mut() {
+            register.value = word_width.wrap(register.value);
+        }
+        self.controller.word_width = word_width;
+        self.main_memory.set_word_width(word_width);
+    }
+
+    pub fn word_width(&self) -> WordWidth {
+        self.controller.word_width
+    }
+
+    /// A handle the host raises the interrupt lines of the CPU with, from any thread
+    pub fn interrupt_lines(&self) -> InterruptLines {
+        self.interrupts.lines.clone()
+    }
+
+    pub fn memory_traffic(&self) -> MemoryTraffic {
+        let mut total = self.controller.memory_traffic();
+        for alu in self.alu_bank.components.iter() {
+            total += alu.main_memory.traffic;
+        }
+        total
+    }
+
+    pub fn performance_report(&self) -> PerformanceReport {
+        PerformanceReport {
+            cycles      : self.cycle,
+            alus        : self.alu_bank.components
+                .iter()
+                .map(|alu| AluReport {
+                    addr            : alu.addr,
+                    counters        : alu.counters.clone(),
+                    memory_traffic  : alu.main_memory.traffic,
+                    cache           : alu.main_memory.cache.as_ref().map(|cache| cache.stats),
+                })
+                .collect(),
+            controller  : ControllerCounters {
+                memory_traffic  : self.controller.memory_traffic(),
+                ..self.controller.counters
+            },
+        }
+    }
+
+    /// Executes one cycle, returning whether the controller is still running. A fault leaves the
+    /// cycle partially executed.
+    pub fn execute(&mut self) -> Result<bool, SimulationError> {
+        // interrupts raised up to now are seen by this cycle
+        let raised = self.interrupts.lines.take() | self.main_memory.poll_interrupts(self.cycle);
+        let activated = self.interrupts.raise(raised);
+        for register in (0..REGISTER_COUNT).filter(|register| activated & 1 << register != 0) {
+            self.register_bank.components[register].write(true.to_word());
+        }
+        if self.controller.accepts_interrupt() && let Some(handler) = self.interrupts.take_vector() {
+            self.controller.vector_interrupt(handler);
+        }
 
-    #[must_use]
-    pub fn execute(&mut self) -> bool {
         if let Some(mut controller_read_req) =
             self.controller.cpu_registers_reader.get_read_request() {
             controller_read_req.satisfy( &self.register_bank)
@@ -36,35 +174,57 @@ impl Cpu {
             controller_pc_read_req.satisfy(&self.register_bank);
         }
 
-        self.controller
+        let reconfigured = self.controller
             .alu_config_writer
             .configure_alus(&mut self.alu_bank);
+        if reconfigured {
+            self.alu_schedule.rebuild(&self.alu_bank);
+        }
 
+        let changed_registers = self.register_changes.take_changed(&self.register_bank);
+        let scheduled = match self.scheduling_mode {
+            SchedulingMode::FullScan    => ALL_ALUS,
+            SchedulingMode::EventDriven => self.alu_schedule.scheduled(changed_registers),
+        };
+
+        let (evaluated, settled) = match self.propagation_mode {
+            PropagationMode::Registered     => (scheduled, 0),
+            // combinational ALUs are settled once the stateful ones wrote their outputs
+            PropagationMode::Combinational  => (
+                scheduled & self.alu_schedule.stateful,
+                self.alu_schedule.configured & !self.alu_schedule.stateful,
+            ),
+        };
 
         // give alus the requested data
-        for alu in self.alu_bank.components.iter_mut(){
-            let mut reqs = alu.collect_read_requests();
-            for (port, req) in &mut reqs{
-                req.satisfy(&self.register_bank);
-            }
-            // alu_reads.push(reqs);
+        for ix in mask_bits(evaluated){
+            self.alu_bank.components[ix].read_inputs(&self.register_bank);
         }
 
-        if self.controller.execute().not(){
-            return false;
+        if self.controller.execute(self.cycle)?.not(){
+            return Ok(false);
         };
 
 
-        for alu in self.alu_bank.components.iter_mut(){
-            alu.execute();
+        self.executor.execute(
+            &mut self.alu_bank,
+            &mut self.worker_pool,
+            evaluated,
+            self.alu_schedule.memory,
+            self.cycle,
+        )?;
+        self.alu_schedule.pending = 0;
+
+        // alus that weren't evaluated keep writing the outputs of their last evaluation
+        for ix in mask_bits((self.alu_schedule.configured | evaluated) & !settled){
+            self.alu_bank.components[ix].write_outputs(&mut self.register_bank);
         }
 
-        for alu in self.alu_bank.components.iter_mut(){
-            let mut reqs = alu.collect_write_requests();
-            for (port, req) in &mut reqs{
-                req.satisfy(&mut self.register_bank);
-            }
-            // alu_reads.push(reqs);
+        if settled != 0 {
+            settle_combinational(&mut self.alu_bank, &mut self.register_bank, settled, self.cycle)?;
+        }
+        for ix in mask_bits(self.alu_schedule.configured & !evaluated){
+            self.alu_bank.components[ix].skip();
         }
 
         if let Some(write_req) = self.controller.cpu_registers_writer.get_write_request(){
@@ -75,6 +235,7 @@ impl Cpu {
             write_pc_req.satisfy(&mut self.register_bank);
         }
 
-        true
+        self.cycle += 1;
+        Ok(true)
     }
 }
@@ -0,0 +1,318 @@
+use crate::Step;
+use crate::application::simulation::alu::CustomOps;
+use crate::application::simulation::error::SimulationError;
+use crate::application::simulation::instruction::Instruction;
+use crate::application::simulation::main_memory::MainMemory;
+use crate::application::simulation::performance::PerformanceReport;
+use crate::application::simulation::simulation::Cpu;
+use crate::word::{Word, WordWidth};
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Interleaving {
+    /// Every CPU executes one step per system cycle
+    Lockstep,
+    /// A single CPU executes per system cycle, each one running `quantum` steps before handing
+    /// over to the next
+    RoundRobin {
+        quantum : Step,
+    },
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ArbitrationPolicy {
+    /// Lower CPU indices always get the memory first, so a CPU accessing it every cycle starves
+    /// the ones above
+    FixedPriority,
+    /// The CPU getting the memory first rotates every system cycle
+    RoundRobin,
+}
+
+/// Serializes the CPUs' accesses to the shared memory: it serves one CPU per system cycle, and
+/// the memory ALUs of the others stall and retry in a later cycle. The CPUs step in priority
+/// order, so the first one to reach the memory gets it.
+pub struct MemoryArbiter {
+    pub policy              : ArbitrationPolicy,
+    /// Per CPU, the system cycles in which it accessed memory
+    pub grants              : Vec<u64>,
+    /// Per CPU, the system cycles in which its accesses stalled behind another CPU's
+    pub stalls              : Vec<u64>,
+    /// System cycles in which a CPU stalled behind another
+    pub contended_cycles    : u64,
+    first                   : usize,
+    contended               : bool,
+}
+
+impl MemoryArbiter {
+    pub fn new(policy: ArbitrationPolicy, cpu_count: usize) -> Self {
+        Self {
+            policy,
+            grants          : vec![0; cpu_count],
+            stalls          : vec![0; cpu_count],
+            contended_cycles: 0,
+            first           : 0,
+            contended       : false,
+        }
+    }
+
+    /// The CPU granted the shared memory first in the current system cycle
+    pub fn first(&self) -> usize {
+        self.first
+    }
+
+    fn record(&mut self, cpu: usize, accessed: bool, stalled: bool) {
+        self.grants[cpu] += accessed as u64;
+        self.stalls[cpu] += stalled as u64;
+        self.contended |= stalled;
+    }
+
+    fn end_cycle(&mut self) {
+        if self.contended {
+            self.contended_cycles += 1;
+            self.contended = false;
+        }
+        if self.policy == ArbitrationPolicy::RoundRobin {
+            self.first = (self.first + 1) % self.grants.len();
+        }
+    }
+}
+
+/// Several CPUs stepping over one shared `MainMemory`, which `arbiter` serves to one CPU per
+/// system cycle
+pub struct System {
+    pub cpus            : Vec<Cpu>,
+    pub main_memory     : MainMemory,
+    pub interleaving    : Interleaving,
+    pub arbiter         : MemoryArbiter,
+    pub cycle           : Step,
+    halted              : Vec<bool>,
+    current             : usize,
+    current_steps       : Step,
+}
+
+impl System {
+    /// Builds a CPU per program, with words of `word_width` and the operations of `custom_ops`
+    pub fn new(
+        programs        : Vec<Vec<Instruction>>,
+        data            : Vec<Word>,
+        word_width      : WordWidth,
+        custom_ops      : &CustomOps,
+        interleaving    : Interleaving,
+        policy          : ArbitrationPolicy,
+    ) -> Self {
+        let main_memory = MainMemory::with_word_width(data, word_width);
+        main_memory.set_arbitrated(true);
+        let cpus = programs
+            .into_iter()
+            .enumerate()
+            .map(|(ix, program)| {
+                let mut cpu = Cpu::with_custom_ops(program, &main_memory, custom_ops);
+                cpu.set_word_width(word_width);
+                for alu in cpu.alu_bank.components.iter_mut() {
+                    alu.main_memory.requester = ix;
+                }
+                cpu
+            })
+            .collect::<Vec<_>>();
+        let cpu_count = cpus.len();
+
+        Self {
+            cpus,
+            main_memory,
+            interleaving,
+            arbiter         : MemoryArbiter::new(policy, cpu_count),
+            cycle           : 0,
+            halted          : vec![false; cpu_count],
+            current         : 0,
+            current_steps   : 0,
+        }
+    }
+
+    pub fn is_halted(&self) -> bool {
+        self.halted.iter().all(|halted| *halted)
+    }
+
+    /// Executes one system cycle, returning whether any CPU is still running
+    pub fn execute(&mut self) -> Result<bool, SimulationError> {
+        if self.is_halted() {
+            return Ok(false);
+        }
+        // the CPUs' own cycles drift apart under round-robin, so the timing model claims ports
+        // in system cycles
+        self.main_memory.set_system_cycle(Some(self.cycle));
+
+        match self.interleaving {
+            Interleaving::Lockstep => {
+                let cpu_count = self.cpus.len();
+                for offset in 0..cpu_count {
+                    self.step_cpu((self.arbiter.first + offset) % cpu_count)?;
+                }
+                self.arbiter.end_cycle();
+            }
+            Interleaving::RoundRobin { quantum } => {
+                while self.halted[self.current] {
+                    self.switch_cpu();
+                }
+                self.step_cpu(self.current)?;
+                self.arbiter.end_cycle();
+
+                self.current_steps += 1;
+                if self.current_steps >= quantum || self.halted[self.current] {
+                    self.switch_cpu();
+                }
+            }
+        }
+
+        self.cycle += 1;
+        Ok(!self.is_halted())
+    }
+
+    /// Executes until every CPU stopped or `max_cycles` is reached, returning the cycles executed
+    pub fn run(&mut self, max_cycles: Step) -> Result<Step, SimulationError> {
+        let start = self.cycle;
+        while self.cycle - start < max_cycles && self.execute()? {}
+        Ok(self.cycle - start)
+    }
+
+    pub fn performance_reports(&self) -> Vec<PerformanceReport> {
+        self.cpus.iter().map(Cpu::performance_report).collect()
+    }
+
+    /// Steps a CPU if it's still running, recording its memory accesses with the arbiter
+    fn step_cpu(&mut self, ix: usize) -> Result<(), SimulationError> {
+        if self.halted[ix] {
+            return Ok(());
+        }
+        let cpu = &mut self.cpus[ix];
+        let before = cpu.memory_traffic();
+        if !cpu.execute()? {
+            self.halted[ix] = true;
+        }
+        let after = cpu.memory_traffic();
+        self.arbiter.record(
+            ix,
+            (after.reads, after.writes) != (before.reads, before.writes),
+            after.arbitration_stalls != before.arbitration_stalls,
+        );
+        Ok(())
+    }
+
+    fn switch_cpu(&mut self) {
+        self.current = (self.current + 1) % self.cpus.len();
+        self.current_steps = 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::num::NonZeroUsize;
+    use super::*;
+    use crate::application::simulation::alu::{AluOperation, CustomAluOperation, CustomOpInputs, CustomOpOutputs, CustomOpPorts};
+    use crate::application::simulation::memory_timing::MemoryTiming;
+
+    #[test]
+    fn round_robin_cpus_claim_bank_ports_in_system_cycles() {
+        // each CPU reads memory once, in its own cycle 3. Those are system cycles 3 and 7, so
+        // the second read finds the port free again.
+        let program = vec![
+            Instruction::SetAluConfig { alu_addr: 0, alu_config: AluOperation::ReadFromMem {
+                activation_input: 5, data_input_0: 1, data_output_0: 2, activation_output: None,
+            }},
+            Instruction::NoOp,
+            Instruction::SetLiteral { literal: !0, register: 5 },
+            Instruction::SetLiteral { literal: 0, register: 5 },
+            Instruction::Jump { addr: 4 },
+        ];
+        let mut system = System::new(
+            vec![program.clone(), program],
+            vec![0; 4],
+            WordWidth::default(),
+            &CustomOps::new(),
+            Interleaving::RoundRobin { quantum: 4 },
+            ArbitrationPolicy::FixedPriority,
+        );
+        system.main_memory.set_timing(Some(MemoryTiming {
+            banks               : NonZeroUsize::MIN,
+            ports_per_bank      : NonZeroUsize::MIN,
+            port_busy_cycles    : NonZeroUsize::new(3).unwrap(),
+            read_latency        : 0,
+            write_latency       : 0,
+        }));
+        system.run(16).unwrap();
+
+        for cpu in &system.cpus {
+            assert_eq!(cpu.memory_traffic().reads, 1);
+            assert_eq!(cpu.memory_traffic().bank_conflicts, 0);
+        }
+    }
+
+    /// Two CPUs whose ALU 0 reads memory every cycle from cycle 2 on
+    fn reading_system(policy: ArbitrationPolicy) -> System {
+        let program = vec![
+            Instruction::SetLiteral { literal: !0, register: 5 },
+            Instruction::SetAluConfig { alu_addr: 0, alu_config: AluOperation::ReadFromMem {
+                activation_input: 5, data_input_0: 1, data_output_0: 2, activation_output: None,
+            }},
+            Instruction::Jump { addr: 2 },
+        ];
+        System::new(
+            vec![program.clone(), program],
+            vec![0; 4],
+            WordWidth::default(),
+            &CustomOps::new(),
+            Interleaving::Lockstep,
+            policy,
+        )
+    }
+
+    #[test]
+    fn one_cpu_accesses_the_shared_memory_per_cycle() {
+        let mut system = reading_system(ArbitrationPolicy::FixedPriority);
+        system.run(10).unwrap();
+        assert_eq!(system.arbiter.grants, vec![8, 0]);
+        assert_eq!(system.arbiter.stalls, vec![0, 8]);
+        assert_eq!(system.arbiter.contended_cycles, 8);
+        assert_eq!(system.cpus[1].memory_traffic().reads, 0);
+        assert_eq!(system.cpus[1].memory_traffic().arbitration_stalls, 8);
+
+        let mut system = reading_system(ArbitrationPolicy::RoundRobin);
+        system.run(10).unwrap();
+        assert_eq!(system.arbiter.grants, vec![4, 4]);
+        assert_eq!(system.arbiter.stalls, vec![4, 4]);
+        assert_eq!(system.arbiter.contended_cycles, 8);
+    }
+
+    struct Idle;
+
+    impl CustomAluOperation for Idle {
+        fn name(&self) -> &str {
+            "IDLE"
+        }
+
+        fn ports(&self) -> CustomOpPorts {
+            CustomOpPorts::default()
+        }
+
+        fn execute(&self, _inputs: CustomOpInputs, _state: &mut [Word; 2]) -> CustomOpOutputs {
+            CustomOpOutputs::default()
+        }
+    }
+
+    #[test]
+    fn systems_take_a_word_width_and_custom_operations() {
+        let mut custom_ops = CustomOps::new();
+        let id = custom_ops.register(Idle).unwrap();
+        let system = System::new(
+            vec![vec![], vec![]],
+            vec![0x1ff],
+            WordWidth::W8,
+            &custom_ops,
+            Interleaving::Lockstep,
+            ArbitrationPolicy::FixedPriority,
+        );
+        assert_eq!(system.main_memory.contents(), vec![-1]);
+        for cpu in &system.cpus {
+            assert_eq!(cpu.word_width(), WordWidth::W8);
+            assert!(cpu.alu_bank.components[0].custom_ops.get(id).is_some());
+        }
+    }
+}
@@ -0,0 +1,80 @@
+use crate::application::simulation::alu::AluOperation;
+use crate::application::simulation::instruction::Instruction;
+use crate::application::simulation::simulation::Cpu;
+use crate::word::Word;
+use crate::Step;
+
+/// The registers and main memory after a cycle
+#[derive(PartialEq, Eq, Debug)]
+pub struct CpuState {
+    pub cycle       : Step,
+    pub registers   : Vec<Word>,
+    pub memory      : Vec<Word>,
+}
+
+impl CpuState {
+    pub fn of(cpu: &Cpu) -> Self {
+        CpuState {
+            cycle       : cpu.cycle,
+            registers   : cpu.register_bank.components.iter().map(|register| register.value).collect(),
+            memory      : cpu.main_memory.contents(),
+        }
+    }
+}
+
+/// A counter driving memory writes and reads through ALUs in different parts of the bank, two
+/// ALUs writing the same register each cycle, and ALUs reconfigured while the fabric runs
+pub fn mixed_program() -> Vec<Instruction> {
+    let add = |data_input_0, data_input_1, data_output_0| AluOperation::Add {
+        activation_input: 0, data_input_0, data_input_1, data_output_0,
+        flags_output: None, activation_output: None,
+    };
+    let mut program = vec![
+        Instruction::SetLiteral { literal: !0, register: 0 },
+        Instruction::SetLiteral { literal: 1, register: 1 },
+        Instruction::SetLiteral { literal: 3, register: 2 },
+        Instruction::SetLiteral { literal: 7, register: 3 },
+        // r10 counts up, r11 = r10 & 7 addresses memory
+        Instruction::SetAluConfig { alu_addr: 0, alu_config: add(10, 1, 10) },
+        Instruction::SetAluConfig { alu_addr: 2, alu_config: AluOperation::And {
+            activation_input: 0, data_input_0: 10, data_input_1: 3, data_output_0: 11,
+            activation_output: None,
+        }},
+        Instruction::SetAluConfig { alu_addr: 17, alu_config: AluOperation::WriteToMem {
+            activation_input: 0, address_input: 11, data_input: 10, activation_output: None,
+        }},
+        Instruction::SetAluConfig { alu_addr: 25, alu_config: AluOperation::ReadFromMem {
+            activation_input: 0, data_input_0: 11, data_output_0: 12, activation_output: Some(14),
+        }},
+        // ALUs 9 and 26 both write r13
+        Instruction::SetAluConfig { alu_addr: 9, alu_config: add(10, 2, 13) },
+        Instruction::SetAluConfig { alu_addr: 26, alu_config: AluOperation::Sub {
+            activation_input: 0, data_input_0: 12, data_input_1: 2, data_output_0: 13,
+            flags_output: Some(15), activation_output: None,
+        }},
+        Instruction::NoOp,
+        Instruction::NoOp,
+        Instruction::NoOp,
+        Instruction::SetAluConfig { alu_addr: 26, alu_config: AluOperation::Mul {
+            activation_input: 0, data_input_0: 12, data_input_1: 11,
+            first_word_output: 16, second_word_output: None, activation_output: None,
+        }},
+        Instruction::NoOp,
+        Instruction::NoOp,
+        Instruction::SetAluConfig { alu_addr: 9, alu_config: AluOperation::NoOp },
+    ];
+    // keeps the controller busy while the fabric runs
+    let end = program.len() as Word;
+    program.push(Instruction::Jump { addr: end });
+    program
+}
+
+/// Steps `cpu` for `cycles` cycles, returning its state after each one
+pub fn trace(mut cpu: Cpu, cycles: Step) -> Vec<CpuState> {
+    (0..cycles)
+        .map(|_| {
+            assert!(cpu.execute().unwrap());
+            CpuState::of(&cpu)
+        })
+        .collect()
+}
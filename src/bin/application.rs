@@ -17,14 +17,12 @@ use strucc::application::grid::cpu::{CpuComponentsGridDefns, CpuGridDefns};
 use strucc::application::grid::grid_limits::GridLimits;
 use strucc::application::grid::path::Path;
 use strucc::application::grid::pos::grid_pos;
-use strucc::application::simulation::alu::{AluBank, AluOperation, ALU_COUNT};
-use strucc::application::simulation::controller::Controller;
-use strucc::application::simulation::cpu_registers::{CpuRegisterBank, REGISTER_COUNT};
+use strucc::application::simulation::alu::{AluOperation, ALU_COUNT};
+use strucc::application::simulation::cpu_registers::REGISTER_COUNT;
+use strucc::application::simulation::memory_image::load_memory_image;
 use strucc::application::simulation::simulation::Cpu;
 use strucc::application::simulation::instruction::Instruction;
-use strucc::application::simulation::instruction_reader::InstructionMemory;
-use strucc::application::simulation::main_memory::MainMemory;
-use strucc::word::Word;
+use strucc::word::{Word, WordWidth};
 
 // arch name: STruCC
 //  Spatially distributed and Structured Computation and Control
@@ -48,7 +46,12 @@ async fn amain() {
         128
     ];
 
-    let data = vec![];
+    // an optional main-memory image, see `load_memory_image` for the formats
+    let data = match std::env::args().nth(1) {
+        Some(path)  => load_memory_image(&path, WordWidth::default())
+            .unwrap_or_else(|error| panic!("couldn't load memory image {path}: {error}")),
+        None        => vec![],
+    };
 
     let screen_size = size(1600, 900);
 
@@ -165,27 +168,7 @@ fn build_full_cpu(
     grid_to_screen_mapper   : &GridToScreenMapper,
 ) -> FullCpu {
 
-    let mut main_memory = MainMemory::new(data);
-
-    let registers = CpuRegisterBank::new();
-
-    let instruction_memory = InstructionMemory::new(program.clone());
-
-    let alus = AluBank::new(&mut  main_memory);
-
-    let controller = Controller::new(
-        &instruction_memory,
-    );
-
-    let cpu = {
-        Cpu {
-            main_memory,
-            alu_bank: alus,
-            register_bank: registers,
-            controller,
-            instruction_memory,
-        }
-    };
+    let cpu = Cpu::new(program, data);
 
 
     let port_drawing_data = PortDrawingDefns {
@@ -0,0 +1,280 @@
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Instant;
+use strucc::application::simulation::alu::{AluOperation, AluPortName, ALU_COUNT};
+use strucc::application::simulation::error::SimulationError;
+use strucc::application::simulation::instruction::Instruction;
+use strucc::application::simulation::parallel::AluExecutor;
+use strucc::application::simulation::scheduling::SchedulingMode;
+use strucc::application::simulation::simulation::Cpu;
+
+// measures simulated cycles per second
+//
+// usage: benchmark [dense|sparse] [full-scan|event-driven] [sequential|parallel=<threads>]
+//  dense : every ALU of the fabric is configured and busy
+//  sparse: a single counter runs while a few ALUs hold results computed from constants
+//
+// The same program is first run through `execute_with_request_maps`, the step as it was before
+// the port tables, then through `Cpu::execute`. A parallel executor is also compared with the
+// sequential one. All runs must end with the same registers.
+
+const CYCLES: u32 = 2_000_000;
+
+struct CountingAllocator;
+
+static ALLOCATIONS: AtomicU64 = AtomicU64::new(0);
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        // SAFETY: forwards the caller's layout, which meets `GlobalAlloc::alloc`'s contract
+        unsafe { System.alloc(layout) }
+    }
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        // SAFETY: `ptr` came from `alloc` above with `layout`, so from `System`
+        unsafe { System.dealloc(ptr, layout) }
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAllocator = CountingAllocator;
+
+fn alu_config(alu_addr: usize) -> AluOperation {
+    // registers 0..8 hold constants, 8..40 hold one output per ALU
+    let activation_input = 0;
+    let data_output_0 = 8 + alu_addr;
+    let data_input_0 = 8 + (alu_addr + 1) % ALU_COUNT;
+    let data_input_1 = 1 + alu_addr % 4;
+
+    match alu_addr % 8 {
+        0 => AluOperation::Add {
+            activation_input, data_input_0, data_input_1, data_output_0,
+            flags_output: Some(5), activation_output: None,
+        },
+        1 => AluOperation::Sub {
+            activation_input, data_input_0, data_input_1, data_output_0,
+            flags_output: None, activation_output: Some(6),
+        },
+        2 => AluOperation::Xor {
+            activation_input, data_input_0, data_input_1, data_output_0,
+            activation_output: None,
+        },
+        3 => AluOperation::Eq {
+            activation_input, data_input_0, data_input_1, data_output: data_output_0,
+            activation_output: None,
+        },
+        4 => AluOperation::ReadFromMem {
+            activation_input, data_input_0: 2, data_output_0,
+            activation_output: None,
+        },
+        5 => AluOperation::WriteToMem {
+            activation_input, address_input: 3, data_input: data_input_0,
+            activation_output: None,
+        },
+        6 => AluOperation::Mul {
+            activation_input, data_input_0, data_input_1,
+            first_word_output: data_output_0, second_word_output: None,
+            activation_output: None,
+        },
+        _ => AluOperation::Neg {
+            activation_input, input: data_input_0, data_output_0,
+            activation_output: None,
+        },
+    }
+}
+
+fn dense_program() -> Vec<Instruction> {
+    let mut program = vec![
+        Instruction::SetLiteral { literal: !0, register: 0 },
+        Instruction::SetLiteral { literal: 3,  register: 1 },
+        Instruction::SetLiteral { literal: 5,  register: 2 },
+        Instruction::SetLiteral { literal: 7,  register: 3 },
+        Instruction::SetLiteral { literal: 11, register: 4 },
+    ];
+    program.extend((0..ALU_COUNT).map(|alu_addr| Instruction::SetAluConfig {
+        alu_addr,
+        alu_config: alu_config(alu_addr),
+    }));
+    // register 7 is never written, so the controller stalls while the fabric keeps running
+    program.push(Instruction::WaitForActivationSignal { register_index: 7 });
+    program
+}
+
+fn sparse_program() -> Vec<Instruction> {
+    let mut program = vec![
+        Instruction::SetLiteral { literal: !0, register: 0 },
+        Instruction::SetLiteral { literal: 1,  register: 1 },
+        Instruction::SetLiteral { literal: 5,  register: 2 },
+        Instruction::SetAluConfig {
+            alu_addr    : 0,
+            alu_config  : AluOperation::Add {
+                activation_input: 0, data_input_0: 8, data_input_1: 1, data_output_0: 8,
+                flags_output: None, activation_output: None,
+            },
+        },
+    ];
+    program.extend((1..4).map(|alu_addr| Instruction::SetAluConfig {
+        alu_addr,
+        alu_config: AluOperation::Mul {
+            activation_input: 0, data_input_0: 2, data_input_1: alu_addr,
+            first_word_output: 8 + alu_addr, second_word_output: None,
+            activation_output: None,
+        },
+    }));
+    program.push(Instruction::WaitForActivationSignal { register_index: 7 });
+    program
+}
+
+/// The step before the port tables: every cycle, each ALU collects a map of read requests and a
+/// map of write requests from its ports. Main memory is the atomic one either way, so this only
+/// measures what the port tables saved. Runs every ALU every cycle, like `SchedulingMode::FullScan`.
+fn execute_with_request_maps(cpu: &mut Cpu) -> Result<bool, SimulationError> {
+    if let Some(mut controller_read_req) = cpu.controller.cpu_registers_reader.get_read_request() {
+        controller_read_req.satisfy(&cpu.register_bank);
+    }
+    if let Some(mut controller_pc_read_req) =
+        cpu.controller.instruction_reader.program_counter_reader.get_read_request()
+    {
+        controller_pc_read_req.satisfy(&cpu.register_bank);
+    }
+    cpu.controller.alu_config_writer.configure_alus(&mut cpu.alu_bank);
+
+    for alu in cpu.alu_bank.components.iter_mut() {
+        let mut reqs = HashMap::new();
+        if let Some(req) = alu.data_input_0.get_read_request() {
+            reqs.insert(AluPortName::DataIn0, req);
+        }
+        if let Some(req) = alu.data_input_1.get_read_request() {
+            reqs.insert(AluPortName::DataIn1, req);
+        }
+        if let Some(req) = alu.activation_input.get_read_request() {
+            reqs.insert(AluPortName::ActivationIn, req);
+        }
+        for req in reqs.values_mut() {
+            req.satisfy(&cpu.register_bank);
+        }
+    }
+
+    if !cpu.controller.execute(cpu.cycle)? {
+        return Ok(false);
+    }
+
+    for alu in cpu.alu_bank.components.iter_mut() {
+        alu.execute(cpu.cycle)?;
+    }
+
+    for alu in cpu.alu_bank.components.iter() {
+        let mut reqs = HashMap::new();
+        if let Some(req) = alu.data_output_0.get_write_request() {
+            reqs.insert(AluPortName::DataOut0, req);
+        }
+        if let Some(req) = alu.data_output_1.get_write_request() {
+            reqs.insert(AluPortName::DataOut1, req);
+        }
+        if let Some(req) = alu.activation_output.get_write_request() {
+            reqs.insert(AluPortName::ActivationOut, req);
+        }
+        for req in reqs.values() {
+            req.satisfy(&mut cpu.register_bank);
+        }
+    }
+
+    if let Some(write_req) = cpu.controller.cpu_registers_writer.get_write_request() {
+        write_req.satisfy(&mut cpu.register_bank);
+    }
+    if let Some(write_pc_req) = cpu.controller.instruction_reader.program_counter_writer.get_write_request() {
+        write_pc_req.satisfy(&mut cpu.register_bank);
+    }
+
+    cpu.cycle += 1;
+    Ok(true)
+}
+
+struct Measurement {
+    cycles              : u32,
+    cycles_per_second   : f64,
+    allocations         : u64,
+    register_checksum   : u64,
+}
+
+fn measure(name: &str, cpu: &mut Cpu, mut step: impl FnMut(&mut Cpu) -> Result<bool, SimulationError>) -> Measurement {
+    let allocations_before = ALLOCATIONS.load(Ordering::Relaxed);
+    let start = Instant::now();
+    let mut cycles = 0;
+    while cycles < CYCLES && step(cpu).unwrap() {
+        cycles += 1;
+    }
+    let elapsed = start.elapsed();
+    let allocations = ALLOCATIONS.load(Ordering::Relaxed) - allocations_before;
+
+    let register_checksum = cpu.register_bank.components
+        .iter()
+        .fold(0_u64, |sum, register| sum.wrapping_mul(31).wrapping_add(register.value as u32 as u64));
+
+    let measurement = Measurement {
+        cycles,
+        cycles_per_second: cycles as f64 / elapsed.as_secs_f64(),
+        allocations,
+        register_checksum,
+    };
+    println!(
+        "{:<13} {} cycles in {:.3}s: {:.0} cycles/s, {} allocations, register checksum {:016x}",
+        name,
+        measurement.cycles,
+        elapsed.as_secs_f64(),
+        measurement.cycles_per_second,
+        measurement.allocations,
+        measurement.register_checksum,
+    );
+    measurement
+}
+
+fn main() {
+    let args = std::env::args().collect::<Vec<_>>();
+    let program = match args.get(1).map(String::as_str) {
+        None | Some("dense")    => dense_program(),
+        Some("sparse")          => sparse_program(),
+        Some(other)             => panic!("unknown program {other}"),
+    };
+    let scheduling_mode = match args.get(2).map(String::as_str) {
+        None | Some("full-scan")    => SchedulingMode::FullScan,
+        Some("event-driven")        => SchedulingMode::EventDriven,
+        Some(other)                 => panic!("unknown scheduling mode {other}"),
+    };
+    let executor = match args.get(3).map(String::as_str) {
+        None | Some("sequential")   => AluExecutor::Sequential,
+        Some(other)                 => match other.strip_prefix("parallel=") {
+            Some(threads)   => AluExecutor::Parallel { threads: threads.parse().unwrap() },
+            None            => panic!("unknown executor {other}"),
+        },
+    };
+
+    let mut before = Cpu::new(program.clone(), vec![0; 64]);
+    let before = measure("request maps", &mut before, execute_with_request_maps);
+
+    let mut after = Cpu::new(program.clone(), vec![0; 64]);
+    after.scheduling_mode = scheduling_mode;
+    after.executor = executor;
+    let after = measure("port tables", &mut after, Cpu::execute);
+
+    assert_eq!(
+        (before.cycles, before.register_checksum),
+        (after.cycles, after.register_checksum),
+        "both steps must compute the same registers",
+    );
+    println!("speedup {:.1}x", after.cycles_per_second / before.cycles_per_second);
+
+    if executor != AluExecutor::Sequential {
+        let mut sequential = Cpu::new(program, vec![0; 64]);
+        sequential.scheduling_mode = scheduling_mode;
+        let sequential = measure("sequential", &mut sequential, Cpu::execute);
+
+        assert_eq!(
+            (sequential.cycles, sequential.register_checksum),
+            (after.cycles, after.register_checksum),
+            "both executors must compute the same registers",
+        );
+        println!("parallel speedup {:.1}x", after.cycles_per_second / sequential.cycles_per_second);
+    }
+}
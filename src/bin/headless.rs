@@ -0,0 +1,58 @@
+use strucc::application::simulation::alu::AluOperation;
+use strucc::application::simulation::instruction::Instruction;
+use strucc::application::simulation::program_image::ProgramImage;
+use strucc::application::simulation::simulation::Cpu;
+
+// runs a STruCC program without the visualization and prints the performance report
+//
+// usage: headless [program image], running a small counter program if no image is given
+
+const MAX_CYCLES: u32 = 1_000_000;
+
+fn counter_program() -> Vec<Instruction> {
+    vec![
+        Instruction::SetLiteral { literal: 0,   register: 0 },
+        Instruction::SetLiteral { literal: 1,   register: 1 },
+        Instruction::SetLiteral { literal: !0,  register: 2 },
+        Instruction::SetLiteral { literal: 100, register: 4 },
+        Instruction::SetAluConfig {
+            alu_addr    : 0,
+            alu_config  : AluOperation::Add {
+                activation_input    : 2,
+                data_input_0        : 0,
+                data_input_1        : 1,
+                data_output_0       : 0,
+                flags_output        : None,
+                activation_output   : None,
+            },
+        },
+        Instruction::SetAluConfig {
+            alu_addr    : 1,
+            alu_config  : AluOperation::Eq {
+                activation_input    : 2,
+                data_input_0        : 0,
+                data_input_1        : 4,
+                data_output         : 5,
+                activation_output   : None,
+            },
+        },
+        Instruction::WaitForActivationSignal { register_index: 5 },
+        Instruction::ResetAll,
+    ]
+}
+
+fn main() {
+    let mut cpu = match std::env::args().nth(1) {
+        Some(path)  => ProgramImage::load(&path)
+            .and_then(ProgramImage::into_cpu)
+            .unwrap_or_else(|error| panic!("couldn't load program image {path}: {error}")),
+        None        => Cpu::new(counter_program(), vec![0; 16]),
+    };
+    match cpu.run(MAX_CYCLES) {
+        Ok(MAX_CYCLES)  => println!("stopped after reaching the cycle limit of {MAX_CYCLES}"),
+        Ok(_)           => {}
+        Err(error)      => println!("stopped by {error}"),
+    }
+
+    print!("{}", cpu.performance_report());
+}
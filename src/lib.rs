@@ -10,7 +10,6 @@
 #![feature(
 	const_type_name,
 	slice_as_array,
-	bigint_helper_methods,
 	strict_overflow_ops,
 	mixed_integer_ops_unsigned_sub,
 	unique_rc_arc,
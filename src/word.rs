@@ -1,10 +1,62 @@
-pub type Word = i32;
+/// Storage for a word of every supported width, see `WordWidth`
+pub type Word = i64;
+/// `Word` read as unsigned
+pub type UWord = u64;
+pub type AtomicWord = std::sync::atomic::AtomicI64;
+
+/// The width of the words the simulated machine computes with. Values are stored as `Word`s
+/// sign-extended from the width, and everything an ALU writes is wrapped to it.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default, Hash)]
+pub enum WordWidth {
+    W8,
+    W16,
+    #[default]
+    W32,
+    W64,
+}
+
+impl WordWidth {
+    pub const ALL: [WordWidth; 4] = [WordWidth::W8, WordWidth::W16, WordWidth::W32, WordWidth::W64];
+
+    pub const fn bits(self) -> u32 {
+        match self {
+            WordWidth::W8   => 8,
+            WordWidth::W16  => 16,
+            WordWidth::W32  => 32,
+            WordWidth::W64  => 64,
+        }
+    }
+
+    pub fn from_bits(bits: u32) -> Option<WordWidth> {
+        Self::ALL.into_iter().find(|width| width.bits() == bits)
+    }
+
+    pub const fn min(self) -> Word {
+        Word::MIN >> (Word::BITS - self.bits())
+    }
+
+    pub const fn max(self) -> Word {
+        Word::MAX >> (Word::BITS - self.bits())
+    }
+
+    /// The low `bits()` of `value`, sign-extended
+    pub const fn wrap(self, value: Word) -> Word {
+        let unused = Word::BITS - self.bits();
+        (value << unused) >> unused
+    }
+
+    /// The low `bits()` of `value`, zero-extended
+    pub const fn unsigned(self, value: Word) -> UWord {
+        let unused = Word::BITS - self.bits();
+        ((value as UWord) << unused) >> unused
+    }
+}
 
 pub trait ToWord {
-    fn to_word(&self) -> i32;
+    fn to_word(&self) -> Word;
 }
 impl ToWord for bool{
-    fn to_word(&self) -> i32{
+    fn to_word(&self) -> Word{
         match self{
             &true  => {!0}
             &false => {0}